@@ -0,0 +1,177 @@
+use crate::positive_env;
+use axum::body::Body;
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentLimits {
+    pub max_file_bytes: u64,
+    // Total size of all attachments of one task.
+    pub max_task_bytes: u64,
+}
+
+impl Default for AttachmentLimits {
+    fn default() -> Self {
+        AttachmentLimits {
+            max_file_bytes: 10 << 20,
+            max_task_bytes: 50 << 20,
+        }
+    }
+}
+
+impl AttachmentLimits {
+    // Reads `ATTACHMENT_MAX_FILE_BYTES` and `ATTACHMENT_MAX_TASK_BYTES`; unset ones keep the default.
+    pub fn from_env() -> Result<Self, String> {
+        let mut limits = AttachmentLimits::default();
+        if let Some(bytes) = positive_env("ATTACHMENT_MAX_FILE_BYTES")? {
+            limits.max_file_bytes = bytes;
+        }
+        if let Some(bytes) = positive_env("ATTACHMENT_MAX_TASK_BYTES")? {
+            limits.max_task_bytes = bytes;
+        }
+        Ok(limits)
+    }
+}
+
+// An uploaded file spooled to a temporary file, with what we learned while reading it.
+pub struct Upload {
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+    pub content_type: &'static str,
+}
+
+pub enum UploadError {
+    TooLarge,
+    Io(io::Error),
+}
+
+static UPLOAD_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Streams the request body into a temporary file in `dir`, hashing it on the way, so that
+// large files never sit in memory. Stops as soon as the body grows over `max_bytes`.
+pub async fn receive(body: Body, dir: &Path, max_bytes: u64) -> Result<Upload, UploadError> {
+    let path = dir.join(format!(
+        "upload-{}-{}",
+        std::process::id(),
+        UPLOAD_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = spool(body, &path, max_bytes).await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+    result
+}
+
+async fn spool(body: Body, path: &Path, max_bytes: u64) -> Result<Upload, UploadError> {
+    let mut file = tokio::fs::File::create(path)
+        .await
+        .map_err(UploadError::Io)?;
+    let mut hasher = Sha256::new();
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    let mut size = 0;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| UploadError::Io(io::Error::other(e)))?;
+        size += chunk.len() as u64;
+        if size > max_bytes {
+            return Err(UploadError::TooLarge);
+        }
+        if head.len() < SNIFF_LEN {
+            let take = chunk.len().min(SNIFF_LEN - head.len());
+            head.extend_from_slice(&chunk[..take]);
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await.map_err(UploadError::Io)?;
+    }
+    file.flush().await.map_err(UploadError::Io)?;
+    Ok(Upload {
+        path: path.to_path_buf(),
+        size,
+        sha256: hex::encode(hasher.finalize()),
+        content_type: sniff(&head),
+    })
+}
+
+const SNIFF_LEN: usize = 512;
+
+// Detects the type from the first bytes of the file; the type claimed by the client is never
+// trusted. Only types that browsers won't run as a page are produced, anything else that isn't
+// plain text is served as an opaque binary.
+pub fn sniff(head: &[u8]) -> &'static str {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+    ];
+    for (signature, content_type) in SIGNATURES {
+        if head.starts_with(signature) {
+            return content_type;
+        }
+    }
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    if is_text(head) {
+        return "text/plain; charset=utf-8";
+    }
+    "application/octet-stream"
+}
+
+fn is_text(head: &[u8]) -> bool {
+    let valid = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        // The sample may end in the middle of a character.
+        Err(e) => e.error_len().is_none(),
+    };
+    valid
+        && !head
+            .iter()
+            .any(|b| b.is_ascii_control() && !b"\t\n\r\x0c\x1b".contains(b))
+}
+
+// Keeps the last path component without control characters, at most 255 characters long.
+pub fn clean_filename(filename: &str) -> Option<String> {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name.chars().filter(|c| !c.is_control()).take(255).collect();
+    let name = name.trim();
+    match name {
+        "" | "." | ".." => None,
+        name => Some(name.to_string()),
+    }
+}
+
+// `Content-Disposition` with an ASCII fallback name and the exact one in RFC 5987 encoding.
+pub fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() => c,
+            _ => '_',
+        })
+        .collect();
+    let mut encoded = String::new();
+    for byte in filename.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
@@ -0,0 +1,39 @@
+use similar::{ChangeTag, TextDiff};
+
+// Line-based diff in the usual unified format.
+pub fn unified(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(old_label, new_label)
+        .to_string()
+}
+
+// Inline diff that marks removed words as `[-old-]` and added ones as `{+new+}`,
+// like `git diff --word-diff`.
+pub fn words(old: &str, new: &str) -> String {
+    let diff = TextDiff::from_words(old, new);
+    let mut result = String::new();
+    let mut current: Option<ChangeTag> = None;
+    for change in diff.iter_all_changes() {
+        if current != Some(change.tag()) {
+            close(&mut result, current);
+            match change.tag() {
+                ChangeTag::Delete => result.push_str("[-"),
+                ChangeTag::Insert => result.push_str("{+"),
+                ChangeTag::Equal => {}
+            }
+            current = Some(change.tag());
+        }
+        result.push_str(change.value());
+    }
+    close(&mut result, current);
+    result
+}
+
+fn close(result: &mut String, tag: Option<ChangeTag>) {
+    match tag {
+        Some(ChangeTag::Delete) => result.push_str("-]"),
+        Some(ChangeTag::Insert) => result.push_str("+}"),
+        _ => {}
+    }
+}
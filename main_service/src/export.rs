@@ -0,0 +1,155 @@
+use crate::workflow::Workflow;
+use crate::GetTaskResponse1;
+
+// Columns of the CSV export; the importable ones match the field names of the import mapping,
+// so an export can be imported back without a mapping.
+pub const CSV_COLUMNS: [&str; 12] = [
+    "external_id",
+    "key",
+    "title",
+    "description",
+    "status",
+    "priority",
+    "due_date",
+    "estimate_minutes",
+    "assignee_id",
+    "labels",
+    "created_at",
+    "updated_at",
+];
+
+pub fn csv(tasks: &[GetTaskResponse1]) -> Result<String, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(CSV_COLUMNS)?;
+    for task in tasks {
+        let labels: Vec<&str> = task.labels.iter().map(|l| l.name.as_str()).collect();
+        writer.write_record([
+            task.external_id.clone().unwrap_or_default(),
+            task.key.clone().unwrap_or_default(),
+            task.title.clone(),
+            task.description.clone(),
+            task.status.clone(),
+            task.priority.clone(),
+            task.due_date.clone().unwrap_or_default(),
+            task.estimate_minutes
+                .map(|m| m.to_string())
+                .unwrap_or_default(),
+            task.assignee_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            labels.join(";"),
+            task.created_at.clone(),
+            task.updated_at.clone(),
+        ])?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+// A GitHub-style checklist with done tasks checked.
+pub fn markdown(tasks: &[GetTaskResponse1], workflow: &Workflow) -> String {
+    let mut result = String::new();
+    for task in tasks {
+        let mark = if workflow.is_done(&task.status) {
+            "x"
+        } else {
+            " "
+        };
+        let reference = match &task.key {
+            Some(key) => key.clone(),
+            None => format!("#{}", task.task_id),
+        };
+        let title = task.title.split_whitespace().collect::<Vec<_>>().join(" ");
+        result.push_str(&format!("- [{}] {} {}", mark, reference, title));
+        if let Some(due_date) = &task.due_date {
+            result.push_str(&format!(" (due {})", &due_date[..due_date.len().min(10)]));
+        }
+        result.push('\n');
+    }
+    result
+}
+
+// An iCalendar (RFC 5545) calendar with a VTODO per task.
+pub fn ical(tasks: &[GetTaskResponse1], workflow: &Workflow) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Task Tracker//Export//EN".to_string(),
+    ];
+    for task in tasks {
+        lines.push("BEGIN:VTODO".to_string());
+        lines.push(format!("UID:task-{}@task-tracker", task.task_id));
+        if let Some(updated_at) = ical_time(&task.updated_at) {
+            lines.push(format!("DTSTAMP:{}", updated_at));
+            lines.push(format!("LAST-MODIFIED:{}", updated_at));
+        }
+        if let Some(created_at) = ical_time(&task.created_at) {
+            lines.push(format!("CREATED:{}", created_at));
+        }
+        lines.push(format!("SUMMARY:{}", ical_text(&task.title)));
+        if !task.description.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", ical_text(&task.description)));
+        }
+        if let Some(due) = task.due_date.as_deref().and_then(ical_time) {
+            lines.push(format!("DUE:{}", due));
+        }
+        // 1 is the highest priority and 9 the lowest.
+        let priority = match task.priority.as_str() {
+            "urgent" => 1,
+            "high" => 3,
+            "low" => 9,
+            _ => 5,
+        };
+        lines.push(format!("PRIORITY:{}", priority));
+        let status = if workflow.is_done(&task.status) {
+            "COMPLETED"
+        } else if task.status == workflow.initial_status {
+            "NEEDS-ACTION"
+        } else {
+            "IN-PROCESS"
+        };
+        lines.push(format!("STATUS:{}", status));
+        if !task.labels.is_empty() {
+            let labels: Vec<String> = task.labels.iter().map(|l| ical_text(&l.name)).collect();
+            lines.push(format!("CATEGORIES:{}", labels.join(",")));
+        }
+        lines.push("END:VTODO".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+fn ical_time(value: &str) -> Option<String> {
+    let time = chrono::DateTime::parse_from_rfc3339(value).ok()?;
+    Some(
+        time.with_timezone(&chrono::Utc)
+            .format("%Y%m%dT%H%M%SZ")
+            .to_string(),
+    )
+}
+
+fn ical_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// Lines longer than 75 octets continue on the next line after a space, without splitting
+// UTF-8 sequences.
+fn fold(line: &str) -> String {
+    let mut result = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            result.push_str("\r\n ");
+            width = 1;
+        }
+        result.push(c);
+        width += c.len_utf8();
+    }
+    result.push_str("\r\n");
+    result
+}
@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+
+// What a grant allows on a task; each permission includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    View,
+    Comment,
+    Edit,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 3] = [Permission::View, Permission::Comment, Permission::Edit];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::View => "view",
+            Permission::Comment => "comment",
+            Permission::Edit => "edit",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Permission> {
+        Permission::ALL
+            .into_iter()
+            .find(|permission| permission.as_str() == value)
+    }
+}
+
+// Who a task is shared with: either `user_id` or `team_id` is set.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Grant {
+    pub grant_id: i64,
+    pub task_id: i64,
+    pub user_id: Option<i64>,
+    pub team_id: Option<i64>,
+    pub permission: Permission,
+    pub granted_by: i64,
+    pub created_at: String,
+}
+
+const GRANT_COLUMNS: &str = "id, task_id, user_id, team_id, permission, granted_by, \
+     to_json(created_at) #>> '{}' AS created_at";
+
+fn grant(row: &sqlx::postgres::PgRow) -> Result<Grant, sqlx::Error> {
+    let permission: String = row.try_get("permission")?;
+    Ok(Grant {
+        grant_id: row.try_get("id")?,
+        task_id: row.try_get("task_id")?,
+        user_id: row.try_get("user_id")?,
+        team_id: row.try_get("team_id")?,
+        permission: Permission::parse(&permission).ok_or_else(|| {
+            sqlx::Error::Decode(format!("Unknown permission {}", permission).into())
+        })?,
+        granted_by: row.try_get("granted_by")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+// Shares the task with the user or the team; sharing it again replaces the permission.
+pub async fn grant_access(
+    pool: &Pool<Postgres>,
+    task_id: i64,
+    user_id: Option<i64>,
+    team_id: Option<i64>,
+    permission: Permission,
+    granted_by: i64,
+) -> Result<Grant, sqlx::Error> {
+    let conflict = if user_id.is_some() {
+        "(task_id, user_id) WHERE user_id IS NOT NULL"
+    } else {
+        "(task_id, team_id) WHERE team_id IS NOT NULL"
+    };
+    let row = sqlx::query(&format!(
+        "INSERT INTO task_grants (task_id, user_id, team_id, permission, granted_by) \
+         VALUES ($1, $2, $3, $4, $5) ON CONFLICT {} DO UPDATE \
+         SET permission = EXCLUDED.permission, granted_by = EXCLUDED.granted_by RETURNING {}",
+        conflict, GRANT_COLUMNS
+    ))
+    .bind(task_id)
+    .bind(user_id)
+    .bind(team_id)
+    .bind(permission.as_str())
+    .bind(granted_by)
+    .fetch_one(pool)
+    .await?;
+    grant(&row)
+}
+
+pub async fn list(pool: &Pool<Postgres>, task_id: i64) -> Result<Vec<Grant>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM task_grants WHERE task_id = $1 ORDER BY id",
+        GRANT_COLUMNS
+    ))
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(grant).collect()
+}
+
+// Removes a grant of the task; false if the task has no such grant.
+pub async fn revoke(
+    pool: &Pool<Postgres>,
+    task_id: i64,
+    grant_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM task_grants WHERE id = $1 AND task_id = $2")
+        .bind(grant_id)
+        .bind(task_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// The strongest permission the user holds on the task, themselves or through a team.
+pub async fn permission(
+    pool: &Pool<Postgres>,
+    task_id: i64,
+    user_id: i64,
+) -> Result<Option<Permission>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT permission FROM task_grants WHERE task_id = $1 AND (user_id = $2 \
+         OR team_id IN (SELECT team_id FROM team_members WHERE user_id = $2))",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    let mut strongest = None;
+    for row in rows {
+        let permission: String = row.try_get("permission")?;
+        strongest = strongest.max(Permission::parse(&permission));
+    }
+    Ok(strongest)
+}
+
+// The tasks shared with the user in any way.
+pub async fn shared_task_ids(pool: &Pool<Postgres>, user_id: i64) -> Result<Vec<i64>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT DISTINCT task_id FROM task_grants WHERE user_id = $1 \
+         OR team_id IN (SELECT team_id FROM team_members WHERE user_id = $1)",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(|row| row.try_get("task_id")).collect()
+}
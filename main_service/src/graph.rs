@@ -0,0 +1,61 @@
+use std::collections::{HashMap, VecDeque};
+
+// The chain of dependent tasks with the largest total estimate: nothing in the graph can be
+// finished sooner than the work on this chain.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CriticalPath {
+    pub task_ids: Vec<i64>,
+    pub total_minutes: i64,
+}
+
+// `weights` holds every task with its remaining estimate, `edges` go from blocker to blocked.
+// Edges to unknown tasks are ignored. The graph is acyclic because cycles are rejected on insert.
+pub fn critical_path(weights: &[(i64, i64)], edges: &[(i64, i64)]) -> CriticalPath {
+    let index: HashMap<i64, usize> = weights
+        .iter()
+        .enumerate()
+        .map(|(i, (task_id, _))| (*task_id, i))
+        .collect();
+    let mut successors = vec![vec![]; weights.len()];
+    let mut indegree = vec![0; weights.len()];
+    for (blocker, blocked) in edges {
+        if let (Some(&from), Some(&to)) = (index.get(blocker), index.get(blocked)) {
+            successors[from].push(to);
+            indegree[to] += 1;
+        }
+    }
+
+    // Longest path ending in every task, as (minutes, number of tasks); ties go to longer chains.
+    let mut finish: Vec<(i64, usize)> = weights.iter().map(|(_, w)| (*w, 1)).collect();
+    let mut previous: Vec<Option<usize>> = vec![None; weights.len()];
+    let mut queue: VecDeque<usize> = (0..weights.len()).filter(|i| indegree[*i] == 0).collect();
+    while let Some(node) = queue.pop_front() {
+        for &next in &successors[node] {
+            let candidate = (finish[node].0 + weights[next].1, finish[node].1 + 1);
+            if candidate > finish[next] {
+                finish[next] = candidate;
+                previous[next] = Some(node);
+            }
+            indegree[next] -= 1;
+            if indegree[next] == 0 {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let mut end = match (0..weights.len()).max_by_key(|i| (finish[*i], std::cmp::Reverse(*i))) {
+        Some(end) => end,
+        None => return CriticalPath::default(),
+    };
+    let total_minutes = finish[end].0;
+    let mut task_ids = vec![weights[end].0];
+    while let Some(node) = previous[end] {
+        task_ids.push(weights[node].0);
+        end = node;
+    }
+    task_ids.reverse();
+    CriticalPath {
+        task_ids,
+        total_minutes,
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+// Task fields that can be imported. Rows with an `external_id` update the task imported
+// earlier with the same id instead of creating another one.
+pub const FIELDS: [&str; 7] = [
+    "external_id",
+    "title",
+    "description",
+    "status",
+    "priority",
+    "due_date",
+    "estimate_minutes",
+];
+
+// One row of the input: the non-empty values of the mapped fields.
+#[derive(Debug, Default)]
+pub struct Record {
+    // 1-based line of the CSV file (the header is line 1) or index in the JSON array.
+    pub row: usize,
+    pub values: HashMap<&'static str, String>,
+    pub errors: Vec<String>,
+}
+
+// `mapping` goes from task field to the column (CSV) or key (JSON) holding it; unmapped fields
+// are read from the column of the same name, if there is one.
+pub fn check_mapping(mapping: &HashMap<String, String>) -> Result<(), String> {
+    for field in mapping.keys() {
+        if !FIELDS.contains(&field.as_str()) {
+            return Err(format!(
+                "Unknown field {} in the mapping, fields are: {}",
+                field,
+                FIELDS.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn source<'a>(mapping: &'a HashMap<String, String>, field: &'a str) -> &'a str {
+    mapping.get(field).map(|s| s.as_str()).unwrap_or(field)
+}
+
+pub fn csv_records(data: &str, mapping: &HashMap<String, String>) -> Result<Vec<Record>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(data.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let mut columns = vec![];
+    for field in FIELDS {
+        let column = source(mapping, field);
+        match headers.iter().position(|header| header.trim() == column) {
+            Some(index) => columns.push((field, index)),
+            None if mapping.contains_key(field) => {
+                return Err(format!("Column {} is missing", column));
+            }
+            None => {}
+        }
+    }
+
+    let mut records = vec![];
+    for row in reader.records() {
+        let row = row.map_err(|e| e.to_string())?;
+        let mut record = Record {
+            row: row.position().map_or(0, |p| p.line() as usize),
+            ..Default::default()
+        };
+        for (field, index) in &columns {
+            if let Some(value) = row.get(*index).filter(|v| !v.trim().is_empty()) {
+                record.values.insert(field, value.to_string());
+            }
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+pub fn json_records(
+    data: &serde_json::Value,
+    mapping: &HashMap<String, String>,
+) -> Result<Vec<Record>, String> {
+    let rows = data
+        .as_array()
+        .ok_or("JSON data must be an array of objects")?;
+    let mut records = vec![];
+    for (index, row) in rows.iter().enumerate() {
+        let mut record = Record {
+            row: index,
+            ..Default::default()
+        };
+        let object = match row.as_object() {
+            Some(object) => object,
+            None => {
+                record.errors.push("Row must be an object".to_string());
+                records.push(record);
+                continue;
+            }
+        };
+        for field in FIELDS {
+            let key = source(mapping, field);
+            match object.get(key) {
+                None | Some(serde_json::Value::Null) => {}
+                Some(serde_json::Value::String(s)) if s.trim().is_empty() => {}
+                Some(serde_json::Value::String(s)) => {
+                    record.values.insert(field, s.clone());
+                }
+                Some(value @ serde_json::Value::Number(_)) => {
+                    record.values.insert(field, value.to_string());
+                }
+                Some(_) => record
+                    .errors
+                    .push(format!("{} must be a string or a number", key)),
+            }
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
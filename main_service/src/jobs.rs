@@ -0,0 +1,182 @@
+use crate::notifications;
+use crate::notifier::Notifier;
+use crate::positive_env;
+use crate::proto::task_service_client::TaskServiceClient;
+use crate::reminders;
+use sqlx::{Pool, Postgres, Row};
+use std::{sync::Arc, time::Duration};
+use tonic::transport::Channel;
+
+// Key of the advisory lock held by the scheduler that is running the jobs, so that only one
+// of several main_service replicas works through the queue at a time.
+const JOBS_LOCK: i64 = 0x6a6f6273;
+const BATCH_SIZE: i64 = 100;
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobConfig {
+    pub poll_interval: Duration,
+    pub max_attempts: i32,
+    // Delay before the first retry; it doubles with every failed attempt.
+    pub retry_delay: Duration,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        JobConfig {
+            poll_interval: Duration::from_secs(5),
+            max_attempts: 8,
+            retry_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl JobConfig {
+    // Reads `JOBS_POLL_INTERVAL_SECONDS`, `JOBS_MAX_ATTEMPTS` and `JOBS_RETRY_DELAY_SECONDS`;
+    // unset ones keep the default.
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = JobConfig::default();
+        if let Some(seconds) = positive_env("JOBS_POLL_INTERVAL_SECONDS")? {
+            config.poll_interval = Duration::from_secs(seconds);
+        }
+        if let Some(attempts) = positive_env("JOBS_MAX_ATTEMPTS")? {
+            config.max_attempts = attempts.min(i32::MAX as u64) as i32;
+        }
+        if let Some(seconds) = positive_env("JOBS_RETRY_DELAY_SECONDS")? {
+            config.retry_delay = Duration::from_secs(seconds);
+        }
+        Ok(config)
+    }
+
+    fn backoff(&self, attempts: i32) -> Duration {
+        let factor = 1u32 << attempts.clamp(1, 16).saturating_sub(1);
+        self.retry_delay.saturating_mul(factor).min(MAX_BACKOFF)
+    }
+}
+
+// What the jobs need from the rest of the service.
+#[derive(Clone)]
+pub struct JobContext {
+    pub pool: Pool<Postgres>,
+    pub notifier: Arc<dyn Notifier>,
+    pub tasks_client: TaskServiceClient<Channel>,
+}
+
+pub enum JobOutcome {
+    Done,
+    // The job isn't due yet after all and runs again at this RFC 3339 time, without using up
+    // an attempt.
+    RunAt(String),
+}
+
+// Adds a job that runs at `run_at` (an RFC 3339 time), or right away, and returns its id.
+pub async fn enqueue<'e, E>(
+    executor: E,
+    kind: &str,
+    payload: &serde_json::Value,
+    run_at: Option<&str>,
+) -> Result<i64, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = Postgres>,
+{
+    let row = sqlx::query(
+        "INSERT INTO jobs (kind, payload, run_at) \
+         VALUES ($1, $2::jsonb, coalesce($3::timestamptz, now())) RETURNING id",
+    )
+    .bind(kind)
+    .bind(payload.to_string())
+    .bind(run_at)
+    .fetch_one(executor)
+    .await?;
+    row.try_get("id")
+}
+
+async fn run_job(
+    context: &mut JobContext,
+    kind: &str,
+    payload: &serde_json::Value,
+) -> Result<JobOutcome, String> {
+    match kind {
+        reminders::JOB_KIND => reminders::run_job(context, payload).await,
+        notifications::JOB_KIND => notifications::run_job(context, payload).await,
+        _ => Err(format!("Unknown job kind {}", kind)),
+    }
+}
+
+// Runs the due jobs if no other replica is doing it and returns how many ran.
+pub async fn run_due_jobs(
+    context: &mut JobContext,
+    config: &JobConfig,
+) -> Result<usize, sqlx::Error> {
+    // The lock lives as long as this transaction, and so goes away with the connection if the
+    // process dies. The jobs themselves are updated outside of it, one by one.
+    let mut lock = context.pool.begin().await?;
+    let locked: bool = sqlx::query("SELECT pg_try_advisory_xact_lock($1)")
+        .bind(JOBS_LOCK)
+        .fetch_one(&mut lock)
+        .await?
+        .try_get(0)?;
+    if !locked {
+        return Ok(0);
+    }
+
+    let rows = sqlx::query(
+        "SELECT id, kind, payload::text AS payload, attempts FROM jobs \
+         WHERE status = 'pending' AND run_at <= now() ORDER BY run_at, id LIMIT $1",
+    )
+    .bind(BATCH_SIZE)
+    .fetch_all(&context.pool)
+    .await?;
+    for row in &rows {
+        let id: i64 = row.try_get("id")?;
+        let kind: String = row.try_get("kind")?;
+        let payload: String = row.try_get("payload")?;
+        let attempts: i32 = row.try_get("attempts")?;
+        let outcome = match serde_json::from_str(&payload) {
+            Ok(payload) => run_job(context, &kind, &payload).await,
+            Err(e) => Err(e.to_string()),
+        };
+        let query = match outcome {
+            Ok(JobOutcome::Done) => {
+                sqlx::query("UPDATE jobs SET status = 'done', updated_at = now() WHERE id = $1")
+                    .bind(id)
+            }
+            Ok(JobOutcome::RunAt(run_at)) => sqlx::query(
+                "UPDATE jobs SET run_at = $2::timestamptz, updated_at = now() WHERE id = $1",
+            )
+            .bind(id)
+            .bind(run_at),
+            Err(e) => {
+                let attempts = attempts + 1;
+                let status = if attempts >= config.max_attempts {
+                    println!("Job {} ({}) failed for good: {}", id, kind, e);
+                    "failed"
+                } else {
+                    "pending"
+                };
+                sqlx::query(
+                    "UPDATE jobs SET status = $2, attempts = $3, last_error = $4, \
+                     run_at = now() + make_interval(secs => $5), updated_at = now() WHERE id = $1",
+                )
+                .bind(id)
+                .bind(status)
+                .bind(attempts)
+                .bind(e)
+                .bind(config.backoff(attempts).as_secs_f64())
+            }
+        };
+        query.execute(&context.pool).await?;
+    }
+    lock.rollback().await?;
+    Ok(rows.len())
+}
+
+pub async fn run_scheduler(config: JobConfig, mut context: JobContext) {
+    let mut interval = tokio::time::interval(config.poll_interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = run_due_jobs(&mut context, &config).await {
+            println!("Failed to run the due jobs: {}", e);
+        }
+    }
+}
@@ -1,36 +1,70 @@
+use attachments::AttachmentLimits;
 use axum::{
-    extract::State,
-    http::{request, response, HeaderMap, StatusCode},
+    body::Body,
+    extract::{Path, Query, State},
+    http::header,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     routing::{delete, get, post, put},
-    Error, Json, Router,
+    Json, Router,
 };
 use chrono::Local;
 use chrono::NaiveDate;
-use hex;
+use chrono_tz::Tz;
+use grants::{Grant, Permission};
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
-use proto::{stat_service_client::StatServiceClient, HealthcheckRequest, HealthcheckResponse};
-use proto::{task_service_client::TaskServiceClient, CreateTaskResponse};
-use proto::{
-    CreateTaskRequest, DeleteTaskRequest, GetTaskRequest, ListTasksRequest, UpdateTaskRequest,
-};
+use mentions::Mention;
+use notifications::{Event, NotificationRecord, TaskEvent};
+use proto::stat_service_client::StatServiceClient;
+use proto::task_service_client::TaskServiceClient;
+use rrule::Rule;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres, Row};
-use std::{borrow::Borrow, str, sync::Arc, thread, time::Duration};
-use tonic;
-use tracing_subscriber::field::RecordFields;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use storage::BlobStorage;
+use templates::{SubtaskTemplate, Template, TemplateFields};
+use trash::TrashConfig;
+use workflow::{Role, TransitionError, Workflow};
+use worklogs::{GroupBy, Period, Scope, WorkLog};
 
 pub mod proto {
     tonic::include_proto!("common");
 }
 
+pub mod attachments;
+pub mod diff;
+pub mod export;
+pub mod grants;
+pub mod graph;
+pub mod import;
+pub mod jobs;
+pub mod markdown;
+pub mod mentions;
+pub mod notifications;
+pub mod notifier;
+pub mod rank;
+pub mod recurrence;
+pub mod reminders;
+pub mod rrule;
+pub mod search;
+pub mod storage;
+pub mod templates;
+pub mod trash;
+pub mod workflow;
+pub mod worklogs;
+
 pub async fn create_pool(database_url: &str) -> Pool<Postgres> {
     let mut attempts = 0;
     let max_attempts = 5;
 
     while attempts < max_attempts {
-        match PgPoolOptions::new().connect(&database_url).await {
+        match PgPoolOptions::new().connect(database_url).await {
             Ok(pool) => return pool,
             Err(err) => {
                 println!(
@@ -53,7 +87,18 @@ pub async fn create_pool(database_url: &str) -> Pool<Postgres> {
     std::process::exit(1);
 }
 
-fn get_hash(password: &String) -> String {
+// Reads a positive integer from the environment; `None` when the variable is unset.
+pub(crate) fn positive_env(name: &str) -> Result<Option<u64>, String> {
+    match std::env::var(name) {
+        Ok(value) => match value.parse::<u64>() {
+            Ok(n) if n > 0 => Ok(Some(n)),
+            _ => Err(format!("{} must be a positive integer", name)),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+fn get_hash(password: &str) -> String {
     let mut hasher = Sha256::new();
     hasher.update(b"dlkD7jQsiH");
     hasher.update(password.as_bytes());
@@ -63,8 +108,15 @@ fn get_hash(password: &String) -> String {
 
 struct AppState {
     pool: Pool<Postgres>,
+    workflow: Workflow,
+    trash: TrashConfig,
+    storage: Arc<dyn BlobStorage>,
+    attachment_limits: AttachmentLimits,
+    // One lazily connected channel shared by all requests.
+    tasks_client: TaskServiceClient<tonic::transport::Channel>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct UsersModel {
     id: u64,
@@ -77,24 +129,151 @@ pub struct UsersModel {
     phone_number: Option<String>,
 }
 
-pub async fn create_app(users_db_url: &str, need_to_clear: bool) -> Router {
+const TASKS_SERVICE_URL: &str = "http://tasks_service:50051";
+
+// A client over a channel that connects on first use and is shared by its clones.
+pub fn tasks_client() -> TaskServiceClient<tonic::transport::Channel> {
+    let channel = tonic::transport::Endpoint::from_static(TASKS_SERVICE_URL).connect_lazy();
+    TaskServiceClient::new(channel)
+}
+
+pub async fn create_app(
+    users_db_url: &str,
+    workflow: Workflow,
+    trash: TrashConfig,
+    storage: Arc<dyn BlobStorage>,
+    attachment_limits: AttachmentLimits,
+    need_to_clear: bool,
+) -> Router {
     let pool = create_pool(users_db_url).await;
 
     if need_to_clear {
         let _ = sqlx::query("TRUNCATE TABLE users").execute(&pool).await;
     }
 
-    let shared_state = Arc::new(AppState { pool });
+    let shared_state = Arc::new(AppState {
+        pool,
+        workflow,
+        trash,
+        storage,
+        attachment_limits,
+        tasks_client: tasks_client(),
+    });
     Router::new()
         .route("/signup", post(signup))
         .route("/login", post(login))
         .route("/personal_data", put(update_personal_data))
         .route("/personal_data", get(get_personal_data))
+        .route("/users/:user_id", get(get_user))
+        .route("/create_team", post(create_team))
+        .route("/add_team_member", post(add_team_member))
+        .route("/remove_team_member", delete(remove_team_member))
+        .route("/my_teams", get(my_teams))
+        .route("/create_project", post(create_project))
+        .route("/add_project_member", post(add_project_member))
+        .route("/remove_project_member", delete(remove_project_member))
+        .route("/archive_project", put(archive_project))
+        .route("/my_projects", get(my_projects))
+        .route("/project_tasks", get(project_tasks))
         .route("/create_task", post(create_task))
         .route("/update_task", put(update_task))
         .route("/delete_task", delete(delete_task))
+        .route("/bulk_tasks", post(bulk_tasks))
+        .route("/export_tasks", get(export_tasks))
+        .route("/import_tasks", post(import_tasks))
         .route("/get_task", get(get_task))
         .route("/list_tasks", get(list_tasks))
+        .route("/assigned_to_me", get(assigned_to_me))
+        .route("/created_by_me", get(created_by_me))
+        .route("/unassigned_tasks", get(unassigned_tasks))
+        .route("/search", get(search_tasks))
+        .route("/assign_task", put(assign_task))
+        .route("/unassign_task", put(unassign_task))
+        .route("/transition_task", post(transition_task))
+        .route("/task_transitions", get(task_transitions))
+        .route("/task_revisions", get(task_revisions))
+        .route("/revision_diff", get(revision_diff))
+        .route("/restore_revision", post(restore_revision))
+        .route("/trash", get(list_trash))
+        .route("/restore_task", post(restore_task))
+        .route("/series", post(create_series))
+        .route(
+            "/series/:series_id",
+            get(get_series).put(update_series).delete(stop_series),
+        )
+        .route(
+            "/tasks/:task_id/reminders",
+            post(create_reminder).get(list_reminders),
+        )
+        .route("/reminders/:reminder_id", delete(delete_reminder))
+        .route("/templates", post(create_template).get(list_templates))
+        .route(
+            "/templates/:template_id",
+            get(get_template)
+                .put(update_template)
+                .delete(delete_template),
+        )
+        .route("/tasks/:task_id/checklist/:index", put(set_checklist_item))
+        .route(
+            "/tasks/:task_id/grants",
+            get(list_grants).post(create_grant),
+        )
+        .route("/tasks/:task_id/grants/:grant_id", delete(delete_grant))
+        .route("/tasks/:task_id/timer", post(start_timer))
+        .route("/timer", get(get_timer))
+        .route("/timer/stop", post(stop_timer))
+        .route(
+            "/tasks/:task_id/work_logs",
+            post(create_work_log).get(list_work_logs),
+        )
+        .route(
+            "/work_logs/:work_log_id",
+            put(update_work_log).delete(delete_work_log),
+        )
+        .route("/time_totals", get(time_totals))
+        .route("/time_report", get(time_report))
+        .route(
+            "/tasks/:task_id/watchers",
+            get(list_watchers).post(watch_task).delete(unwatch_task),
+        )
+        .route("/notifications", get(list_notifications))
+        .route("/notifications/read_all", post(mark_all_notifications_read))
+        .route(
+            "/notifications/:notification_id/read",
+            post(mark_notification_read),
+        )
+        .route(
+            "/notifications/:notification_id/unread",
+            post(mark_notification_unread),
+        )
+        .route(
+            "/notification_preferences",
+            get(get_notification_preferences).put(update_notification_preferences),
+        )
+        .route("/tasks/:task_id/attachments", post(upload_attachment))
+        .route(
+            "/attachments/:attachment_id",
+            get(download_attachment).delete(delete_attachment),
+        )
+        .route("/subtasks", get(subtasks))
+        .route("/add_dependency", post(add_dependency))
+        .route("/remove_dependency", delete(remove_dependency))
+        .route("/task_dependencies", get(task_dependencies))
+        .route("/dependency_graph", get(dependency_graph))
+        .route("/create_comment", post(create_comment))
+        .route("/edit_comment", put(edit_comment))
+        .route("/delete_comment", delete(delete_comment))
+        .route("/list_comments", get(list_comments))
+        .route("/create_label", post(create_label))
+        .route("/update_label", put(update_label))
+        .route("/delete_label", delete(delete_label))
+        .route("/merge_labels", post(merge_labels))
+        .route("/list_labels", get(list_labels))
+        .route("/attach_label", post(attach_label))
+        .route("/detach_label", delete(detach_label))
+        .route("/boards/:project", get(get_board))
+        .route("/boards/:project/move", post(move_board_task))
+        .route("/boards/:project/wip_limits", put(set_wip_limit))
         .route("/like", post(like))
         .route("/view", post(view))
         .route("/healthcheck_stat", get(healthcheck_stat))
@@ -132,7 +311,7 @@ pub struct UpdateUserDataRequest {
     phone_number: Option<String>,
 }
 
-fn check_login(login: &String) -> bool {
+fn check_login(login: &str) -> bool {
     if login.len() < 2 || login.len() > 20 {
         return false;
     }
@@ -142,14 +321,14 @@ fn check_login(login: &String) -> bool {
     {
         return false;
     }
-    return true;
+    true
 }
 
-fn check_password(password: &String) -> bool {
+fn check_password(password: &str) -> bool {
     if password.len() < 8 || password.len() > 30 {
         return false;
     }
-    if !password.chars().all(|c| c.is_ascii()) {
+    if !password.is_ascii() {
         return false;
     }
     if !password.chars().any(|c| c.is_ascii_lowercase()) {
@@ -167,7 +346,7 @@ fn check_password(password: &String) -> bool {
     {
         return false;
     }
-    return true;
+    true
 }
 
 async fn signup(
@@ -210,11 +389,11 @@ struct TokenData {
     exp: usize,
 }
 
-fn generate_token(id: i64, username: &String) -> String {
+fn generate_token(id: i64, username: &str) -> String {
     let secret = b"my_secret_key_d47fjs&w3)wj";
     let token_data = TokenData {
         id,
-        username: username.clone(),
+        username: username.to_string(),
         exp: (Local::now() + chrono::Duration::hours(24)).timestamp() as usize,
     };
     let encoding_key = EncodingKey::from_secret(secret);
@@ -225,11 +404,11 @@ fn decode_token(
     token: &str,
 ) -> Result<jsonwebtoken::TokenData<TokenData>, jsonwebtoken::errors::Error> {
     let secret = b"my_secret_key_d47fjs&w3)wj";
-    return decode::<TokenData>(
+    decode::<TokenData>(
         token,
         &DecodingKey::from_secret(secret),
         &Validation::new(Algorithm::HS256),
-    );
+    )
 }
 
 async fn login(
@@ -282,7 +461,7 @@ async fn check_authorization(headers: HeaderMap) -> CheckAuthorizationResult {
         }
     };
 
-    return CheckAuthorizationResult::IdAndUsername((decoded_token.id, decoded_token.username));
+    CheckAuthorizationResult::IdAndUsername((decoded_token.id, decoded_token.username))
 }
 
 async fn update_personal_data(
@@ -301,44 +480,29 @@ async fn update_personal_data(
     };
 
     let mut set_vector = Vec::new();
-    match input_payload.first_name {
-        Some(first_name) => {
-            set_vector.push(format!("first_name='{}'", first_name));
-        }
-        None => {}
-    };
-    match input_payload.second_name {
-        Some(second_name) => {
-            set_vector.push(format!("second_name='{}'", second_name));
-        }
-        None => {}
-    };
-    match input_payload.birthday {
-        Some(birthday) => {
-            let date_opt = NaiveDate::from_ymd_opt(birthday.year, birthday.month, birthday.day);
-            match date_opt {
-                Some(date) => {
-                    set_vector.push(format!("birthday='{}', ", date));
-                }
-                None => {
-                    return (StatusCode::NOT_ACCEPTABLE, "Incorrect birthdate").into_response();
-                }
-            };
-        }
-        None => {}
-    };
-    match input_payload.email {
-        Some(email) => {
-            set_vector.push(format!("email='{}'", email));
-        }
-        None => {}
-    };
-    match input_payload.phone_number {
-        Some(phone_number) => {
-            set_vector.push(format!("phone_number='{}'", phone_number));
-        }
-        None => {}
-    };
+    if let Some(first_name) = input_payload.first_name {
+        set_vector.push(format!("first_name='{}'", first_name));
+    }
+    if let Some(second_name) = input_payload.second_name {
+        set_vector.push(format!("second_name='{}'", second_name));
+    }
+    if let Some(birthday) = input_payload.birthday {
+        let date_opt = NaiveDate::from_ymd_opt(birthday.year, birthday.month, birthday.day);
+        match date_opt {
+            Some(date) => {
+                set_vector.push(format!("birthday='{}', ", date));
+            }
+            None => {
+                return (StatusCode::NOT_ACCEPTABLE, "Incorrect birthdate").into_response();
+            }
+        };
+    }
+    if let Some(email) = input_payload.email {
+        set_vector.push(format!("email='{}'", email));
+    }
+    if let Some(phone_number) = input_payload.phone_number {
+        set_vector.push(format!("phone_number='{}'", phone_number));
+    }
 
     let query = format!(
         "UPDATE users SET {} WHERE username='{}' RETURNING *",
@@ -404,88 +568,134 @@ async fn get_personal_data(State(state): State<Arc<AppState>>, headers: HeaderMa
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct CreateTaskRequest1 {
-    text: String,
+pub struct CreateTeamRequest1 {
+    name: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct CreateTaskResponse1 {
-    task_id: i64,
+pub struct CreateTeamResponse1 {
+    team_id: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct UpdateTaskRequest1 {
-    task_id: i64,
-    new_text: String,
+pub struct TeamMemberRequest1 {
+    team_id: i64,
+    user_id: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-pub struct DeleteTaskRequest1 {
-    task_id: i64,
+pub struct TeamResponse1 {
+    team_id: i64,
+    name: String,
+    owner_id: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GetTaskRequest1 {
-    task_id: i64,
+async fn project_ids_of_user(state: &Arc<AppState>, user_id: i64) -> Result<Vec<i64>, sqlx::Error> {
+    let rows = sqlx::query("SELECT project_id FROM project_members WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_all(&state.pool)
+        .await?;
+    rows.iter().map(|row| row.try_get("project_id")).collect()
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GetTaskResponse1 {
-    task_id: i64,
-    author_id: i64,
-    text: String,
+async fn team_ids_of_user(state: &Arc<AppState>, user_id: i64) -> Result<Vec<i64>, sqlx::Error> {
+    let rows = sqlx::query("SELECT team_id FROM team_members WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_all(&state.pool)
+        .await?;
+    rows.iter().map(|row| row.try_get("team_id")).collect()
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ListTasksRequest1 {
+// Users sharing at least one team with the user, the user included.
+async fn teammate_ids_of_user(
+    state: &Arc<AppState>,
     user_id: i64,
-    offset: i64,
-    limit: i64,
+) -> Result<Vec<i64>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT DISTINCT other.user_id FROM team_members own \
+         JOIN team_members other ON other.team_id = own.team_id WHERE own.user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_all(&state.pool)
+    .await?;
+    rows.iter().map(|row| row.try_get("user_id")).collect()
 }
 
-async fn create_task(
+// What tasks_service needs to know to show the user only the tasks they may see.
+async fn viewer(state: &Arc<AppState>, user_id: i64) -> Result<proto::Viewer, sqlx::Error> {
+    Ok(proto::Viewer {
+        user_id,
+        teammate_ids: teammate_ids_of_user(state, user_id).await?,
+        project_ids: project_ids_of_user(state, user_id).await?,
+        shared_task_ids: grants::shared_task_ids(&state.pool, user_id).await?,
+    })
+}
+
+// Whether the user may change the task through an edit grant, without being its author.
+async fn edit_granted(state: &Arc<AppState>, task_id: i64, user_id: i64) -> Result<bool, Response> {
+    match grants::permission(&state.pool, task_id, user_id).await {
+        Ok(permission) => Ok(permission == Some(Permission::Edit)),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    }
+}
+
+// The reader of an endpoint open to everyone: anonymous without a token, but a token that
+// is there has to be valid.
+async fn optional_viewer(
+    state: &Arc<AppState>,
     headers: HeaderMap,
-    Json(input_payload): Json<CreateTaskRequest1>,
-) -> Response {
-    let id_and_username = match check_authorization(headers).await {
-        CheckAuthorizationResult::IdAndUsername(username) => username,
-        CheckAuthorizationResult::NoToken => {
-            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+) -> Result<proto::Viewer, Response> {
+    match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(id_and_username) => {
+            match viewer(state, id_and_username.0).await {
+                Ok(viewer) => Ok(viewer),
+                Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+            }
         }
+        CheckAuthorizationResult::NoToken => Ok(proto::Viewer::default()),
         CheckAuthorizationResult::Invalid => {
-            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+            Err((StatusCode::UNAUTHORIZED, "Invalid token").into_response())
         }
-    };
+    }
+}
 
-    let url = "http://tasks_service:50051";
-    let mut client = match TaskServiceClient::connect(url).await {
-        Ok(client) => client,
-        Err(_) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
-    };
-    let req = proto::CreateTaskRequest {
-        author_id: id_and_username.0,
-        text: input_payload.text,
-    };
-    let request = tonic::Request::new(req);
-    let response = match client.create_task(request).await {
-        Ok(response) => response,
-        Err(e) => {
-            println!("Error creating task: {:?}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
+// Fails as get_task would if the reader may not see the task, so that what belongs to a hidden
+// task can't be read through its id either.
+async fn check_task_readable(
+    state: &Arc<AppState>,
+    headers: HeaderMap,
+    task_id: i64,
+) -> Result<(), Response> {
+    let viewer = optional_viewer(state, headers).await?;
+    let mut client = state.tasks_client.clone();
+    let req = proto::GetTaskRequest {
+        task_id,
+        viewer: Some(viewer),
+        ..Default::default()
     };
+    match client.get_task(tonic::Request::new(req)).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(grpc_error_response(e)),
+    }
+}
 
-    let resp = CreateTaskResponse1 {
-        task_id: response.get_ref().task_id,
-    };
-    (StatusCode::CREATED, Json(resp)).into_response()
+async fn is_team_member(
+    state: &Arc<AppState>,
+    team_id: i64,
+    user_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query("SELECT 1 FROM team_members WHERE team_id = $1 AND user_id = $2")
+        .bind(team_id)
+        .bind(user_id)
+        .fetch_optional(&state.pool)
+        .await?;
+    Ok(row.is_some())
 }
 
-async fn update_task(
+async fn create_team(
+    State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-    Json(input_payload): Json<UpdateTaskRequest1>,
+    Json(input_payload): Json<CreateTeamRequest1>,
 ) -> Response {
     let id_and_username = match check_authorization(headers).await {
         CheckAuthorizationResult::IdAndUsername(username) => username,
@@ -496,33 +706,56 @@ async fn update_task(
             return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
         }
     };
+    let name = input_payload.name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            "Team name must be from 1 to 100 symbols",
+        )
+            .into_response();
+    }
 
-    let url = "http://tasks_service:50051";
-    let mut client = match TaskServiceClient::connect(url).await {
-        Ok(client) => client,
-        Err(_) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
-    };
-    let req = proto::UpdateTaskRequest {
-        user_id: id_and_username.0,
-        task_id: input_payload.task_id,
-        new_text: input_payload.new_text,
+    let mut transaction = match state.pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
     };
-    let request = tonic::Request::new(req);
-    match client.update_task(request).await {
-        Ok(_) => {}
-        Err(_) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
+    let query_result =
+        sqlx::query("INSERT INTO teams (name, owner_id) VALUES ($1, $2) RETURNING id")
+            .bind(name)
+            .bind(id_and_username.0)
+            .fetch_one(&mut transaction)
+            .await;
+    let team_id: i64 = match query_result {
+        Ok(row) => row.get("id"),
+        Err(_) => return (StatusCode::CONFLICT, "Team name exists").into_response(),
     };
+    let query_result = sqlx::query("INSERT INTO team_members (team_id, user_id) VALUES ($1, $2)")
+        .bind(team_id)
+        .bind(id_and_username.0)
+        .execute(&mut transaction)
+        .await;
+    if query_result.is_err() || transaction.commit().await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
 
-    (StatusCode::OK).into_response()
+    (StatusCode::CREATED, Json(CreateTeamResponse1 { team_id })).into_response()
 }
 
-async fn delete_task(
+async fn team_owner(state: &Arc<AppState>, team_id: i64) -> Result<Option<i64>, sqlx::Error> {
+    let row = sqlx::query("SELECT owner_id FROM teams WHERE id = $1")
+        .bind(team_id)
+        .fetch_optional(&state.pool)
+        .await?;
+    match row {
+        Some(row) => Ok(Some(row.try_get("owner_id")?)),
+        None => Ok(None),
+    }
+}
+
+async fn add_team_member(
+    State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-    Json(input_payload): Json<DeleteTaskRequest1>,
+    Json(input_payload): Json<TeamMemberRequest1>,
 ) -> Response {
     let id_and_username = match check_authorization(headers).await {
         CheckAuthorizationResult::IdAndUsername(username) => username,
@@ -533,98 +766,37 @@ async fn delete_task(
             return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
         }
     };
+    match team_owner(&state, input_payload.team_id).await {
+        Ok(Some(owner_id)) if owner_id == id_and_username.0 => {}
+        Ok(Some(_)) => return (StatusCode::FORBIDDEN).into_response(),
+        Ok(None) => return (StatusCode::NOT_FOUND, "Team doesn't exist").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+    match user_exists(&state, input_payload.user_id).await {
+        Ok(true) => {}
+        Ok(false) => return (StatusCode::NOT_ACCEPTABLE, "User doesn't exist").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
 
-    let url = "http://tasks_service:50051";
-    let mut client = match TaskServiceClient::connect(url).await {
-        Ok(client) => client,
-        Err(_) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
-    };
-    let req = proto::DeleteTaskRequest {
-        user_id: id_and_username.0,
-        task_id: input_payload.task_id,
-    };
-    let request = tonic::Request::new(req);
-    match client.delete_task(request).await {
-        Ok(_) => {}
-        Err(_) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
-    };
-    (StatusCode::OK).into_response()
-}
-
-async fn get_task(Json(input_payload): Json<GetTaskRequest1>) -> Response {
-    let url = "http://tasks_service:50051";
-    let mut client = match TaskServiceClient::connect(url).await {
-        Ok(client) => client,
-        Err(_) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
-    };
-    let req = proto::GetTaskRequest {
-        task_id: input_payload.task_id,
-    };
-    let request = tonic::Request::new(req);
-    let response = match client.get_task(request).await {
-        Ok(response) => response,
-        Err(_) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
-    };
-
-    let resp = GetTaskResponse1 {
-        task_id: response.get_ref().task_id,
-        author_id: response.get_ref().author_id,
-        text: response.get_ref().text.clone(),
-    };
-    (StatusCode::CREATED, Json(resp)).into_response()
-}
-
-async fn list_tasks(Json(input_payload): Json<ListTasksRequest1>) -> Response {
-    let url = "http://tasks_service:50051";
-    let mut client = match TaskServiceClient::connect(url).await {
-        Ok(client) => client,
-        Err(_) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
-    };
-    let req = proto::ListTasksRequest {
-        user_id: input_payload.user_id,
-        offset: input_payload.offset,
-        limit: input_payload.limit,
-    };
-    let request = tonic::Request::new(req);
-    let response = match client.list_tasks(request).await {
-        Ok(response) => response,
-        Err(e) => {
-            println!("{}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
-    };
-
-    let tasks: Vec<GetTaskResponse1> = response
-        .get_ref()
-        .clone()
-        .tasks
-        .into_iter()
-        .map(|task| GetTaskResponse1 {
-            task_id: task.task_id,
-            author_id: task.author_id,
-            text: task.text,
-        })
-        .collect();
-
-    (StatusCode::OK, Json(tasks)).into_response()
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LikeOrViewRequest1 {
-    task_id: i64,
+    let query_result = sqlx::query(
+        "INSERT INTO team_members (team_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+    )
+    .bind(input_payload.team_id)
+    .bind(input_payload.user_id)
+    .execute(&state.pool)
+    .await;
+    match query_result {
+        Ok(_) => (StatusCode::OK).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
 }
 
-async fn like(headers: HeaderMap, Json(input_payload): Json<LikeOrViewRequest1>) -> Response {
+// The owner can remove anyone but themselves; members can leave on their own.
+async fn remove_team_member(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<TeamMemberRequest1>,
+) -> Response {
     let id_and_username = match check_authorization(headers).await {
         CheckAuthorizationResult::IdAndUsername(username) => username,
         CheckAuthorizationResult::NoToken => {
@@ -634,32 +806,30 @@ async fn like(headers: HeaderMap, Json(input_payload): Json<LikeOrViewRequest1>)
             return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
         }
     };
-
-    let url = "http://tasks_service:50051";
-    let mut client = match TaskServiceClient::connect(url).await {
-        Ok(client) => client,
-        Err(_) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
-    };
-
-    let req = proto::SendLikeOrViewRequest {
-        task_id: input_payload.task_id,
-        liker_id: id_and_username.0,
-    };
-    let request = tonic::Request::new(req);
-    match client.send_like(request).await {
-        Ok(_) => {}
-        Err(e) => {
-            println!("{:?}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
-        }
+    let owner_id = match team_owner(&state, input_payload.team_id).await {
+        Ok(Some(owner_id)) => owner_id,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Team doesn't exist").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
     };
+    if input_payload.user_id == owner_id {
+        return (StatusCode::CONFLICT, "The owner can't leave the team").into_response();
+    }
+    if id_and_username.0 != owner_id && id_and_username.0 != input_payload.user_id {
+        return (StatusCode::FORBIDDEN).into_response();
+    }
 
-    (StatusCode::OK).into_response()
+    let query_result = sqlx::query("DELETE FROM team_members WHERE team_id = $1 AND user_id = $2")
+        .bind(input_payload.team_id)
+        .bind(input_payload.user_id)
+        .execute(&state.pool)
+        .await;
+    match query_result {
+        Ok(_) => (StatusCode::OK).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
 }
 
-async fn view(headers: HeaderMap, Json(input_payload): Json<LikeOrViewRequest1>) -> Response {
+async fn my_teams(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
     let id_and_username = match check_authorization(headers).await {
         CheckAuthorizationResult::IdAndUsername(username) => username,
         CheckAuthorizationResult::NoToken => {
@@ -670,20 +840,6705 @@ async fn view(headers: HeaderMap, Json(input_payload): Json<LikeOrViewRequest1>)
         }
     };
 
-    let url = "http://tasks_service:50051";
-    let mut client = match TaskServiceClient::connect(url).await {
-        Ok(client) => client,
-        Err(_) => {
-            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    let query_result = sqlx::query(
+        "SELECT t.id, t.name, t.owner_id FROM teams t \
+         JOIN team_members m ON m.team_id = t.id WHERE m.user_id = $1 ORDER BY t.id",
+    )
+    .bind(id_and_username.0)
+    .fetch_all(&state.pool)
+    .await;
+    match query_result {
+        Ok(rows) => {
+            let teams: Vec<TeamResponse1> = rows
+                .iter()
+                .map(|row| TeamResponse1 {
+                    team_id: row.get("id"),
+                    name: row.get("name"),
+                    owner_id: row.get("owner_id"),
+                })
+                .collect();
+            (StatusCode::OK, Json(teams)).into_response()
         }
-    };
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
 
-    let req = proto::SendLikeOrViewRequest {
-        task_id: input_payload.task_id,
-        liker_id: id_and_username.0,
-    };
-    let request = tonic::Request::new(req);
-    match client.send_view(request).await {
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateProjectRequest1 {
+    key: String,
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateProjectResponse1 {
+    project_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectMemberRequest1 {
+    project_id: i64,
+    user_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveProjectRequest1 {
+    project_id: i64,
+    archived: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectResponse1 {
+    project_id: i64,
+    key: String,
+    name: String,
+    owner_id: i64,
+    archived: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectTasksRequest1 {
+    project_id: i64,
+    #[serde(flatten)]
+    query: TaskListQuery1,
+}
+
+struct ProjectModel {
+    owner_id: i64,
+    archived: bool,
+}
+
+fn check_project_key(key: &str) -> bool {
+    (2..=10).contains(&key.len())
+        && key.starts_with(|c: char| c.is_ascii_uppercase())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+// Splits a task key like `OPS-42` into the project key and the number inside the project.
+fn parse_task_key(key: &str) -> Option<(&str, i64)> {
+    let (project_key, number) = key.rsplit_once('-')?;
+    let number: i64 = number.parse().ok()?;
+    if !check_project_key(project_key) || number < 1 {
+        return None;
+    }
+    Some((project_key, number))
+}
+
+async fn project_id_by_key(state: &Arc<AppState>, key: &str) -> Result<Option<i64>, sqlx::Error> {
+    let row = sqlx::query("SELECT id FROM projects WHERE key = $1")
+        .bind(key)
+        .fetch_optional(&state.pool)
+        .await?;
+    match row {
+        Some(row) => Ok(Some(row.try_get("id")?)),
+        None => Ok(None),
+    }
+}
+
+async fn get_project(
+    state: &Arc<AppState>,
+    project_id: i64,
+) -> Result<Option<ProjectModel>, sqlx::Error> {
+    let row = sqlx::query("SELECT owner_id, archived FROM projects WHERE id = $1")
+        .bind(project_id)
+        .fetch_optional(&state.pool)
+        .await?;
+    match row {
+        Some(row) => Ok(Some(ProjectModel {
+            owner_id: row.try_get("owner_id")?,
+            archived: row.try_get("archived")?,
+        })),
+        None => Ok(None),
+    }
+}
+
+async fn is_project_member(
+    state: &Arc<AppState>,
+    project_id: i64,
+    user_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query("SELECT 1 FROM project_members WHERE project_id = $1 AND user_id = $2")
+        .bind(project_id)
+        .bind(user_id)
+        .fetch_optional(&state.pool)
+        .await?;
+    Ok(row.is_some())
+}
+
+// Checks that the user may work with the project's tasks; archived projects are read-only.
+async fn check_project_access(
+    state: &Arc<AppState>,
+    project_id: i64,
+    user_id: i64,
+    for_write: bool,
+) -> Result<(), Response> {
+    let project = match get_project(state, project_id).await {
+        Ok(Some(project)) => project,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Project doesn't exist").into_response()),
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    };
+    match is_project_member(state, project_id, user_id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err((StatusCode::FORBIDDEN, "Not a project member").into_response());
+        }
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    }
+    if for_write && project.archived {
+        return Err((StatusCode::CONFLICT, "Project is archived").into_response());
+    }
+    Ok(())
+}
+
+async fn fill_task_keys(
+    state: &Arc<AppState>,
+    tasks: &mut [GetTaskResponse1],
+) -> Result<(), sqlx::Error> {
+    let project_ids: Vec<i64> = tasks.iter().filter_map(|task| task.project_id).collect();
+    if project_ids.is_empty() {
+        return Ok(());
+    }
+    let rows = sqlx::query("SELECT id, key FROM projects WHERE id = ANY($1)")
+        .bind(&project_ids)
+        .fetch_all(&state.pool)
+        .await?;
+    let mut keys = HashMap::new();
+    for row in rows {
+        let id: i64 = row.try_get("id")?;
+        let key: String = row.try_get("key")?;
+        keys.insert(id, key);
+    }
+    for task in tasks.iter_mut() {
+        if let Some(project_key) = task.project_id.and_then(|id| keys.get(&id)) {
+            task.key = Some(format!("{}-{}", project_key, task.project_number));
+        }
+    }
+    Ok(())
+}
+
+async fn fill_task_mentions(
+    state: &Arc<AppState>,
+    tasks: &mut [GetTaskResponse1],
+) -> Result<(), sqlx::Error> {
+    let task_ids: Vec<i64> = tasks.iter().map(|task| task.task_id).collect();
+    let mut mentions = mentions::load(&state.pool, mentions::Source::Task, &task_ids).await?;
+    for task in tasks.iter_mut() {
+        if let Some(task_mentions) = mentions.remove(&task.task_id) {
+            task.rendered_text = mentions::render(&task.text, &task_mentions);
+            task.text_html = markdown::to_html(&task.rendered_text);
+            task.mentions = task_mentions;
+        }
+    }
+    Ok(())
+}
+
+// Fills in what the responses link to: the keys of the tasks and the users they mention.
+async fn fill_task_links(
+    state: &Arc<AppState>,
+    tasks: &mut [GetTaskResponse1],
+) -> Result<(), sqlx::Error> {
+    fill_task_keys(state, tasks).await?;
+    fill_task_mentions(state, tasks).await
+}
+
+fn fill_task_relations(workflow: &Workflow, tasks: &mut [GetTaskResponse1]) {
+    for task in tasks.iter_mut() {
+        let subtasks: i64 = task.subtask_statuses.iter().map(|s| s.count).sum();
+        if subtasks > 0 {
+            let done: i64 = task
+                .subtask_statuses
+                .iter()
+                .filter(|s| workflow.is_done(&s.status))
+                .map(|s| s.count)
+                .sum();
+            task.progress = Some(ProgressResponse1 {
+                subtasks,
+                done,
+                percent: done * 100 / subtasks,
+            });
+        }
+        task.blocked = task
+            .blocker_statuses
+            .iter()
+            .any(|s| !workflow.is_done(&s.status));
+    }
+}
+
+async fn create_project(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<CreateProjectRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if !check_project_key(&input_payload.key) {
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            "Project key must be from 2 to 10 symbols, start with an uppercase letter and consist only of uppercase letters and digits.",
+        )
+            .into_response();
+    }
+    let name = input_payload.name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            "Project name must be from 1 to 100 symbols",
+        )
+            .into_response();
+    }
+
+    let mut transaction = match state.pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+    let query_result =
+        sqlx::query("INSERT INTO projects (key, name, owner_id) VALUES ($1, $2, $3) RETURNING id")
+            .bind(&input_payload.key)
+            .bind(name)
+            .bind(id_and_username.0)
+            .fetch_one(&mut transaction)
+            .await;
+    let project_id: i64 = match query_result {
+        Ok(row) => row.get("id"),
+        Err(_) => return (StatusCode::CONFLICT, "Project key exists").into_response(),
+    };
+    let query_result =
+        sqlx::query("INSERT INTO project_members (project_id, user_id) VALUES ($1, $2)")
+            .bind(project_id)
+            .bind(id_and_username.0)
+            .execute(&mut transaction)
+            .await;
+    if query_result.is_err() || transaction.commit().await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+
+    (
+        StatusCode::CREATED,
+        Json(CreateProjectResponse1 { project_id }),
+    )
+        .into_response()
+}
+
+async fn check_project_owner(
+    state: &Arc<AppState>,
+    project_id: i64,
+    user_id: i64,
+) -> Result<ProjectModel, Response> {
+    match get_project(state, project_id).await {
+        Ok(Some(project)) if project.owner_id == user_id => Ok(project),
+        Ok(Some(_)) => Err((StatusCode::FORBIDDEN).into_response()),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Project doesn't exist").into_response()),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    }
+}
+
+async fn add_project_member(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<ProjectMemberRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if let Err(response) =
+        check_project_owner(&state, input_payload.project_id, id_and_username.0).await
+    {
+        return response;
+    }
+    match user_exists(&state, input_payload.user_id).await {
+        Ok(true) => {}
+        Ok(false) => return (StatusCode::NOT_ACCEPTABLE, "User doesn't exist").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+
+    let query_result = sqlx::query(
+        "INSERT INTO project_members (project_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+    )
+    .bind(input_payload.project_id)
+    .bind(input_payload.user_id)
+    .execute(&state.pool)
+    .await;
+    match query_result {
+        Ok(_) => (StatusCode::OK).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+async fn remove_project_member(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<ProjectMemberRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let project =
+        match check_project_owner(&state, input_payload.project_id, id_and_username.0).await {
+            Ok(project) => project,
+            Err(response) => return response,
+        };
+    if input_payload.user_id == project.owner_id {
+        return (StatusCode::CONFLICT, "The owner can't leave the project").into_response();
+    }
+
+    let query_result =
+        sqlx::query("DELETE FROM project_members WHERE project_id = $1 AND user_id = $2")
+            .bind(input_payload.project_id)
+            .bind(input_payload.user_id)
+            .execute(&state.pool)
+            .await;
+    match query_result {
+        Ok(_) => (StatusCode::OK).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+async fn archive_project(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<ArchiveProjectRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if let Err(response) =
+        check_project_owner(&state, input_payload.project_id, id_and_username.0).await
+    {
+        return response;
+    }
+
+    let query_result = sqlx::query("UPDATE projects SET archived = $1 WHERE id = $2")
+        .bind(input_payload.archived)
+        .bind(input_payload.project_id)
+        .execute(&state.pool)
+        .await;
+    match query_result {
+        Ok(_) => (StatusCode::OK).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+async fn my_projects(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let query_result = sqlx::query(
+        "SELECT p.id, p.key, p.name, p.owner_id, p.archived FROM projects p \
+         JOIN project_members m ON m.project_id = p.id WHERE m.user_id = $1 ORDER BY p.key",
+    )
+    .bind(id_and_username.0)
+    .fetch_all(&state.pool)
+    .await;
+    match query_result {
+        Ok(rows) => {
+            let projects: Vec<ProjectResponse1> = rows
+                .iter()
+                .map(|row| ProjectResponse1 {
+                    project_id: row.get("id"),
+                    key: row.get("key"),
+                    name: row.get("name"),
+                    owner_id: row.get("owner_id"),
+                    archived: row.get("archived"),
+                })
+                .collect();
+            (StatusCode::OK, Json(projects)).into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+async fn project_tasks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<ProjectTasksRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if let Err(response) =
+        check_project_access(&state, input_payload.project_id, id_and_username.0, false).await
+    {
+        return response;
+    }
+    let req = match task_list_request(&state.workflow, input_payload.query) {
+        Ok(req) => req,
+        Err(e) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+    };
+    let viewer = match viewer(&state, id_and_username.0).await {
+        Ok(viewer) => viewer,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+
+    send_list_tasks(
+        &state,
+        proto::ListTasksRequest {
+            user_id: id_and_username.0,
+            kind: proto::TaskListKind::InProject.into(),
+            project_id: input_payload.project_id,
+            viewer: Some(viewer),
+            ..req
+        },
+    )
+    .await
+}
+
+const TASK_PRIORITIES: [&str; 4] = ["low", "medium", "high", "urgent"];
+const DEFAULT_TASK_PRIORITY: &str = "medium";
+const TASK_VISIBILITIES: [&str; 3] = ["private", "team", "public"];
+const DEFAULT_TASK_VISIBILITY: &str = "public";
+const MAX_TITLE_LEN: usize = 200;
+const MAX_DESCRIPTION_LEN: usize = 20000;
+const MAX_ESTIMATE_MINUTES: i64 = 60 * 24 * 365;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTaskRequest1 {
+    // Old clients send only `text`; it is treated as the description.
+    text: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    status: Option<String>,
+    priority: Option<String>,
+    due_date: Option<String>,
+    assignee_id: Option<i64>,
+    project_id: Option<i64>,
+    parent_id: Option<i64>,
+    estimate_minutes: Option<i64>,
+    visibility: Option<String>,
+    // Only /create_task takes a template; the fields above override what it fills in.
+    template_id: Option<i64>,
+    #[serde(default)]
+    variables: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTaskResponse1 {
+    task_id: i64,
+    subtask_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateTaskRequest1 {
+    task_id: i64,
+    new_text: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    status: Option<String>,
+    priority: Option<String>,
+    due_date: Option<String>,
+    assignee_id: Option<i64>,
+    #[serde(default)]
+    clear_due_date: bool,
+    #[serde(default)]
+    clear_assignee: bool,
+    parent_id: Option<i64>,
+    estimate_minutes: Option<i64>,
+    #[serde(default)]
+    clear_parent: bool,
+    #[serde(default)]
+    clear_estimate: bool,
+    visibility: Option<String>,
+    // The version the change is based on, as in the `ETag` of /get_task; /update_task also
+    // takes it from `If-Match`.
+    expected_version: Option<i64>,
+}
+
+impl UpdateTaskRequest1 {
+    // Whether the request changes nothing at all; the status is rejected on its own.
+    fn is_empty(&self) -> bool {
+        self.new_text.is_none()
+            && self.title.is_none()
+            && self.description.is_none()
+            && self.priority.is_none()
+            && self.due_date.is_none()
+            && self.assignee_id.is_none()
+            && !self.clear_due_date
+            && !self.clear_assignee
+            && self.parent_id.is_none()
+            && self.estimate_minutes.is_none()
+            && !self.clear_parent
+            && !self.clear_estimate
+            && self.visibility.is_none()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteTaskRequest1 {
+    task_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetTaskRequest1 {
+    task_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetTaskResponse1 {
+    task_id: i64,
+    author_id: i64,
+    text: String,
+    title: String,
+    description: String,
+    status: String,
+    priority: String,
+    due_date: Option<String>,
+    assignee_id: Option<i64>,
+    created_at: String,
+    updated_at: String,
+    labels: Vec<LabelResponse1>,
+    attachments: Vec<AttachmentResponse1>,
+    project_id: Option<i64>,
+    key: Option<String>,
+    parent_id: Option<i64>,
+    estimate_minutes: Option<i64>,
+    external_id: Option<String>,
+    series_id: Option<i64>,
+    version: i64,
+    visibility: String,
+    // Roll-up over the direct subtasks; absent when the task has none.
+    progress: Option<ProgressResponse1>,
+    // Some task this one depends on isn't done yet.
+    blocked: bool,
+    // The users mentioned in the text, and the text with them as Markdown links.
+    mentions: Vec<Mention>,
+    rendered_text: String,
+    // The rendered text as sanitised HTML.
+    text_html: String,
+    #[serde(skip)]
+    project_number: i64,
+    #[serde(skip)]
+    subtask_statuses: Vec<proto::StatusCount>,
+    #[serde(skip)]
+    blocker_statuses: Vec<proto::StatusCount>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProgressResponse1 {
+    subtasks: i64,
+    done: i64,
+    percent: i64,
+}
+
+impl From<proto::Task> for GetTaskResponse1 {
+    fn from(task: proto::Task) -> Self {
+        GetTaskResponse1 {
+            task_id: task.task_id,
+            author_id: task.author_id,
+            description: task.text.clone(),
+            rendered_text: task.text.clone(),
+            text_html: markdown::to_html(&task.text),
+            text: task.text,
+            title: task.title,
+            status: task.status,
+            priority: task.priority,
+            due_date: task.due_date,
+            assignee_id: task.assignee_id,
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            labels: task.labels.into_iter().map(LabelResponse1::from).collect(),
+            attachments: task
+                .attachments
+                .into_iter()
+                .map(AttachmentResponse1::from)
+                .collect(),
+            project_id: task.project_id,
+            key: None,
+            parent_id: task.parent_id,
+            estimate_minutes: task.estimate_minutes,
+            external_id: task.external_id,
+            series_id: task.series_id,
+            version: task.version,
+            visibility: task.visibility,
+            progress: None,
+            blocked: false,
+            mentions: vec![],
+            project_number: task.project_number,
+            subtask_statuses: task.subtask_statuses,
+            blocker_statuses: task.blocker_statuses,
+        }
+    }
+}
+
+impl From<proto::GetTaskResponse> for GetTaskResponse1 {
+    fn from(task: proto::GetTaskResponse) -> Self {
+        GetTaskResponse1 {
+            task_id: task.task_id,
+            author_id: task.author_id,
+            description: task.text.clone(),
+            rendered_text: task.text.clone(),
+            text_html: markdown::to_html(&task.text),
+            text: task.text,
+            title: task.title,
+            status: task.status,
+            priority: task.priority,
+            due_date: task.due_date,
+            assignee_id: task.assignee_id,
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            labels: task.labels.into_iter().map(LabelResponse1::from).collect(),
+            attachments: task
+                .attachments
+                .into_iter()
+                .map(AttachmentResponse1::from)
+                .collect(),
+            project_id: task.project_id,
+            key: None,
+            parent_id: task.parent_id,
+            estimate_minutes: task.estimate_minutes,
+            external_id: task.external_id,
+            series_id: task.series_id,
+            version: task.version,
+            visibility: task.visibility,
+            progress: None,
+            blocked: false,
+            mentions: vec![],
+            project_number: task.project_number,
+            subtask_statuses: task.subtask_statuses,
+            blocker_statuses: task.blocker_statuses,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListTasksRequest1 {
+    user_id: i64,
+    #[serde(flatten)]
+    query: TaskListQuery1,
+}
+
+// Paging, sorting and filters shared by all task listings.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskListQuery1 {
+    // Kept for old clients; new ones follow `next_cursor`, which doesn't skip or repeat
+    // tasks when others are created in between.
+    #[serde(default)]
+    offset: i64,
+    #[serde(default = "default_page_size")]
+    limit: i64,
+    cursor: Option<String>,
+    sort: Option<String>,
+    order: Option<String>,
+    #[serde(default)]
+    statuses: Vec<String>,
+    #[serde(default)]
+    label_ids: Vec<i64>,
+    assignee_id: Option<i64>,
+    created_after: Option<String>,
+    created_before: Option<String>,
+    updated_after: Option<String>,
+    updated_before: Option<String>,
+    due_after: Option<String>,
+    due_before: Option<String>,
+    #[serde(default)]
+    include_total: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskPageResponse1 {
+    tasks: Vec<GetTaskResponse1>,
+    next_cursor: Option<String>,
+    total_count: Option<i64>,
+}
+
+const TASK_SORTS: [&str; 5] = ["created", "updated", "due", "priority", "likes"];
+
+fn parse_optional_timestamp(value: Option<String>, name: &str) -> Result<Option<String>, String> {
+    value.map(|value| parse_timestamp(&value, name)).transpose()
+}
+
+// Validates the query and fills everything but the listing kind and its owner.
+fn task_list_request(
+    workflow: &Workflow,
+    query: TaskListQuery1,
+) -> Result<proto::ListTasksRequest, String> {
+    check_page(query.offset, query.limit)?;
+    if query.cursor.is_some() && query.offset != 0 {
+        return Err("cursor and offset can't be used together".to_string());
+    }
+    let sort = match query.sort.as_deref().unwrap_or("created") {
+        "created" => proto::TaskSort::Created,
+        "updated" => proto::TaskSort::Updated,
+        "due" => proto::TaskSort::Due,
+        "priority" => proto::TaskSort::Priority,
+        "likes" => proto::TaskSort::Likes,
+        _ => return Err(format!("Sort must be one of: {}", TASK_SORTS.join(", "))),
+    };
+    let descending = match query.order.as_deref().unwrap_or("asc") {
+        "asc" => false,
+        "desc" => true,
+        _ => return Err("Order must be asc or desc".to_string()),
+    };
+    for status in &query.statuses {
+        check_status(workflow, status)?;
+    }
+    Ok(proto::ListTasksRequest {
+        offset: query.offset,
+        limit: query.limit,
+        label_ids: query.label_ids,
+        statuses: query.statuses,
+        assignee_id: query.assignee_id,
+        created_after: parse_optional_timestamp(query.created_after, "created_after")?,
+        created_before: parse_optional_timestamp(query.created_before, "created_before")?,
+        updated_after: parse_optional_timestamp(query.updated_after, "updated_after")?,
+        updated_before: parse_optional_timestamp(query.updated_before, "updated_before")?,
+        due_after: parse_optional_timestamp(query.due_after, "due_after")?,
+        due_before: parse_optional_timestamp(query.due_before, "due_before")?,
+        sort: sort.into(),
+        descending,
+        cursor: query.cursor.unwrap_or_default(),
+        include_total: query.include_total,
+        ..Default::default()
+    })
+}
+
+fn check_title(title: &str) -> Result<(), String> {
+    if title.trim().is_empty() {
+        return Err("Title must not be empty".to_string());
+    }
+    if title.chars().count() > MAX_TITLE_LEN {
+        return Err(format!("Title must be at most {} symbols", MAX_TITLE_LEN));
+    }
+    Ok(())
+}
+
+fn check_description(description: &str) -> Result<(), String> {
+    if description.chars().count() > MAX_DESCRIPTION_LEN {
+        return Err(format!(
+            "Description must be at most {} symbols",
+            MAX_DESCRIPTION_LEN
+        ));
+    }
+    Ok(())
+}
+
+fn check_status(workflow: &Workflow, status: &str) -> Result<(), String> {
+    if !workflow.has_status(status) {
+        return Err(format!(
+            "Status must be one of: {}",
+            workflow.statuses.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+// New tasks enter the workflow at its start; they move on with transitions.
+fn check_initial_status(workflow: &Workflow, status: &str) -> Result<(), String> {
+    if status != workflow.initial_status {
+        return Err(format!(
+            "A new task must have the status {}",
+            workflow.initial_status
+        ));
+    }
+    Ok(())
+}
+
+fn check_estimate(estimate_minutes: i64) -> Result<(), String> {
+    if !(0..=MAX_ESTIMATE_MINUTES).contains(&estimate_minutes) {
+        return Err(format!(
+            "Estimate must be from 0 to {} minutes",
+            MAX_ESTIMATE_MINUTES
+        ));
+    }
+    Ok(())
+}
+
+fn check_priority(priority: &str) -> Result<(), String> {
+    if !TASK_PRIORITIES.contains(&priority) {
+        return Err(format!(
+            "Priority must be one of: {}",
+            TASK_PRIORITIES.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+fn check_visibility(visibility: &str) -> Result<(), String> {
+    if !TASK_VISIBILITIES.contains(&visibility) {
+        return Err(format!(
+            "Visibility must be one of: {}",
+            TASK_VISIBILITIES.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+// Accepts either an RFC 3339 timestamp or a plain `YYYY-MM-DD` date (midnight UTC)
+// and returns it normalized to RFC 3339.
+fn parse_timestamp(value: &str, name: &str) -> Result<String, String> {
+    if let Ok(date_time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(date_time.to_rfc3339());
+    }
+    match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        Ok(date) => Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc().to_rfc3339()),
+        Err(_) => Err(format!(
+            "{} must be an RFC 3339 timestamp or a YYYY-MM-DD date",
+            name
+        )),
+    }
+}
+
+fn parse_due_date(due_date: &str) -> Result<String, String> {
+    parse_timestamp(due_date, "Due date")
+}
+
+async fn user_exists(state: &Arc<AppState>, user_id: i64) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query("SELECT id FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&state.pool)
+        .await?;
+    Ok(row.is_some())
+}
+
+async fn check_assignee(state: &Arc<AppState>, assignee_id: Option<i64>) -> Result<(), Response> {
+    let assignee_id = match assignee_id {
+        Some(assignee_id) => assignee_id,
+        None => return Ok(()),
+    };
+    match user_exists(state, assignee_id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err((StatusCode::NOT_ACCEPTABLE, "Assignee doesn't exist").into_response()),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    }
+}
+
+// A subtask changes its parent's progress, so only those who may edit the parent add one.
+async fn check_parent(
+    state: &Arc<AppState>,
+    parent_id: Option<i64>,
+    user_id: i64,
+) -> Result<(), Response> {
+    let parent_id = match parent_id {
+        Some(parent_id) => parent_id,
+        None => return Ok(()),
+    };
+    let parent = visible_task(state, parent_id, user_id).await?;
+    if parent.author_id != user_id && !edit_granted(state, parent_id, user_id).await? {
+        return Err((StatusCode::FORBIDDEN, "Permission Denied").into_response());
+    }
+    Ok(())
+}
+
+// Title for tasks created by old clients: the first non-empty line of the text.
+fn title_from_text(text: &str) -> String {
+    let first_line = text
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .unwrap_or("");
+    first_line.chars().take(MAX_TITLE_LEN).collect()
+}
+
+async fn create_task_request(
+    state: &Arc<AppState>,
+    user_id: i64,
+    input_payload: CreateTaskRequest1,
+) -> Result<proto::CreateTaskRequest, Response> {
+    if input_payload.template_id.is_some() {
+        return Err((
+            StatusCode::NOT_ACCEPTABLE,
+            "Templates can only be used with /create_task",
+        )
+            .into_response());
+    }
+    let description = input_payload
+        .description
+        .or(input_payload.text)
+        .unwrap_or_default();
+    let title = match input_payload.title {
+        Some(title) => title.trim().to_string(),
+        None => title_from_text(&description),
+    };
+    let status = input_payload
+        .status
+        .unwrap_or(state.workflow.initial_status.clone());
+    let priority = input_payload
+        .priority
+        .unwrap_or(DEFAULT_TASK_PRIORITY.to_string());
+    let visibility = input_payload
+        .visibility
+        .unwrap_or(DEFAULT_TASK_VISIBILITY.to_string());
+    let due_date = match input_payload.due_date.as_deref().map(parse_due_date) {
+        Some(Ok(due_date)) => Some(due_date),
+        Some(Err(e)) => return Err((StatusCode::NOT_ACCEPTABLE, e).into_response()),
+        None => None,
+    };
+    let checks = check_title(&title)
+        .and(check_description(&description))
+        .and(check_initial_status(&state.workflow, &status))
+        .and(check_priority(&priority))
+        .and(check_visibility(&visibility))
+        .and(
+            input_payload
+                .estimate_minutes
+                .map_or(Ok(()), check_estimate),
+        );
+    if let Err(e) = checks {
+        return Err((StatusCode::NOT_ACCEPTABLE, e).into_response());
+    }
+    check_assignee(state, input_payload.assignee_id).await?;
+    if let Some(project_id) = input_payload.project_id {
+        check_project_access(state, project_id, user_id, true).await?;
+    }
+    check_parent(state, input_payload.parent_id, user_id).await?;
+
+    Ok(proto::CreateTaskRequest {
+        author_id: user_id,
+        text: description,
+        title,
+        status,
+        priority,
+        due_date,
+        assignee_id: input_payload.assignee_id,
+        project_id: input_payload.project_id,
+        parent_id: input_payload.parent_id,
+        estimate_minutes: input_payload.estimate_minutes,
+        external_id: None,
+        label_ids: vec![],
+        subtasks: vec![],
+        visibility,
+    })
+}
+
+async fn create_task(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<CreateTaskRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut input_payload = input_payload;
+    let instance = match input_payload.template_id.take() {
+        Some(template_id) => {
+            match instantiate_template(&state, &id_and_username, template_id, input_payload).await {
+                Ok(instance) => {
+                    input_payload = instance.request;
+                    Some((instance.label_ids, instance.subtasks))
+                }
+                Err(response) => return response,
+            }
+        }
+        None => None,
+    };
+    let mut req = match create_task_request(&state, id_and_username.0, input_payload).await {
+        Ok(req) => req,
+        Err(response) => return response,
+    };
+    if let Some((label_ids, subtasks)) = instance {
+        req.label_ids = label_ids;
+        req.subtasks = subtasks
+            .into_iter()
+            .map(|subtask| proto::CreateTaskRequest {
+                author_id: req.author_id,
+                text: subtask.description,
+                title: subtask.title,
+                status: state.workflow.initial_status.clone(),
+                priority: req.priority.clone(),
+                project_id: req.project_id,
+                visibility: req.visibility.clone(),
+                ..Default::default()
+            })
+            .collect();
+    }
+    let assigned = req.assignee_id.is_some();
+    let text = req.text.clone();
+    let subtask_texts: Vec<String> = req
+        .subtasks
+        .iter()
+        .map(|subtask| subtask.text.clone())
+        .collect();
+    let mut client = state.tasks_client.clone();
+    let request = tonic::Request::new(req);
+    let response = match client.create_task(request).await {
+        Ok(response) => response,
+        Err(e) => {
+            println!("Error creating task: {:?}", e);
+            return grpc_error_response(e);
+        }
+    };
+
+    let resp = CreateTaskResponse1 {
+        task_id: response.get_ref().task_id,
+        subtask_ids: response.get_ref().subtask_ids.clone(),
+    };
+    if assigned {
+        notify_task_watchers(
+            &state,
+            resp.task_id,
+            &id_and_username,
+            Event::Assigned,
+            None,
+        )
+        .await;
+    }
+    sync_mentions(
+        &state,
+        mentions::Source::Task,
+        resp.task_id,
+        resp.task_id,
+        &text,
+        &id_and_username,
+    )
+    .await;
+    for (subtask_id, text) in resp.subtask_ids.iter().zip(&subtask_texts) {
+        sync_mentions(
+            &state,
+            mentions::Source::Task,
+            *subtask_id,
+            *subtask_id,
+            text,
+            &id_and_username,
+        )
+        .await;
+    }
+    (StatusCode::CREATED, Json(resp)).into_response()
+}
+
+// Tasks are tagged with their version, which changes with every edit.
+fn task_etag(version: i64) -> String {
+    format!("\"{}\"", version)
+}
+
+// The version an `If-Match` header expects; None without one or for `*`. A value that is no
+// ETag of a task can't match.
+fn if_match_version(headers: &HeaderMap) -> Result<Option<i64>, String> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let value = value.to_str().unwrap_or_default().trim();
+    if value == "*" {
+        return Ok(None);
+    }
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .and_then(|version| version.parse().ok())
+    {
+        Some(version) => Ok(Some(version)),
+        None => Err("If-Match must be an ETag of the task".to_string()),
+    }
+}
+
+async fn update_task_request(
+    state: &Arc<AppState>,
+    user_id: i64,
+    input_payload: UpdateTaskRequest1,
+) -> Result<proto::UpdateTaskRequest, Response> {
+    if input_payload.status.is_some() {
+        return Err((
+            StatusCode::NOT_ACCEPTABLE,
+            "Status can only be changed through /transition_task",
+        )
+            .into_response());
+    }
+    if input_payload.is_empty() {
+        return Err((StatusCode::NOT_ACCEPTABLE, "Nothing to update").into_response());
+    }
+
+    let description = input_payload.description.or(input_payload.new_text);
+    let title = input_payload.title.map(|title| title.trim().to_string());
+    let due_date = match input_payload.due_date.as_deref().map(parse_due_date) {
+        Some(Ok(due_date)) => Some(due_date),
+        Some(Err(e)) => return Err((StatusCode::NOT_ACCEPTABLE, e).into_response()),
+        None => None,
+    };
+    let checks = title
+        .as_deref()
+        .map_or(Ok(()), check_title)
+        .and(description.as_deref().map_or(Ok(()), check_description))
+        .and(
+            input_payload
+                .priority
+                .as_deref()
+                .map_or(Ok(()), check_priority),
+        )
+        .and(
+            input_payload
+                .visibility
+                .as_deref()
+                .map_or(Ok(()), check_visibility),
+        )
+        .and(
+            input_payload
+                .estimate_minutes
+                .map_or(Ok(()), check_estimate),
+        );
+    if let Err(e) = checks {
+        return Err((StatusCode::NOT_ACCEPTABLE, e).into_response());
+    }
+    check_assignee(state, input_payload.assignee_id).await?;
+    check_parent(state, input_payload.parent_id, user_id).await?;
+    let granted = edit_granted(state, input_payload.task_id, user_id).await?;
+
+    Ok(proto::UpdateTaskRequest {
+        user_id,
+        task_id: input_payload.task_id,
+        new_text: description,
+        title,
+        status: None,
+        priority: input_payload.priority,
+        due_date,
+        assignee_id: input_payload.assignee_id,
+        clear_due_date: input_payload.clear_due_date,
+        clear_assignee: input_payload.clear_assignee,
+        parent_id: input_payload.parent_id,
+        estimate_minutes: input_payload.estimate_minutes,
+        clear_parent: input_payload.clear_parent,
+        clear_estimate: input_payload.clear_estimate,
+        expected_version: input_payload.expected_version,
+        visibility: input_payload.visibility,
+        granted,
+    })
+}
+
+async fn update_task(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(mut input_payload): Json<UpdateTaskRequest1>,
+) -> Response {
+    match if_match_version(&headers) {
+        Ok(Some(version)) => input_payload.expected_version = Some(version),
+        Ok(None) => {}
+        Err(e) => return (StatusCode::PRECONDITION_FAILED, e).into_response(),
+    }
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let req = match update_task_request(&state, id_and_username.0, input_payload).await {
+        Ok(req) => req,
+        Err(response) => return response,
+    };
+    let task_id = req.task_id;
+    let due_date_changed = req.due_date.is_some() || req.clear_due_date;
+    let new_text = req.new_text.clone();
+    let mut client = state.tasks_client.clone();
+    let request = tonic::Request::new(req);
+    let version = match client.update_task(request).await {
+        Ok(response) => response.into_inner().version,
+        Err(e) => return grpc_error_response(e),
+    };
+    notify_task_watchers(&state, task_id, &id_and_username, Event::Updated, None).await;
+    if let Some(text) = new_text {
+        sync_mentions(
+            &state,
+            mentions::Source::Task,
+            task_id,
+            task_id,
+            &text,
+            &id_and_username,
+        )
+        .await;
+    }
+    if due_date_changed {
+        reschedule_reminders(&state, task_id).await;
+    }
+
+    (StatusCode::OK, [(header::ETAG, task_etag(version))]).into_response()
+}
+
+// The due date of the task has changed, so its reminders relative to it fire at another time.
+async fn reschedule_reminders(state: &Arc<AppState>, task_id: i64) {
+    if let Err(e) = reminders::due_date_changed(&state.pool, task_id).await {
+        println!(
+            "Failed to reschedule the reminders of task {}: {}",
+            task_id, e
+        );
+    }
+}
+
+async fn delete_task(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<DeleteTaskRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+    // The watchers are only known while the task is still there.
+    let req = proto::GetTaskRequest {
+        task_id: input_payload.task_id,
+        ..Default::default()
+    };
+    let task = match client.get_task(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+    let granted = match edit_granted(&state, input_payload.task_id, id_and_username.0).await {
+        Ok(granted) => granted,
+        Err(response) => return response,
+    };
+    let req = proto::DeleteTaskRequest {
+        user_id: id_and_username.0,
+        task_id: input_payload.task_id,
+        granted,
+    };
+    let request = tonic::Request::new(req);
+    if let Err(e) = client.delete_task(request).await {
+        return grpc_error_response(e);
+    }
+    notify_watchers(&state, &task, &id_and_username, Event::Deleted, None).await;
+    (StatusCode::OK).into_response()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetChecklistItemRequest1 {
+    checked: bool,
+}
+
+// Checks or unchecks a task list item of the text, numbered as by `data-index` in `text_html`,
+// by editing the Markdown source. Returns the task.
+async fn set_checklist_item(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path((task_id, index)): Path<(i64, usize)>,
+    Json(input_payload): Json<SetChecklistItemRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    // Only those who may edit the text learn anything about it, even its number of items.
+    let task = match visible_task(&state, task_id, id_and_username.0).await {
+        Ok(task) => task,
+        Err(response) => return response,
+    };
+    let granted = match edit_granted(&state, task_id, id_and_username.0).await {
+        Ok(granted) => granted,
+        Err(response) => return response,
+    };
+    if task.author_id != id_and_username.0 && !granted {
+        return (StatusCode::FORBIDDEN, "Permission Denied").into_response();
+    }
+    let mut client = state.tasks_client.clone();
+    let req = proto::GetTaskRequest {
+        task_id,
+        ..Default::default()
+    };
+    let text = match markdown::set_task_item(&task.text, index, input_payload.checked) {
+        Some(text) => text,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                format!(
+                    "The text has {} checklist items",
+                    markdown::task_item_count(&task.text)
+                ),
+            )
+                .into_response();
+        }
+    };
+
+    let mut task = task;
+    if text != task.text {
+        let update = proto::UpdateTaskRequest {
+            user_id: id_and_username.0,
+            task_id,
+            new_text: Some(text),
+            // The text was edited as it was at this version.
+            expected_version: Some(task.version),
+            granted,
+            ..Default::default()
+        };
+        if let Err(e) = client.update_task(tonic::Request::new(update)).await {
+            return grpc_error_response(e);
+        }
+        let detail = format!(
+            "{} checklist item {}",
+            if input_payload.checked {
+                "checked"
+            } else {
+                "unchecked"
+            },
+            index + 1
+        );
+        notify_task_watchers(
+            &state,
+            task_id,
+            &id_and_username,
+            Event::Updated,
+            Some(&detail),
+        )
+        .await;
+        task = match client.get_task(tonic::Request::new(req)).await {
+            Ok(response) => response.into_inner(),
+            Err(e) => return grpc_error_response(e),
+        };
+    }
+
+    let mut resp = GetTaskResponse1::from(task);
+    fill_task_relations(&state.workflow, std::slice::from_mut(&mut resp));
+    if fill_task_links(&state, std::slice::from_mut(&mut resp))
+        .await
+        .is_err()
+    {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+    let etag = task_etag(resp.version);
+    (StatusCode::OK, [(header::ETAG, etag)], Json(resp)).into_response()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskRefRequest1 {
+    task_id: Option<i64>,
+    // Human-readable key like `OPS-42`.
+    key: Option<String>,
+}
+
+async fn get_task(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<TaskRefRequest1>,
+) -> Response {
+    let viewer = match optional_viewer(&state, headers).await {
+        Ok(viewer) => viewer,
+        Err(response) => return response,
+    };
+    let req = match (input_payload.task_id, input_payload.key) {
+        (Some(task_id), _) => proto::GetTaskRequest {
+            task_id,
+            viewer: Some(viewer),
+            ..Default::default()
+        },
+        (None, Some(key)) => {
+            let (project_key, project_number) = match parse_task_key(&key) {
+                Some(parsed) => parsed,
+                None => return (StatusCode::NOT_ACCEPTABLE, "Invalid task key").into_response(),
+            };
+            let project_id = match project_id_by_key(&state, project_key).await {
+                Ok(Some(project_id)) => project_id,
+                Ok(None) => {
+                    return (StatusCode::NOT_FOUND, "Project doesn't exist").into_response()
+                }
+                Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+            };
+            proto::GetTaskRequest {
+                task_id: 0,
+                project_id,
+                project_number,
+                viewer: Some(viewer),
+            }
+        }
+        (None, None) => {
+            return (StatusCode::NOT_ACCEPTABLE, "task_id or key is required").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+    let request = tonic::Request::new(req);
+    let response = match client.get_task(request).await {
+        Ok(response) => response,
+        Err(e) => return grpc_error_response(e),
+    };
+
+    let mut resp = GetTaskResponse1::from(response.into_inner());
+    fill_task_relations(&state.workflow, std::slice::from_mut(&mut resp));
+    if fill_task_links(&state, std::slice::from_mut(&mut resp))
+        .await
+        .is_err()
+    {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+    let etag = task_etag(resp.version);
+    (StatusCode::CREATED, [(header::ETAG, etag)], Json(resp)).into_response()
+}
+
+async fn send_list_tasks(state: &Arc<AppState>, req: proto::ListTasksRequest) -> Response {
+    let mut client = state.tasks_client.clone();
+    let request = tonic::Request::new(req);
+    let response = match client.list_tasks(request).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => {
+            println!("{}", e);
+            return grpc_error_response(e);
+        }
+    };
+
+    let mut tasks: Vec<GetTaskResponse1> = response
+        .tasks
+        .into_iter()
+        .map(GetTaskResponse1::from)
+        .collect();
+    fill_task_relations(&state.workflow, &mut tasks);
+    if fill_task_links(state, &mut tasks).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+
+    let resp = TaskPageResponse1 {
+        tasks,
+        next_cursor: Some(response.next_cursor).filter(|cursor| !cursor.is_empty()),
+        total_count: response.total_count,
+    };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+async fn list_tasks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<ListTasksRequest1>,
+) -> Response {
+    let viewer = match optional_viewer(&state, headers).await {
+        Ok(viewer) => viewer,
+        Err(response) => return response,
+    };
+    let req = match task_list_request(&state.workflow, input_payload.query) {
+        Ok(req) => req,
+        Err(e) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+    };
+    send_list_tasks(
+        &state,
+        proto::ListTasksRequest {
+            user_id: input_payload.user_id,
+            kind: proto::TaskListKind::CreatedByUser.into(),
+            viewer: Some(viewer),
+            ..req
+        },
+    )
+    .await
+}
+
+async fn my_tasks(
+    state: Arc<AppState>,
+    headers: HeaderMap,
+    input_payload: TaskListQuery1,
+    kind: proto::TaskListKind,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let req = match task_list_request(&state.workflow, input_payload) {
+        Ok(req) => req,
+        Err(e) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+    };
+    send_list_tasks(
+        &state,
+        proto::ListTasksRequest {
+            user_id: id_and_username.0,
+            kind: kind.into(),
+            ..req
+        },
+    )
+    .await
+}
+
+async fn assigned_to_me(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<TaskListQuery1>,
+) -> Response {
+    my_tasks(
+        state,
+        headers,
+        input_payload,
+        proto::TaskListKind::AssignedToUser,
+    )
+    .await
+}
+
+async fn created_by_me(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<TaskListQuery1>,
+) -> Response {
+    my_tasks(
+        state,
+        headers,
+        input_payload,
+        proto::TaskListKind::CreatedByUser,
+    )
+    .await
+}
+
+async fn unassigned_tasks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<TaskListQuery1>,
+) -> Response {
+    let viewer = match optional_viewer(&state, headers).await {
+        Ok(viewer) => viewer,
+        Err(response) => return response,
+    };
+    let req = match task_list_request(&state.workflow, input_payload) {
+        Ok(req) => req,
+        Err(e) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+    };
+    send_list_tasks(
+        &state,
+        proto::ListTasksRequest {
+            kind: proto::TaskListKind::Unassigned.into(),
+            viewer: Some(viewer),
+            ..req
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchRequest1 {
+    query: String,
+    author_id: Option<i64>,
+    assignee_id: Option<i64>,
+    status: Option<String>,
+    label_id: Option<i64>,
+    created_after: Option<String>,
+    created_before: Option<String>,
+    #[serde(default)]
+    offset: i64,
+    #[serde(default = "default_page_size")]
+    limit: i64,
+}
+
+fn default_page_size() -> i64 {
+    20
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchHitResponse1 {
+    task: GetTaskResponse1,
+    rank: f64,
+    // Snippets are HTML-escaped text with matches wrapped in <mark>.
+    title_snippet: String,
+    description_snippet: String,
+    comment_snippet: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResponse1 {
+    hits: Vec<SearchHitResponse1>,
+    total_count: i64,
+}
+
+async fn search_tasks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<SearchRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let tsquery = match search::to_tsquery(&input_payload.query) {
+        Some(tsquery) => tsquery,
+        None => {
+            return (
+                StatusCode::NOT_ACCEPTABLE,
+                "Query must contain at least one word to look for",
+            )
+                .into_response();
+        }
+    };
+    if let Err(e) = check_page(input_payload.offset, input_payload.limit) {
+        return (StatusCode::NOT_ACCEPTABLE, e).into_response();
+    }
+    let created_after = match input_payload
+        .created_after
+        .as_deref()
+        .map(|value| parse_timestamp(value, "created_after"))
+    {
+        Some(Ok(created_after)) => Some(created_after),
+        Some(Err(e)) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+        None => None,
+    };
+    let created_before = match input_payload
+        .created_before
+        .as_deref()
+        .map(|value| parse_timestamp(value, "created_before"))
+    {
+        Some(Ok(created_before)) => Some(created_before),
+        Some(Err(e)) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+        None => None,
+    };
+    let viewer = match viewer(&state, id_and_username.0).await {
+        Ok(viewer) => viewer,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::SearchTasksRequest {
+        tsquery,
+        author_id: input_payload.author_id,
+        assignee_id: input_payload.assignee_id,
+        status: input_payload.status,
+        label_id: input_payload.label_id,
+        created_after,
+        created_before,
+        visible_project_ids: viewer.project_ids.clone(),
+        offset: input_payload.offset,
+        limit: input_payload.limit,
+        viewer: Some(viewer),
+    };
+    let response = match client.search_tasks(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+
+    let mut tasks = vec![];
+    let mut snippets = vec![];
+    for hit in response.hits {
+        if let Some(task) = hit.task {
+            tasks.push(GetTaskResponse1::from(task));
+            snippets.push((
+                hit.rank,
+                hit.title_snippet,
+                hit.description_snippet,
+                hit.comment_snippet,
+            ));
+        }
+    }
+    fill_task_relations(&state.workflow, &mut tasks);
+    if fill_task_links(&state, &mut tasks).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+    let hits = tasks
+        .into_iter()
+        .zip(snippets)
+        .map(
+            |(task, (rank, title_snippet, description_snippet, comment_snippet))| {
+                SearchHitResponse1 {
+                    task,
+                    rank,
+                    title_snippet,
+                    description_snippet,
+                    comment_snippet,
+                }
+            },
+        )
+        .collect();
+
+    let resp = SearchResponse1 {
+        hits,
+        total_count: response.total_count,
+    };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssignTaskRequest1 {
+    task_id: i64,
+    assignee_id: i64,
+}
+
+async fn change_assignee(
+    state: &Arc<AppState>,
+    headers: HeaderMap,
+    task_id: i64,
+    assignee_id: Option<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let granted = match edit_granted(state, task_id, id_and_username.0).await {
+        Ok(granted) => granted,
+        Err(response) => return response,
+    };
+    let mut client = state.tasks_client.clone();
+    let req = proto::AssignTaskRequest {
+        user_id: id_and_username.0,
+        task_id,
+        assignee_id,
+        granted,
+    };
+    if let Err(e) = client.assign_task(tonic::Request::new(req)).await {
+        return grpc_error_response(e);
+    }
+    notify_task_watchers(state, task_id, &id_and_username, Event::Assigned, None).await;
+    (StatusCode::OK).into_response()
+}
+
+// Assigns the task or hands it over to another user.
+async fn assign_task(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<AssignTaskRequest1>,
+) -> Response {
+    if let Err(response) = check_assignee(&state, Some(input_payload.assignee_id)).await {
+        return response;
+    }
+    change_assignee(
+        &state,
+        headers,
+        input_payload.task_id,
+        Some(input_payload.assignee_id),
+    )
+    .await
+}
+
+async fn unassign_task(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<GetTaskRequest1>,
+) -> Response {
+    change_assignee(&state, headers, input_payload.task_id, None).await
+}
+
+fn grpc_status_code(code: tonic::Code) -> StatusCode {
+    match code {
+        tonic::Code::Ok => StatusCode::OK,
+        tonic::Code::NotFound => StatusCode::NOT_FOUND,
+        tonic::Code::PermissionDenied => StatusCode::FORBIDDEN,
+        tonic::Code::InvalidArgument => StatusCode::NOT_ACCEPTABLE,
+        tonic::Code::FailedPrecondition | tonic::Code::AlreadyExists => StatusCode::CONFLICT,
+        tonic::Code::ResourceExhausted => StatusCode::PAYLOAD_TOO_LARGE,
+        tonic::Code::Aborted => StatusCode::PRECONDITION_FAILED,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn grpc_error_response(status: tonic::Status) -> Response {
+    let code = grpc_status_code(status.code());
+    (code, status.message().to_string()).into_response()
+}
+
+const MAX_BULK_OPERATIONS: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BulkOperation1 {
+    Create(CreateTaskRequest1),
+    Update(UpdateTaskRequest1),
+    Delete(DeleteTaskRequest1),
+    Status(TransitionTaskRequest1),
+    Assign(BulkAssignRequest1),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkAssignRequest1 {
+    task_id: i64,
+    // `null` unassigns the task.
+    assignee_id: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkMode1 {
+    // Every operation that can succeed is applied.
+    #[default]
+    BestEffort,
+    // Either all operations are applied or none.
+    Atomic,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkTasksRequest1 {
+    #[serde(default)]
+    mode: BulkMode1,
+    operations: Vec<BulkOperation1>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkResultResponse1 {
+    index: usize,
+    status: u16,
+    // Id of the created task for creates, of the affected one otherwise.
+    task_id: Option<i64>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkTasksResponse1 {
+    committed: bool,
+    results: Vec<BulkResultResponse1>,
+}
+
+// Runs the same checks as the single-task endpoints and builds the operation for tasks_service.
+async fn prepare_bulk_operation(
+    state: &Arc<AppState>,
+    user_id: i64,
+    operation: BulkOperation1,
+    statuses: &mut HashMap<i64, String>,
+) -> Result<proto::bulk_operation::Op, Response> {
+    use proto::bulk_operation::Op;
+    match operation {
+        BulkOperation1::Create(create) => Ok(Op::Create(
+            create_task_request(state, user_id, create).await?,
+        )),
+        BulkOperation1::Update(update) => Ok(Op::Update(
+            update_task_request(state, user_id, update).await?,
+        )),
+        BulkOperation1::Delete(delete) => Ok(Op::Delete(proto::DeleteTaskRequest {
+            user_id,
+            task_id: delete.task_id,
+            granted: edit_granted(state, delete.task_id, user_id).await?,
+        })),
+        BulkOperation1::Status(transition) => {
+            let task = visible_task(state, transition.task_id, user_id).await?;
+            // An earlier operation of the batch may already move the task.
+            let from_status = statuses.get(&task.task_id).cloned().unwrap_or(task.status);
+            let roles = task_roles(task.author_id, task.assignee_id, user_id);
+            if let Err(e) =
+                state
+                    .workflow
+                    .check_transition(&from_status, &transition.status, &roles)
+            {
+                return Err(transition_error_response(e));
+            }
+            statuses.insert(task.task_id, transition.status.clone());
+            Ok(Op::Transition(proto::TransitionTaskRequest {
+                task_id: transition.task_id,
+                actor_id: user_id,
+                from_status,
+                to_status: transition.status,
+            }))
+        }
+        BulkOperation1::Assign(assign) => {
+            check_assignee(state, assign.assignee_id).await?;
+            Ok(Op::Assign(proto::AssignTaskRequest {
+                user_id,
+                task_id: assign.task_id,
+                assignee_id: assign.assignee_id,
+                granted: edit_granted(state, assign.task_id, user_id).await?,
+            }))
+        }
+    }
+}
+
+// The text of an error response built by the handlers' helpers.
+async fn response_message(response: Response) -> String {
+    match axum::body::to_bytes(response.into_body(), usize::MAX).await {
+        Ok(body) => String::from_utf8_lossy(&body).to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+async fn bulk_error(index: usize, task_id: Option<i64>, response: Response) -> BulkResultResponse1 {
+    BulkResultResponse1 {
+        index,
+        status: response.status().as_u16(),
+        task_id,
+        error: Some(response_message(response).await),
+    }
+}
+
+fn bulk_operation_task_id(operation: &BulkOperation1) -> Option<i64> {
+    match operation {
+        BulkOperation1::Create(_) => None,
+        BulkOperation1::Update(update) => Some(update.task_id),
+        BulkOperation1::Delete(delete) => Some(delete.task_id),
+        BulkOperation1::Status(transition) => Some(transition.task_id),
+        BulkOperation1::Assign(assign) => Some(assign.task_id),
+    }
+}
+
+// Tells the watchers about an applied operation of a batch, the way the single-task endpoints do.
+async fn notify_bulk_operation(
+    state: &Arc<AppState>,
+    actor: &(i64, String),
+    task_id: i64,
+    op: Option<proto::bulk_operation::Op>,
+    deleted_tasks: &HashMap<i64, proto::GetTaskResponse>,
+) {
+    use proto::bulk_operation::Op;
+    if let Some(op) = &op {
+        sync_operation_mentions(state, actor, task_id, op).await;
+    }
+    match op {
+        Some(Op::Create(create)) if create.assignee_id.is_some() => {
+            notify_task_watchers(state, task_id, actor, Event::Assigned, None).await
+        }
+        Some(Op::Update(update)) => {
+            notify_task_watchers(state, update.task_id, actor, Event::Updated, None).await;
+            if update.due_date.is_some() || update.clear_due_date {
+                reschedule_reminders(state, update.task_id).await;
+            }
+        }
+        Some(Op::Transition(transition)) => {
+            let detail = format!(
+                "from {} to {}",
+                transition.from_status, transition.to_status
+            );
+            notify_task_watchers(
+                state,
+                transition.task_id,
+                actor,
+                Event::StatusChanged,
+                Some(&detail),
+            )
+            .await
+        }
+        Some(Op::Assign(assign)) => {
+            notify_task_watchers(state, assign.task_id, actor, Event::Assigned, None).await
+        }
+        Some(Op::Delete(delete)) => {
+            if let Some(task) = deleted_tasks.get(&delete.task_id) {
+                notify_watchers(state, task, actor, Event::Deleted, None).await
+            }
+        }
+        _ => {}
+    }
+}
+
+// Applies many create/update/delete/status/assign operations in one request and one
+// transaction of tasks_service, and reports the outcome of each of them in request order.
+async fn bulk_tasks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<BulkTasksRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let count = input_payload.operations.len();
+    if count == 0 || count > MAX_BULK_OPERATIONS {
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            format!(
+                "A batch must have from 1 to {} operations",
+                MAX_BULK_OPERATIONS
+            ),
+        )
+            .into_response();
+    }
+    let atomic = matches!(input_payload.mode, BulkMode1::Atomic);
+
+    let mut client = state.tasks_client.clone();
+    let mut results: Vec<Option<BulkResultResponse1>> = vec![];
+    let mut task_ids = vec![];
+    let mut operations = vec![];
+    let mut statuses = HashMap::new();
+    for (index, operation) in input_payload.operations.into_iter().enumerate() {
+        let task_id = bulk_operation_task_id(&operation);
+        task_ids.push(task_id);
+        let prepared =
+            prepare_bulk_operation(&state, id_and_username.0, operation, &mut statuses).await;
+        match prepared {
+            Ok(op) => {
+                operations.push((index, proto::BulkOperation { op: Some(op) }));
+                results.push(None);
+            }
+            Err(response) => results.push(Some(bulk_error(index, task_id, response).await)),
+        }
+    }
+
+    let failed_early = results.iter().any(|result| result.is_some());
+    let mut committed = false;
+    // An atomic batch with an invalid operation is not sent at all.
+    let skip = operations.is_empty() || (atomic && failed_early);
+    if !skip {
+        // The watchers of a deleted task are only known while it is still there.
+        let mut deleted_tasks = HashMap::new();
+        for (_, operation) in &operations {
+            if let Some(proto::bulk_operation::Op::Delete(delete)) = &operation.op {
+                let req = proto::GetTaskRequest {
+                    task_id: delete.task_id,
+                    ..Default::default()
+                };
+                if let Ok(response) = client.get_task(tonic::Request::new(req)).await {
+                    deleted_tasks.insert(delete.task_id, response.into_inner());
+                }
+            }
+        }
+        let req = proto::BulkTasksRequest {
+            operations: operations.iter().map(|(_, op)| op.clone()).collect(),
+            atomic,
+        };
+        let response = match client.bulk_tasks(tonic::Request::new(req)).await {
+            Ok(response) => response.into_inner(),
+            Err(e) => return grpc_error_response(e),
+        };
+        committed = response.committed;
+        let mut events = vec![];
+        for ((index, operation), result) in operations.iter().zip(response.results) {
+            let code = grpc_status_code(tonic::Code::from_i32(result.code));
+            if code == StatusCode::OK {
+                events.push((result.task_id, operation.op.clone()));
+            }
+            let (status, task_id) = match operation.op {
+                Some(proto::bulk_operation::Op::Create(_)) if code == StatusCode::OK => {
+                    (StatusCode::CREATED, Some(result.task_id))
+                }
+                _ => (code, task_ids[*index]),
+            };
+            results[*index] = Some(BulkResultResponse1 {
+                index: *index,
+                status: status.as_u16(),
+                task_id,
+                error: (!result.message.is_empty()).then_some(result.message),
+            });
+        }
+        if committed {
+            for (task_id, op) in events {
+                notify_bulk_operation(&state, &id_and_username, task_id, op, &deleted_tasks).await;
+            }
+        }
+    }
+
+    // In a rolled back batch even the operations that went through have no effect.
+    let results = results
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| match result {
+            Some(result) if committed || result.status >= 400 => result,
+            _ => BulkResultResponse1 {
+                index,
+                status: StatusCode::FAILED_DEPENDENCY.as_u16(),
+                task_id: task_ids[index],
+                error: Some("Not applied because another operation failed".to_string()),
+            },
+        })
+        .collect();
+    let resp = BulkTasksResponse1 { committed, results };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+const MAX_EXPORT_TASKS: usize = 10000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportTasksRequest1 {
+    // csv, json, markdown or ical.
+    format: String,
+    // Tasks of the project; without it, the tasks created by the user.
+    project_id: Option<i64>,
+}
+
+async fn export_tasks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<ExportTasksRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let (content_type, extension) = match input_payload.format.as_str() {
+        "csv" => ("text/csv; charset=utf-8", "csv"),
+        "json" => ("application/json", "json"),
+        "markdown" => ("text/markdown; charset=utf-8", "md"),
+        "ical" => ("text/calendar; charset=utf-8", "ics"),
+        _ => {
+            return (
+                StatusCode::NOT_ACCEPTABLE,
+                "Format must be one of: csv, json, markdown, ical",
+            )
+                .into_response();
+        }
+    };
+    let kind = match input_payload.project_id {
+        Some(project_id) => {
+            if let Err(response) =
+                check_project_access(&state, project_id, id_and_username.0, false).await
+            {
+                return response;
+            }
+            proto::TaskListKind::InProject
+        }
+        None => proto::TaskListKind::CreatedByUser,
+    };
+    let viewer = match viewer(&state, id_and_username.0).await {
+        Ok(viewer) => viewer,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+
+    let mut client = state.tasks_client.clone();
+    let mut tasks: Vec<GetTaskResponse1> = vec![];
+    let mut cursor = String::new();
+    loop {
+        let req = proto::ListTasksRequest {
+            user_id: id_and_username.0,
+            kind: kind.into(),
+            project_id: input_payload.project_id.unwrap_or_default(),
+            limit: MAX_PAGE_SIZE,
+            cursor,
+            viewer: Some(viewer.clone()),
+            ..Default::default()
+        };
+        let response = match client.list_tasks(tonic::Request::new(req)).await {
+            Ok(response) => response.into_inner(),
+            Err(e) => return grpc_error_response(e),
+        };
+        tasks.extend(response.tasks.into_iter().map(GetTaskResponse1::from));
+        if tasks.len() > MAX_EXPORT_TASKS {
+            return (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("At most {} tasks can be exported", MAX_EXPORT_TASKS),
+            )
+                .into_response();
+        }
+        if response.next_cursor.is_empty() {
+            break;
+        }
+        cursor = response.next_cursor;
+    }
+    fill_task_relations(&state.workflow, &mut tasks);
+    if fill_task_links(&state, &mut tasks).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+
+    let body = match extension {
+        "csv" => match export::csv(&tasks) {
+            Ok(body) => body,
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+        },
+        "json" => match serde_json::to_string_pretty(&tasks) {
+            Ok(body) => body,
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+        },
+        "md" => export::markdown(&tasks, &state.workflow),
+        _ => export::ical(&tasks, &state.workflow),
+    };
+    let headers = [
+        (header::CONTENT_TYPE, content_type.to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"tasks.{}\"", extension),
+        ),
+    ];
+    (StatusCode::OK, headers, body).into_response()
+}
+
+const MAX_IMPORT_ROWS: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportTasksRequest1 {
+    // csv or json.
+    format: String,
+    // The CSV text, or an array of objects for JSON.
+    data: serde_json::Value,
+    // Task field -> column (CSV) or key (JSON).
+    #[serde(default)]
+    mapping: HashMap<String, String>,
+    project_id: Option<i64>,
+    // Validate and report what would happen without changing anything.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportRowResponse1 {
+    row: usize,
+    external_id: Option<String>,
+    // create, update, unchanged or error.
+    action: String,
+    task_id: Option<i64>,
+    errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportTasksResponse1 {
+    dry_run: bool,
+    created: usize,
+    updated: usize,
+    unchanged: usize,
+    failed: usize,
+    rows: Vec<ImportRowResponse1>,
+}
+
+// Whether the imported due date, in any form `parse_due_date` takes, is the current one.
+fn same_time(current: Option<&str>, new: &str) -> bool {
+    let parse = |value: &str| chrono::DateTime::parse_from_rfc3339(value).ok();
+    let new = parse_due_date(new).ok();
+    current.and_then(parse).is_some() && current.and_then(parse) == new.as_deref().and_then(parse)
+}
+
+// Builds the update bringing an imported task in line with the record; `None` if nothing
+// changed. The status of existing tasks is left to the workflow and checked beforehand.
+fn import_update(
+    task: &proto::Task,
+    values: &HashMap<&str, String>,
+    due_date: Option<String>,
+    estimate_minutes: Option<i64>,
+) -> Option<UpdateTaskRequest1> {
+    let title = values
+        .get("title")
+        .map(|title| title.trim().to_string())
+        .filter(|title| *title != task.title);
+    let description = values
+        .get("description")
+        .filter(|description| **description != task.text)
+        .cloned();
+    let priority = values
+        .get("priority")
+        .filter(|priority| **priority != task.priority)
+        .cloned();
+    let due_date = due_date.filter(|due_date| !same_time(task.due_date.as_deref(), due_date));
+    let estimate_minutes = estimate_minutes.filter(|e| Some(*e) != task.estimate_minutes);
+    if title.is_none()
+        && description.is_none()
+        && priority.is_none()
+        && due_date.is_none()
+        && estimate_minutes.is_none()
+    {
+        return None;
+    }
+    Some(UpdateTaskRequest1 {
+        task_id: task.task_id,
+        new_text: None,
+        title,
+        description,
+        status: None,
+        priority,
+        due_date,
+        assignee_id: None,
+        clear_due_date: false,
+        clear_assignee: false,
+        parent_id: None,
+        estimate_minutes,
+        clear_parent: false,
+        clear_estimate: false,
+        visibility: None,
+        // The changes were worked out against this version of the task.
+        expected_version: Some(task.version),
+    })
+}
+
+// Imports tasks from CSV or JSON. Rows are validated like single creates and updates; the
+// valid ones are applied and the rest are reported. Rows with an external id are matched to
+// the tasks imported before, so running the same import again changes nothing.
+async fn import_tasks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<ImportTasksRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let user_id = id_and_username.0;
+    if let Err(e) = import::check_mapping(&input_payload.mapping) {
+        return (StatusCode::NOT_ACCEPTABLE, e).into_response();
+    }
+    let records = match (input_payload.format.as_str(), &input_payload.data) {
+        ("csv", serde_json::Value::String(data)) => {
+            import::csv_records(data, &input_payload.mapping)
+        }
+        ("csv", _) => Err("CSV data must be a string".to_string()),
+        ("json", data) => import::json_records(data, &input_payload.mapping),
+        _ => Err("Format must be csv or json".to_string()),
+    };
+    let records = match records {
+        Ok(records) => records,
+        Err(e) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+    };
+    if records.len() > MAX_IMPORT_ROWS {
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("At most {} rows can be imported at once", MAX_IMPORT_ROWS),
+        )
+            .into_response();
+    }
+    if let Some(project_id) = input_payload.project_id {
+        if let Err(response) = check_project_access(&state, project_id, user_id, true).await {
+            return response;
+        }
+    }
+
+    let mut client = state.tasks_client.clone();
+    let external_ids: HashSet<String> = records
+        .iter()
+        .filter_map(|record| record.values.get("external_id").cloned())
+        .collect();
+    let req = proto::ExternalIdsRequest {
+        author_id: user_id,
+        external_ids: external_ids.into_iter().collect(),
+    };
+    let existing: HashMap<String, proto::Task> = match client
+        .find_tasks_by_external_ids(tonic::Request::new(req))
+        .await
+    {
+        Ok(response) => response
+            .into_inner()
+            .tasks
+            .into_iter()
+            .filter_map(|task| Some((task.external_id.clone()?, task)))
+            .collect(),
+        Err(e) => return grpc_error_response(e),
+    };
+
+    let mut rows = vec![];
+    let mut operations = vec![];
+    let mut seen = HashSet::new();
+    for mut record in records {
+        let external_id = record.values.get("external_id").cloned();
+        let mut row = ImportRowResponse1 {
+            row: record.row,
+            external_id: external_id.clone(),
+            action: "error".to_string(),
+            task_id: None,
+            errors: std::mem::take(&mut record.errors),
+        };
+        let estimate_minutes = match record.values.get("estimate_minutes").map(|v| v.parse()) {
+            Some(Ok(estimate)) => Some(estimate),
+            Some(Err(_)) => {
+                row.errors
+                    .push("estimate_minutes must be an integer".to_string());
+                None
+            }
+            None => None,
+        };
+        if let Some(external_id) = &external_id {
+            if !seen.insert(external_id.clone()) {
+                row.errors
+                    .push("The external id repeats an earlier row".to_string());
+            }
+        }
+        if !row.errors.is_empty() {
+            rows.push(row);
+            continue;
+        }
+
+        let task = external_id.as_ref().and_then(|id| existing.get(id));
+        let prepared = match task {
+            Some(task) if task.deleted_at.is_some() => {
+                Err("The task with this external id is in the trash".to_string())
+            }
+            Some(task)
+                if record
+                    .values
+                    .get("status")
+                    .is_some_and(|status| *status != task.status) =>
+            {
+                row.task_id = Some(task.task_id);
+                Err(
+                    "The status of an existing task can only be changed by a transition"
+                        .to_string(),
+                )
+            }
+            Some(task) => {
+                row.task_id = Some(task.task_id);
+                let due_date = record.values.get("due_date").cloned();
+                match import_update(task, &record.values, due_date, estimate_minutes) {
+                    None => {
+                        row.action = "unchanged".to_string();
+                        rows.push(row);
+                        continue;
+                    }
+                    Some(update) => match update_task_request(&state, user_id, update).await {
+                        Ok(req) => Ok(("update", proto::bulk_operation::Op::Update(req))),
+                        Err(response) => Err(response_message(response).await),
+                    },
+                }
+            }
+            None => {
+                let create = CreateTaskRequest1 {
+                    text: None,
+                    title: record.values.get("title").cloned(),
+                    description: record.values.get("description").cloned(),
+                    status: record.values.get("status").cloned(),
+                    priority: record.values.get("priority").cloned(),
+                    due_date: record.values.get("due_date").cloned(),
+                    assignee_id: None,
+                    project_id: input_payload.project_id,
+                    parent_id: None,
+                    estimate_minutes,
+                    visibility: None,
+                    template_id: None,
+                    variables: HashMap::new(),
+                };
+                match create_task_request(&state, user_id, create).await {
+                    Ok(req) => Ok((
+                        "create",
+                        proto::bulk_operation::Op::Create(proto::CreateTaskRequest {
+                            external_id,
+                            ..req
+                        }),
+                    )),
+                    Err(response) => Err(response_message(response).await),
+                }
+            }
+        };
+        match prepared {
+            Ok((action, op)) => {
+                row.action = action.to_string();
+                operations.push((rows.len(), proto::BulkOperation { op: Some(op) }));
+            }
+            Err(e) => row.errors.push(e),
+        }
+        rows.push(row);
+    }
+
+    if !input_payload.dry_run {
+        for chunk in operations.chunks(MAX_BULK_OPERATIONS) {
+            let req = proto::BulkTasksRequest {
+                operations: chunk.iter().map(|(_, op)| op.clone()).collect(),
+                atomic: false,
+            };
+            let response = match client.bulk_tasks(tonic::Request::new(req)).await {
+                Ok(response) => response.into_inner(),
+                Err(e) => return grpc_error_response(e),
+            };
+            for ((index, operation), result) in chunk.iter().zip(response.results) {
+                let row = &mut rows[*index];
+                if result.code != tonic::Code::Ok as i32 {
+                    row.action = "error".to_string();
+                    row.errors.push(result.message);
+                    continue;
+                }
+                if row.action == "create" {
+                    row.task_id = Some(result.task_id);
+                }
+                if let Some(op) = &operation.op {
+                    sync_operation_mentions(&state, &id_and_username, result.task_id, op).await;
+                }
+                if let Some(proto::bulk_operation::Op::Update(update)) = &operation.op {
+                    let task_id = update.task_id;
+                    notify_task_watchers(&state, task_id, &id_and_username, Event::Updated, None)
+                        .await;
+                    if update.due_date.is_some() || update.clear_due_date {
+                        reschedule_reminders(&state, task_id).await;
+                    }
+                }
+            }
+        }
+    }
+
+    let count = |action: &str| rows.iter().filter(|row| row.action == action).count();
+    let resp = ImportTasksResponse1 {
+        dry_run: input_payload.dry_run,
+        created: count("create"),
+        updated: count("update"),
+        unchanged: count("unchanged"),
+        failed: count("error"),
+        rows,
+    };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransitionTaskRequest1 {
+    task_id: i64,
+    status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskTransitionResponse1 {
+    task_id: i64,
+    actor_id: i64,
+    from_status: String,
+    to_status: String,
+    created_at: String,
+}
+
+impl From<proto::TaskTransition> for TaskTransitionResponse1 {
+    fn from(transition: proto::TaskTransition) -> Self {
+        TaskTransitionResponse1 {
+            task_id: transition.task_id,
+            actor_id: transition.actor_id,
+            from_status: transition.from_status,
+            to_status: transition.to_status,
+            created_at: transition.created_at,
+        }
+    }
+}
+
+fn task_roles(author_id: i64, assignee_id: Option<i64>, user_id: i64) -> Vec<Role> {
+    let mut roles = vec![];
+    if author_id == user_id {
+        roles.push(Role::Author);
+    }
+    if assignee_id == Some(user_id) {
+        roles.push(Role::Assignee);
+    }
+    roles
+}
+
+fn transition_error_response(e: TransitionError) -> Response {
+    let code = match e {
+        TransitionError::UnknownStatus(_) => StatusCode::NOT_ACCEPTABLE,
+        TransitionError::NotAllowed { .. } => StatusCode::CONFLICT,
+        TransitionError::Forbidden { .. } => StatusCode::FORBIDDEN,
+    };
+    (code, e.to_string()).into_response()
+}
+
+async fn transition_task(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<TransitionTaskRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    // Hidden tasks look missing, whatever the workflow would allow on them.
+    let task = match visible_task(&state, input_payload.task_id, id_and_username.0).await {
+        Ok(task) => task,
+        Err(response) => return response,
+    };
+    let mut client = state.tasks_client.clone();
+
+    let roles = task_roles(task.author_id, task.assignee_id, id_and_username.0);
+    let from_status = task.status.clone();
+    let to_status = input_payload.status;
+    if let Err(e) = state
+        .workflow
+        .check_transition(&from_status, &to_status, &roles)
+    {
+        return transition_error_response(e);
+    }
+
+    let completed = state.workflow.is_done(&to_status);
+    let detail = format!("from {} to {}", from_status, to_status);
+    let req = proto::TransitionTaskRequest {
+        task_id: input_payload.task_id,
+        actor_id: id_and_username.0,
+        from_status,
+        to_status,
+    };
+    let response = match client.transition_task(tonic::Request::new(req)).await {
+        Ok(response) => response,
+        Err(e) => return grpc_error_response(e),
+    };
+    notify_watchers(
+        &state,
+        &task,
+        &id_and_username,
+        Event::StatusChanged,
+        Some(&detail),
+    )
+    .await;
+    // Completing an occurrence of a recurring task brings the next one forward instead of
+    // leaving it to the scheduler.
+    if let (Some(series_id), true) = (task.series_id, completed) {
+        if let Err(e) = recurrence::advance_due(&mut client, &state.workflow, Some(series_id)).await
+        {
+            println!("Failed to advance series {}: {}", series_id, e);
+        }
+    }
+
+    let resp = TaskTransitionResponse1::from(response.into_inner());
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+async fn task_transitions(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<GetTaskRequest1>,
+) -> Response {
+    if let Err(response) = check_task_readable(&state, headers, input_payload.task_id).await {
+        return response;
+    }
+    let mut client = state.tasks_client.clone();
+    let req = proto::ListTaskTransitionsRequest {
+        task_id: input_payload.task_id,
+    };
+    let response = match client.list_task_transitions(tonic::Request::new(req)).await {
+        Ok(response) => response,
+        Err(e) => return grpc_error_response(e),
+    };
+
+    let transitions: Vec<TaskTransitionResponse1> = response
+        .into_inner()
+        .transitions
+        .into_iter()
+        .map(TaskTransitionResponse1::from)
+        .collect();
+    (StatusCode::OK, Json(transitions)).into_response()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevisionResponse1 {
+    task_id: i64,
+    revision_number: i64,
+    actor_id: i64,
+    created_at: String,
+    changed_fields: Vec<String>,
+    text: String,
+    title: String,
+    status: String,
+    priority: String,
+    due_date: Option<String>,
+    assignee_id: Option<i64>,
+    parent_id: Option<i64>,
+    estimate_minutes: Option<i64>,
+    visibility: String,
+}
+
+impl From<proto::Revision> for RevisionResponse1 {
+    fn from(revision: proto::Revision) -> Self {
+        RevisionResponse1 {
+            task_id: revision.task_id,
+            revision_number: revision.revision_number,
+            actor_id: revision.actor_id,
+            created_at: revision.created_at,
+            changed_fields: revision.changed_fields,
+            text: revision.text,
+            title: revision.title,
+            status: revision.status,
+            priority: revision.priority,
+            due_date: revision.due_date,
+            assignee_id: revision.assignee_id,
+            parent_id: revision.parent_id,
+            estimate_minutes: revision.estimate_minutes,
+            visibility: revision.visibility,
+        }
+    }
+}
+
+impl RevisionResponse1 {
+    // Tracked fields in a fixed order; the first two are free text.
+    fn fields(&self) -> [(&'static str, serde_json::Value); 9] {
+        [
+            ("text", self.text.clone().into()),
+            ("title", self.title.clone().into()),
+            ("status", self.status.clone().into()),
+            ("priority", self.priority.clone().into()),
+            ("due_date", self.due_date.clone().into()),
+            ("assignee_id", self.assignee_id.into()),
+            ("parent_id", self.parent_id.into()),
+            ("estimate_minutes", self.estimate_minutes.into()),
+            ("visibility", self.visibility.clone().into()),
+        ]
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevisionDiffRequest1 {
+    task_id: i64,
+    from_revision: i64,
+    to_revision: i64,
+    // `unified` (default) or `words`.
+    mode: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldChangeResponse1 {
+    field: String,
+    old: serde_json::Value,
+    new: serde_json::Value,
+    // Only for text fields.
+    diff: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevisionDiffResponse1 {
+    task_id: i64,
+    from_revision: i64,
+    to_revision: i64,
+    changes: Vec<FieldChangeResponse1>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestoreRevisionRequest1 {
+    task_id: i64,
+    revision_number: i64,
+}
+
+async fn task_revisions(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<GetTaskRequest1>,
+) -> Response {
+    if let Err(response) = check_task_readable(&state, headers, input_payload.task_id).await {
+        return response;
+    }
+    let mut client = state.tasks_client.clone();
+    let req = proto::ListRevisionsRequest {
+        task_id: input_payload.task_id,
+    };
+    let response = match client.list_revisions(tonic::Request::new(req)).await {
+        Ok(response) => response,
+        Err(e) => return grpc_error_response(e),
+    };
+
+    let revisions: Vec<RevisionResponse1> = response
+        .into_inner()
+        .revisions
+        .into_iter()
+        .map(RevisionResponse1::from)
+        .collect();
+    (StatusCode::OK, Json(revisions)).into_response()
+}
+
+async fn get_revision(
+    client: &mut TaskServiceClient<tonic::transport::Channel>,
+    task_id: i64,
+    revision_number: i64,
+) -> Result<RevisionResponse1, Response> {
+    let req = proto::RevisionRequest {
+        task_id,
+        revision_number,
+        ..Default::default()
+    };
+    match client.get_revision(tonic::Request::new(req)).await {
+        Ok(response) => Ok(RevisionResponse1::from(response.into_inner())),
+        Err(e) => Err(grpc_error_response(e)),
+    }
+}
+
+async fn revision_diff(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<RevisionDiffRequest1>,
+) -> Response {
+    let word_diff = match input_payload.mode.as_deref().unwrap_or("unified") {
+        "unified" => false,
+        "words" => true,
+        _ => {
+            return (StatusCode::NOT_ACCEPTABLE, "Mode must be unified or words").into_response();
+        }
+    };
+
+    if let Err(response) = check_task_readable(&state, headers, input_payload.task_id).await {
+        return response;
+    }
+    let mut client = state.tasks_client.clone();
+    let task_id = input_payload.task_id;
+    let from = match get_revision(&mut client, task_id, input_payload.from_revision).await {
+        Ok(revision) => revision,
+        Err(response) => return response,
+    };
+    let to = match get_revision(&mut client, task_id, input_payload.to_revision).await {
+        Ok(revision) => revision,
+        Err(response) => return response,
+    };
+
+    let mut changes = vec![];
+    for ((field, old), (_, new)) in from.fields().into_iter().zip(to.fields()) {
+        if old == new {
+            continue;
+        }
+        let diff = match (&old, &new) {
+            (serde_json::Value::String(old_text), serde_json::Value::String(new_text))
+                if field == "text" || field == "title" =>
+            {
+                Some(if word_diff {
+                    diff::words(old_text, new_text)
+                } else {
+                    diff::unified(
+                        old_text,
+                        new_text,
+                        &format!("revision {}", from.revision_number),
+                        &format!("revision {}", to.revision_number),
+                    )
+                })
+            }
+            _ => None,
+        };
+        changes.push(FieldChangeResponse1 {
+            field: field.to_string(),
+            old,
+            new,
+            diff,
+        });
+    }
+
+    let resp = RevisionDiffResponse1 {
+        task_id,
+        from_revision: from.revision_number,
+        to_revision: to.revision_number,
+        changes,
+    };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+// Brings the content back as a new revision; status and parent stay as they are.
+async fn restore_revision(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<RestoreRevisionRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let granted = match edit_granted(&state, input_payload.task_id, id_and_username.0).await {
+        Ok(granted) => granted,
+        Err(response) => return response,
+    };
+    let mut client = state.tasks_client.clone();
+    let req = proto::RevisionRequest {
+        user_id: id_and_username.0,
+        task_id: input_payload.task_id,
+        revision_number: input_payload.revision_number,
+        granted,
+    };
+    let response = match client.restore_revision(tonic::Request::new(req)).await {
+        Ok(response) => response,
+        Err(e) => return grpc_error_response(e),
+    };
+    let revision = response.into_inner();
+    // The restored revision may bring another due date back.
+    reschedule_reminders(&state, revision.task_id).await;
+    let detail = format!("restored revision {}", input_payload.revision_number);
+    notify_task_watchers(
+        &state,
+        input_payload.task_id,
+        &id_and_username,
+        Event::Updated,
+        Some(&detail),
+    )
+    .await;
+    sync_mentions(
+        &state,
+        mentions::Source::Task,
+        revision.task_id,
+        revision.task_id,
+        &revision.text,
+        &id_and_username,
+    )
+    .await;
+
+    let resp = RevisionResponse1::from(revision);
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashedTaskResponse1 {
+    #[serde(flatten)]
+    task: GetTaskResponse1,
+    deleted_at: String,
+    // After this moment the task can't be restored and is purged.
+    purge_at: String,
+}
+
+async fn list_trash(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::ListTrashRequest {
+        user_id: id_and_username.0,
+    };
+    let response = match client.list_trash(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+
+    let deleted_at: Vec<String> = response
+        .tasks
+        .iter()
+        .map(|task| task.deleted_at.clone().unwrap_or_default())
+        .collect();
+    let mut tasks: Vec<GetTaskResponse1> = response
+        .tasks
+        .into_iter()
+        .map(GetTaskResponse1::from)
+        .collect();
+    if fill_task_links(&state, &mut tasks).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+    let retention =
+        chrono::Duration::from_std(state.trash.retention).unwrap_or(chrono::Duration::MAX);
+    let mut resp = vec![];
+    for (task, deleted_at) in tasks.into_iter().zip(deleted_at) {
+        let purge_at = match chrono::DateTime::parse_from_rfc3339(&deleted_at) {
+            Ok(time) => (time + retention).to_rfc3339(),
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+        };
+        resp.push(TrashedTaskResponse1 {
+            task,
+            deleted_at,
+            purge_at,
+        });
+    }
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+async fn restore_task(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<DeleteTaskRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::RestoreTaskRequest {
+        user_id: id_and_username.0,
+        task_id: input_payload.task_id,
+        retention_seconds: state.trash.retention.as_secs() as i64,
+    };
+    match client.restore_task(tonic::Request::new(req)).await {
+        Ok(_) => {}
+        Err(e) => return grpc_error_response(e),
+    };
+    notify_task_watchers(
+        &state,
+        input_payload.task_id,
+        &id_and_username,
+        Event::Restored,
+        None,
+    )
+    .await;
+    (StatusCode::OK).into_response()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttachmentResponse1 {
+    attachment_id: i64,
+    task_id: i64,
+    uploader_id: i64,
+    filename: String,
+    content_type: String,
+    size: i64,
+    sha256: String,
+    created_at: String,
+}
+
+impl From<proto::Attachment> for AttachmentResponse1 {
+    fn from(attachment: proto::Attachment) -> Self {
+        AttachmentResponse1 {
+            attachment_id: attachment.attachment_id,
+            task_id: attachment.task_id,
+            uploader_id: attachment.uploader_id,
+            filename: attachment.filename,
+            content_type: attachment.content_type,
+            size: attachment.size,
+            sha256: attachment.sha256,
+            created_at: attachment.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadAttachmentParams1 {
+    filename: String,
+}
+
+// The body is the raw file; it is streamed to a temporary file and from there to the storage.
+async fn upload_attachment(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(task_id): Path<i64>,
+    Query(params): Query<UploadAttachmentParams1>,
+    body: Body,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let filename = match attachments::clean_filename(&params.filename) {
+        Some(filename) => filename,
+        None => return (StatusCode::NOT_ACCEPTABLE, "Invalid filename").into_response(),
+    };
+
+    let viewer = match viewer(&state, id_and_username.0).await {
+        Ok(viewer) => viewer,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+    let mut client = state.tasks_client.clone();
+    let req = proto::GetTaskRequest {
+        task_id,
+        viewer: Some(viewer),
+        ..Default::default()
+    };
+    let task = match client.get_task(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+
+    // Cut the upload off early once it can't fit; tasks_service checks the quota again for
+    // concurrent uploads.
+    let limits = state.attachment_limits;
+    let used: i64 = task.attachments.iter().map(|a| a.size).sum();
+    let remaining = limits.max_task_bytes.saturating_sub(used as u64);
+    let max_bytes = limits.max_file_bytes.min(remaining);
+    let upload = match attachments::receive(body, &std::env::temp_dir(), max_bytes).await {
+        Ok(upload) => upload,
+        Err(attachments::UploadError::TooLarge) => {
+            let message = if max_bytes == limits.max_file_bytes {
+                format!("A file can't be larger than {} bytes", max_bytes)
+            } else {
+                format!(
+                    "Attachments of a task can't take more than {} bytes",
+                    limits.max_task_bytes
+                )
+            };
+            return (StatusCode::PAYLOAD_TOO_LARGE, message).into_response();
+        }
+        Err(attachments::UploadError::Io(e)) => {
+            println!("{}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+        }
+    };
+
+    // The blob is stored before the attachment exists, so that no attachment ever points to
+    // a missing blob. If the attachment is then refused, the blob stays: a concurrent upload
+    // of the same contents may already use it.
+    let stored = store_blob(state.storage.as_ref(), &upload).await;
+    let _ = tokio::fs::remove_file(&upload.path).await;
+    if let Err(e) = stored {
+        println!("{}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+    let req = proto::CreateAttachmentRequest {
+        user_id: id_and_username.0,
+        task_id,
+        filename,
+        content_type: upload.content_type.to_string(),
+        size: upload.size as i64,
+        sha256: upload.sha256.clone(),
+        max_task_bytes: limits.max_task_bytes as i64,
+    };
+    let result = client
+        .create_attachment(tonic::Request::new(req))
+        .await
+        .map(|response| response.into_inner())
+        .map_err(grpc_error_response);
+    match result {
+        Ok(attachment) => (
+            StatusCode::CREATED,
+            Json(AttachmentResponse1::from(attachment)),
+        )
+            .into_response(),
+        Err(response) => response,
+    }
+}
+
+// Identical contents are stored once, whatever task they are attached to.
+async fn store_blob(
+    storage: &dyn BlobStorage,
+    upload: &attachments::Upload,
+) -> std::io::Result<()> {
+    if storage.exists(&upload.sha256).await? {
+        return Ok(());
+    }
+    storage.put(&upload.sha256, &upload.path, upload.size).await
+}
+
+// Anyone who can read the task can download its attachments.
+async fn download_attachment(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(attachment_id): Path<i64>,
+) -> Response {
+    let mut client = state.tasks_client.clone();
+    let req = proto::AttachmentRequest {
+        attachment_id,
+        ..Default::default()
+    };
+    let attachment = match client.get_attachment(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+    if let Err(response) = check_task_readable(&state, headers, attachment.task_id).await {
+        return response;
+    }
+    let stream = match state.storage.get(&attachment.sha256).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            println!("{}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+        }
+    };
+
+    let headers = [
+        (header::CONTENT_TYPE, attachment.content_type),
+        (header::CONTENT_LENGTH, attachment.size.to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            attachments::content_disposition(&attachment.filename),
+        ),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+    ];
+    (StatusCode::OK, headers, Body::from_stream(stream)).into_response()
+}
+
+async fn delete_attachment(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(attachment_id): Path<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::AttachmentRequest {
+        user_id: id_and_username.0,
+        attachment_id,
+    };
+    let response = match client.delete_attachment(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+    if response.orphaned {
+        if let Err(e) = state.storage.delete(&response.sha256).await {
+            println!("Failed to delete blob {}: {}", response.sha256, e);
+        }
+    }
+    (StatusCode::OK).into_response()
+}
+
+const MAX_RRULE_LENGTH: usize = 500;
+const UPCOMING_OCCURRENCES: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateSeriesRequest1 {
+    // Fields of every occurrence; the status only applies to the first one.
+    #[serde(flatten)]
+    task: CreateTaskRequest1,
+    rrule: String,
+    // IANA name of the timezone the rule is expanded in; UTC by default.
+    timezone: Option<String>,
+    // The DTSTART of the rule, which also gives the time of day of all occurrences.
+    start: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateSeriesResponse1 {
+    series_id: i64,
+    task_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateSeriesRequest1 {
+    title: Option<String>,
+    description: Option<String>,
+    priority: Option<String>,
+    assignee_id: Option<i64>,
+    #[serde(default)]
+    clear_assignee: bool,
+    estimate_minutes: Option<i64>,
+    #[serde(default)]
+    clear_estimate: bool,
+    rrule: Option<String>,
+    timezone: Option<String>,
+    // Without it, a new timezone keeps the wall-clock time of the current start.
+    start: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeriesResponse1 {
+    series_id: i64,
+    author_id: i64,
+    rrule: String,
+    timezone: String,
+    start: String,
+    title: String,
+    description: String,
+    priority: String,
+    assignee_id: Option<i64>,
+    project_id: Option<i64>,
+    estimate_minutes: Option<i64>,
+    // Start of the next occurrence to be created; null once the series has ended.
+    next_at: Option<String>,
+    // The starts from `next_at` on.
+    upcoming: Vec<String>,
+    occurrence_ids: Vec<i64>,
+    created_at: String,
+    updated_at: String,
+}
+
+impl From<proto::TaskSeries> for SeriesResponse1 {
+    fn from(series: proto::TaskSeries) -> Self {
+        // Times are shown in the timezone of the series.
+        let tz = rrule::parse_timezone(&series.timezone).unwrap_or(Tz::UTC);
+        let parse = |value: &str| {
+            chrono::DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|time| time.with_timezone(&tz))
+        };
+        let local = |value: &str| parse(value).map_or(value.to_string(), |time| time.to_rfc3339());
+        let upcoming = match (
+            series.rrule.parse::<Rule>(),
+            parse(&series.dtstart),
+            series.next_at.as_deref().and_then(parse),
+        ) {
+            (Ok(rule), Some(start), Some(next_at)) => rule
+                .occurrences(start)
+                .skip_while(|time| *time < next_at)
+                .take(UPCOMING_OCCURRENCES)
+                .map(|time| time.with_timezone(&tz).to_rfc3339())
+                .collect(),
+            _ => vec![],
+        };
+        SeriesResponse1 {
+            series_id: series.series_id,
+            author_id: series.author_id,
+            start: local(&series.dtstart),
+            next_at: series.next_at.as_deref().map(local),
+            rrule: series.rrule,
+            timezone: series.timezone,
+            title: series.title,
+            description: series.text,
+            priority: series.priority,
+            assignee_id: series.assignee_id,
+            project_id: series.project_id,
+            estimate_minutes: series.estimate_minutes,
+            upcoming,
+            occurrence_ids: series.occurrence_ids,
+            created_at: series.created_at,
+            updated_at: series.updated_at,
+        }
+    }
+}
+
+fn series_rule(
+    rrule: &str,
+    timezone: &str,
+    start: &str,
+) -> Result<(Rule, chrono::DateTime<Tz>), String> {
+    if rrule.len() > MAX_RRULE_LENGTH {
+        return Err(format!(
+            "RRULE must be at most {} characters long",
+            MAX_RRULE_LENGTH
+        ));
+    }
+    let rule: Rule = rrule.parse()?;
+    let tz = rrule::parse_timezone(timezone)?;
+    Ok((rule, rrule::parse_start(start, tz)?))
+}
+
+// Creates a recurring task together with its first occurrence: the latest start of the rule
+// that has passed, or the first one to come.
+async fn create_series(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<CreateSeriesRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if input_payload.task.due_date.is_some() || input_payload.task.parent_id.is_some() {
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            "A recurring task can't be a subtask, and its due dates come from the rule",
+        )
+            .into_response();
+    }
+    let timezone = input_payload.timezone.as_deref().unwrap_or("UTC");
+    let (rule, start) = match series_rule(&input_payload.rrule, timezone, &input_payload.start) {
+        Ok(rule) => rule,
+        Err(e) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+    };
+    let (first_at, next_at) = match rrule::next_due(rule.occurrences(start), chrono::Utc::now()) {
+        Some(occurrences) => occurrences,
+        None => {
+            return (StatusCode::NOT_ACCEPTABLE, "The rule has no occurrences").into_response();
+        }
+    };
+    let first = match create_task_request(&state, id_and_username.0, input_payload.task).await {
+        Ok(req) => req,
+        Err(response) => return response,
+    };
+
+    let req = proto::CreateSeriesRequest {
+        first: Some(proto::CreateTaskRequest {
+            due_date: Some(first_at.to_rfc3339()),
+            ..first
+        }),
+        rrule: input_payload.rrule.trim().to_string(),
+        timezone: start.timezone().name().to_string(),
+        dtstart: start.to_rfc3339(),
+        next_at: next_at.map(|time| time.to_rfc3339()),
+    };
+    let mut client = state.tasks_client.clone();
+    let response = match client.create_series(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+    let resp = CreateSeriesResponse1 {
+        series_id: response.series_id,
+        task_id: response.task_id,
+    };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+// A series is seen by those who may see its occurrences.
+async fn get_series(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(series_id): Path<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let viewer = match viewer(&state, id_and_username.0).await {
+        Ok(viewer) => viewer,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::SeriesRequest {
+        user_id: id_and_username.0,
+        series_id,
+        viewer: Some(viewer),
+    };
+    match client.get_series(tonic::Request::new(req)).await {
+        Ok(response) => {
+            let resp = SeriesResponse1::from(response.into_inner());
+            (StatusCode::OK, Json(resp)).into_response()
+        }
+        Err(e) => grpc_error_response(e),
+    }
+}
+
+// Edits the whole series: the template of the occurrences to come and the occurrences that are
+// still open. A single occurrence is edited like any other task, with /update_task.
+async fn update_series(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(series_id): Path<i64>,
+    Json(input_payload): Json<UpdateSeriesRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::SeriesRequest {
+        user_id: id_and_username.0,
+        series_id,
+        viewer: None,
+    };
+    let series = match client.get_series(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+    if series.author_id != id_and_username.0 {
+        return (StatusCode::FORBIDDEN, "Only the author can change a series").into_response();
+    }
+
+    let title = input_payload.title.map(|title| title.trim().to_string());
+    let checks = title
+        .as_deref()
+        .map_or(Ok(()), check_title)
+        .and(
+            input_payload
+                .description
+                .as_deref()
+                .map_or(Ok(()), check_description),
+        )
+        .and(
+            input_payload
+                .priority
+                .as_deref()
+                .map_or(Ok(()), check_priority),
+        )
+        .and(
+            input_payload
+                .estimate_minutes
+                .map_or(Ok(()), check_estimate),
+        );
+    if let Err(e) = checks {
+        return (StatusCode::NOT_ACCEPTABLE, e).into_response();
+    }
+    if let Err(response) = check_assignee(&state, input_payload.assignee_id).await {
+        return response;
+    }
+
+    let mut req = proto::UpdateSeriesRequest {
+        user_id: id_and_username.0,
+        series_id,
+        title,
+        text: input_payload.description,
+        priority: input_payload.priority,
+        assignee_id: input_payload.assignee_id,
+        clear_assignee: input_payload.clear_assignee,
+        estimate_minutes: input_payload.estimate_minutes,
+        clear_estimate: input_payload.clear_estimate,
+        done_statuses: recurrence::done_statuses(&state.workflow),
+        ..Default::default()
+    };
+    if input_payload.rrule.is_some()
+        || input_payload.timezone.is_some()
+        || input_payload.start.is_some()
+    {
+        let rrule = input_payload.rrule.unwrap_or(series.rrule);
+        let timezone = input_payload.timezone.unwrap_or(series.timezone.clone());
+        let start = match input_payload.start {
+            Some(start) => start,
+            None => {
+                let tz = rrule::parse_timezone(&series.timezone).unwrap_or(Tz::UTC);
+                match chrono::DateTime::parse_from_rfc3339(&series.dtstart) {
+                    Ok(start) => start
+                        .with_timezone(&tz)
+                        .naive_local()
+                        .format("%Y-%m-%dT%H:%M:%S")
+                        .to_string(),
+                    Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+                }
+            }
+        };
+        let (rule, start) = match series_rule(&rrule, &timezone, &start) {
+            Ok(rule) => rule,
+            Err(e) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+        };
+        // The new rule goes on after the latest occurrence, so that it doesn't repeat it.
+        let mut after = chrono::Utc::now();
+        if let Some(task_id) = series.occurrence_ids.last() {
+            let req = proto::GetTaskRequest {
+                task_id: *task_id,
+                ..Default::default()
+            };
+            let latest = match client.get_task(tonic::Request::new(req)).await {
+                Ok(response) => response.into_inner(),
+                Err(e) => return grpc_error_response(e),
+            };
+            if let Some(due_date) = latest
+                .due_date
+                .and_then(|due_date| chrono::DateTime::parse_from_rfc3339(&due_date).ok())
+            {
+                after = after.max(due_date.with_timezone(&chrono::Utc));
+            }
+        }
+        req.next_at = rule
+            .occurrences(start)
+            .find(|time| *time > after)
+            .map(|time| time.to_rfc3339());
+        req.rrule = Some(rrule.trim().to_string());
+        req.timezone = Some(start.timezone().name().to_string());
+        req.dtstart = Some(start.to_rfc3339());
+    }
+
+    // The open occurrences keep their due dates, and so do their reminders.
+    match client.update_series(tonic::Request::new(req)).await {
+        Ok(response) => {
+            let resp = SeriesResponse1::from(response.into_inner());
+            (StatusCode::OK, Json(resp)).into_response()
+        }
+        Err(e) => grpc_error_response(e),
+    }
+}
+
+// Ends a series; the occurrences created so far stay.
+async fn stop_series(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(series_id): Path<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::SeriesRequest {
+        user_id: id_and_username.0,
+        series_id,
+        viewer: None,
+    };
+    match client.stop_series(tonic::Request::new(req)).await {
+        Ok(_) => (StatusCode::OK).into_response(),
+        Err(e) => grpc_error_response(e),
+    }
+}
+
+const MAX_REMINDERS_PER_TASK: i64 = 10;
+const MAX_MINUTES_BEFORE_DUE: i64 = 366 * 24 * 60;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateReminderRequest1 {
+    // Either a fixed time...
+    remind_at: Option<String>,
+    // ...or an offset before the due date, which follows the due date when it changes.
+    minutes_before_due: Option<i64>,
+}
+
+async fn create_reminder(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(task_id): Path<i64>,
+    Json(input_payload): Json<CreateReminderRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let remind_at = match (input_payload.remind_at, input_payload.minutes_before_due) {
+        (Some(remind_at), None) => match parse_timestamp(&remind_at, "Reminder time") {
+            Ok(remind_at) => Some(remind_at),
+            Err(e) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+        },
+        (None, Some(minutes)) if (0..=MAX_MINUTES_BEFORE_DUE).contains(&minutes) => None,
+        (None, Some(_)) => {
+            return (
+                StatusCode::NOT_ACCEPTABLE,
+                format!(
+                    "minutes_before_due must be from 0 to {}",
+                    MAX_MINUTES_BEFORE_DUE
+                ),
+            )
+                .into_response();
+        }
+        _ => {
+            return (
+                StatusCode::NOT_ACCEPTABLE,
+                "Give either remind_at or minutes_before_due",
+            )
+                .into_response();
+        }
+    };
+
+    // A reminder tells the title and the due date of the task, so it takes being able to read it.
+    let task = match visible_task(&state, task_id, id_and_username.0).await {
+        Ok(task) => task,
+        Err(response) => return response,
+    };
+    let fire_at = match reminders::fire_at(
+        remind_at.as_deref(),
+        input_payload.minutes_before_due,
+        task.due_date.as_deref(),
+    ) {
+        Some(fire_at) => fire_at,
+        None => return (StatusCode::NOT_ACCEPTABLE, "The task has no due date").into_response(),
+    };
+    match reminders::count(&state.pool, task_id, id_and_username.0).await {
+        Ok(count) if count >= MAX_REMINDERS_PER_TASK => {
+            return (
+                StatusCode::NOT_ACCEPTABLE,
+                format!(
+                    "At most {} reminders can be set on a task",
+                    MAX_REMINDERS_PER_TASK
+                ),
+            )
+                .into_response();
+        }
+        Ok(_) => {}
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+
+    match reminders::create(
+        &state.pool,
+        task_id,
+        id_and_username.0,
+        remind_at.as_deref(),
+        input_payload.minutes_before_due,
+        &fire_at.to_rfc3339(),
+    )
+    .await
+    {
+        Ok(reminder) => (StatusCode::CREATED, Json(reminder)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// The reminders the user has set on the task.
+async fn list_reminders(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(task_id): Path<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    match reminders::list(&state.pool, task_id, id_and_username.0).await {
+        Ok(reminders) => (StatusCode::OK, Json(reminders)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+async fn delete_reminder(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(reminder_id): Path<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    match reminders::delete(&state.pool, reminder_id, id_and_username.0).await {
+        Ok(true) => (StatusCode::OK).into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Reminder doesn't exist").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+const MAX_TEMPLATE_NAME_LEN: usize = 100;
+const MAX_TEMPLATE_LABELS: usize = 20;
+const MAX_TEMPLATE_CHECKLIST_ITEMS: usize = 50;
+const MAX_TEMPLATE_SUBTASKS: usize = 20;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTemplateRequest1 {
+    name: String,
+    title: String,
+    #[serde(default)]
+    description: String,
+    priority: Option<String>,
+    #[serde(default)]
+    label_ids: Vec<i64>,
+    #[serde(default)]
+    checklist: Vec<String>,
+    #[serde(default)]
+    subtasks: Vec<SubtaskTemplate>,
+    // Without a team the template belongs to the caller.
+    team_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateTemplateRequest1 {
+    name: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    priority: Option<String>,
+    #[serde(default)]
+    clear_priority: bool,
+    label_ids: Option<Vec<i64>>,
+    checklist: Option<Vec<String>>,
+    subtasks: Option<Vec<SubtaskTemplate>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListTemplatesResponse1 {
+    templates: Vec<Template>,
+}
+
+fn check_template_fields(fields: &TemplateFields) -> Result<(), String> {
+    if fields.name.is_empty() || fields.name.chars().count() > MAX_TEMPLATE_NAME_LEN {
+        return Err(format!(
+            "Template name must be from 1 to {} symbols",
+            MAX_TEMPLATE_NAME_LEN
+        ));
+    }
+    check_title(&fields.title)?;
+    check_description(&fields.description)?;
+    fields.priority.as_deref().map_or(Ok(()), check_priority)?;
+    if fields.label_ids.len() > MAX_TEMPLATE_LABELS {
+        return Err(format!(
+            "A template can have at most {} labels",
+            MAX_TEMPLATE_LABELS
+        ));
+    }
+    if fields.checklist.len() > MAX_TEMPLATE_CHECKLIST_ITEMS {
+        return Err(format!(
+            "A checklist can have at most {} items",
+            MAX_TEMPLATE_CHECKLIST_ITEMS
+        ));
+    }
+    for item in &fields.checklist {
+        if item.is_empty() || item.contains('\n') || item.chars().count() > MAX_TITLE_LEN {
+            return Err(format!(
+                "Checklist items must be single lines of 1 to {} symbols",
+                MAX_TITLE_LEN
+            ));
+        }
+    }
+    if fields.subtasks.len() > MAX_TEMPLATE_SUBTASKS {
+        return Err(format!(
+            "A template can have at most {} subtasks",
+            MAX_TEMPLATE_SUBTASKS
+        ));
+    }
+    for subtask in &fields.subtasks {
+        check_title(&subtask.title)?;
+        check_description(&subtask.description)?;
+    }
+    fields.variables()?;
+    Ok(())
+}
+
+// Trims the texts, drops repeated labels and checks the template. Its labels must be usable
+// by the user, and those of a team template must belong to the team.
+async fn prepare_template_fields(
+    state: &Arc<AppState>,
+    user_id: i64,
+    team_id: Option<i64>,
+    mut fields: TemplateFields,
+) -> Result<TemplateFields, Response> {
+    fields.name = fields.name.trim().to_string();
+    fields.title = fields.title.trim().to_string();
+    for item in fields.checklist.iter_mut() {
+        *item = item.trim().to_string();
+    }
+    for subtask in fields.subtasks.iter_mut() {
+        subtask.title = subtask.title.trim().to_string();
+    }
+    let mut seen = HashSet::new();
+    fields.label_ids.retain(|label_id| seen.insert(*label_id));
+    if let Err(e) = check_template_fields(&fields) {
+        return Err((StatusCode::NOT_ACCEPTABLE, e).into_response());
+    }
+
+    let mut client = state.tasks_client.clone();
+    for label_id in &fields.label_ids {
+        let label = get_usable_label(state, &mut client, user_id, *label_id).await?;
+        let team_label = label.owner_kind() == proto::OwnerKind::Team;
+        if let Some(team_id) = team_id {
+            if !team_label || label.owner_id != team_id {
+                return Err((
+                    StatusCode::NOT_ACCEPTABLE,
+                    "A team template can only use labels of the team",
+                )
+                    .into_response());
+            }
+        }
+    }
+    Ok(fields)
+}
+
+// Personal templates are usable by their owner, team templates by every team member.
+async fn get_usable_template(
+    state: &Arc<AppState>,
+    user_id: i64,
+    template_id: i64,
+) -> Result<Template, Response> {
+    let template = match templates::get(&state.pool, template_id).await {
+        Ok(Some(template)) => template,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Template doesn't exist").into_response()),
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    };
+    let usable = match (template.owner_id, template.team_id) {
+        (Some(owner_id), _) => Ok(owner_id == user_id),
+        (None, Some(team_id)) => is_team_member(state, team_id, user_id).await,
+        (None, None) => Ok(false),
+    };
+    match usable {
+        Ok(true) => Ok(template),
+        Ok(false) => {
+            Err((StatusCode::FORBIDDEN, "Template belongs to someone else").into_response())
+        }
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    }
+}
+
+// A task filled in from a template: the request with the fields it left out taken from the
+// template, the labels to attach and the subtasks to create.
+struct TemplateInstance {
+    request: CreateTaskRequest1,
+    label_ids: Vec<i64>,
+    subtasks: Vec<SubtaskTemplate>,
+}
+
+// Puts the template's texts with their placeholders filled in into the fields the request
+// leaves out; the checklist goes after the description. Returns the filled-in subtasks.
+fn fill_template(
+    fields: &TemplateFields,
+    variables: &HashMap<String, String>,
+    request: &mut CreateTaskRequest1,
+) -> Result<Vec<SubtaskTemplate>, String> {
+    if request.title.is_none() {
+        request.title = Some(templates::fill(&fields.title, variables)?);
+    }
+    if request.description.is_none() && request.text.is_none() {
+        let mut description = templates::fill(&fields.description, variables)?;
+        if !fields.checklist.is_empty() {
+            let items = fields
+                .checklist
+                .iter()
+                .map(|item| templates::fill(item, variables))
+                .collect::<Result<Vec<_>, _>>()?;
+            if !description.is_empty() {
+                description = format!("{}\n\n", description.trim_end());
+            }
+            description.push_str(&templates::checklist_markdown(&items));
+        }
+        request.description = Some(description);
+    }
+    if request.priority.is_none() {
+        request.priority = fields.priority.clone();
+    }
+
+    let mut subtasks = vec![];
+    for subtask in &fields.subtasks {
+        let subtask = SubtaskTemplate {
+            title: templates::fill(&subtask.title, variables)?
+                .trim()
+                .to_string(),
+            description: templates::fill(&subtask.description, variables)?,
+        };
+        check_title(&subtask.title)
+            .and(check_description(&subtask.description))
+            .map_err(|e| format!("Subtask: {}", e))?;
+        subtasks.push(subtask);
+    }
+    Ok(subtasks)
+}
+
+// Fills the placeholders of the template with the given variables and the built-in `username`
+// and `date` (today, UTC). Labels the user can no longer use are left out.
+async fn instantiate_template(
+    state: &Arc<AppState>,
+    id_and_username: &(i64, String),
+    template_id: i64,
+    mut request: CreateTaskRequest1,
+) -> Result<TemplateInstance, Response> {
+    let template = get_usable_template(state, id_and_username.0, template_id).await?;
+    let mut variables = HashMap::from([
+        ("username".to_string(), id_and_username.1.clone()),
+        (
+            "date".to_string(),
+            chrono::Utc::now().format("%Y-%m-%d").to_string(),
+        ),
+    ]);
+    variables.extend(std::mem::take(&mut request.variables));
+    let fields = template.fields;
+    let subtasks = match fill_template(&fields, &variables, &mut request) {
+        Ok(subtasks) => subtasks,
+        Err(e) => return Err((StatusCode::NOT_ACCEPTABLE, e).into_response()),
+    };
+
+    let mut client = state.tasks_client.clone();
+    let mut label_ids = vec![];
+    for label_id in fields.label_ids {
+        let req = proto::LabelIdRequest { label_id };
+        let label = match client.get_label(tonic::Request::new(req)).await {
+            Ok(response) => response.into_inner(),
+            Err(e) if e.code() == tonic::Code::NotFound => continue,
+            Err(e) => return Err(grpc_error_response(e)),
+        };
+        match can_use_label(state, id_and_username.0, &label).await {
+            Ok(true) => label_ids.push(label_id),
+            Ok(false) => {}
+            Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        }
+    }
+
+    Ok(TemplateInstance {
+        request,
+        label_ids,
+        subtasks,
+    })
+}
+
+async fn create_template(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<CreateTemplateRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if let Some(team_id) = input_payload.team_id {
+        match is_team_member(&state, team_id, id_and_username.0).await {
+            Ok(true) => {}
+            Ok(false) => return (StatusCode::FORBIDDEN, "Not a team member").into_response(),
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+        }
+    }
+    let fields = TemplateFields {
+        name: input_payload.name,
+        title: input_payload.title,
+        description: input_payload.description,
+        priority: input_payload.priority,
+        label_ids: input_payload.label_ids,
+        checklist: input_payload.checklist,
+        subtasks: input_payload.subtasks,
+    };
+    let fields =
+        match prepare_template_fields(&state, id_and_username.0, input_payload.team_id, fields)
+            .await
+        {
+            Ok(fields) => fields,
+            Err(response) => return response,
+        };
+
+    match templates::create(
+        &state.pool,
+        id_and_username.0,
+        input_payload.team_id,
+        &fields,
+    )
+    .await
+    {
+        Ok(template) => (StatusCode::CREATED, Json(template)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// The templates of the user and of their teams.
+async fn list_templates(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    match templates::list(&state.pool, id_and_username.0).await {
+        Ok(templates) => {
+            (StatusCode::OK, Json(ListTemplatesResponse1 { templates })).into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+async fn get_template(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(template_id): Path<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    match get_usable_template(&state, id_and_username.0, template_id).await {
+        Ok(template) => (StatusCode::OK, Json(template)).into_response(),
+        Err(response) => response,
+    }
+}
+
+async fn update_template(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(template_id): Path<i64>,
+    Json(input_payload): Json<UpdateTemplateRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let template = match get_usable_template(&state, id_and_username.0, template_id).await {
+        Ok(template) => template,
+        Err(response) => return response,
+    };
+    let current = template.fields;
+    let priority = if input_payload.clear_priority {
+        None
+    } else {
+        input_payload.priority.or(current.priority)
+    };
+    let fields = TemplateFields {
+        name: input_payload.name.unwrap_or(current.name),
+        title: input_payload.title.unwrap_or(current.title),
+        description: input_payload.description.unwrap_or(current.description),
+        priority,
+        label_ids: input_payload.label_ids.unwrap_or(current.label_ids),
+        checklist: input_payload.checklist.unwrap_or(current.checklist),
+        subtasks: input_payload.subtasks.unwrap_or(current.subtasks),
+    };
+    let fields =
+        match prepare_template_fields(&state, id_and_username.0, template.team_id, fields).await {
+            Ok(fields) => fields,
+            Err(response) => return response,
+        };
+
+    match templates::update(&state.pool, template_id, &fields).await {
+        Ok(template) => (StatusCode::OK, Json(template)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// Tasks created from the template keep everything it filled in.
+async fn delete_template(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(template_id): Path<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if let Err(response) = get_usable_template(&state, id_and_username.0, template_id).await {
+        return response;
+    }
+    match templates::delete(&state.pool, template_id).await {
+        Ok(()) => (StatusCode::OK).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GrantRequest1 {
+    user_id: Option<i64>,
+    team_id: Option<i64>,
+    permission: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GrantsResponse1 {
+    grants: Vec<Grant>,
+}
+
+// The task if the user is its author, who alone decides who it is shared with.
+async fn task_of_author(
+    state: &Arc<AppState>,
+    task_id: i64,
+    user_id: i64,
+) -> Result<proto::GetTaskResponse, Response> {
+    let mut client = state.tasks_client.clone();
+    let req = proto::GetTaskRequest {
+        task_id,
+        ..Default::default()
+    };
+    let task = match client.get_task(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return Err(grpc_error_response(e)),
+    };
+    if task.author_id != user_id {
+        return Err((StatusCode::FORBIDDEN, "Only the author can share a task").into_response());
+    }
+    Ok(task)
+}
+
+// Who the task is shared with; the author and its editors may look.
+async fn list_grants(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(task_id): Path<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    match task_of_author(&state, task_id, id_and_username.0).await {
+        Ok(_) => {}
+        Err(response) => match edit_granted(&state, task_id, id_and_username.0).await {
+            Ok(true) => {}
+            Ok(false) => return response,
+            Err(response) => return response,
+        },
+    }
+    match grants::list(&state.pool, task_id).await {
+        Ok(grants) => (StatusCode::OK, Json(GrantsResponse1 { grants })).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// Shares the task with a user or a team; sharing it again with them changes the permission.
+async fn create_grant(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(task_id): Path<i64>,
+    Json(input_payload): Json<GrantRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let Some(permission) = Permission::parse(&input_payload.permission) else {
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            "Permission must be view, comment or edit",
+        )
+            .into_response();
+    };
+    let grantee_exists = match (input_payload.user_id, input_payload.team_id) {
+        (Some(user_id), None) => user_exists(&state, user_id).await,
+        (None, Some(team_id)) => team_owner(&state, team_id)
+            .await
+            .map(|owner| owner.is_some()),
+        _ => {
+            return (StatusCode::NOT_ACCEPTABLE, "Give either user_id or team_id").into_response();
+        }
+    };
+    match grantee_exists {
+        Ok(true) => {}
+        Ok(false) => {
+            return (StatusCode::NOT_ACCEPTABLE, "User or team doesn't exist").into_response();
+        }
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+    if let Err(response) = task_of_author(&state, task_id, id_and_username.0).await {
+        return response;
+    }
+
+    let granted = grants::grant_access(
+        &state.pool,
+        task_id,
+        input_payload.user_id,
+        input_payload.team_id,
+        permission,
+        id_and_username.0,
+    )
+    .await;
+    match granted {
+        Ok(grant) => (StatusCode::CREATED, Json(grant)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+async fn delete_grant(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path((task_id, grant_id)): Path<(i64, i64)>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if let Err(response) = task_of_author(&state, task_id, id_and_username.0).await {
+        return response;
+    }
+    match grants::revoke(&state.pool, task_id, grant_id).await {
+        Ok(true) => (StatusCode::OK).into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Grant doesn't exist").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+const MAX_WORK_LOG_MINUTES: i64 = 24 * 60;
+const MAX_WORK_LOG_NOTE_LEN: usize = 1000;
+const MAX_REPORT_DAYS: i64 = 366;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateWorkLogRequest1 {
+    // When the work started; now by default.
+    started_at: Option<String>,
+    minutes: i64,
+    #[serde(default)]
+    note: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateWorkLogRequest1 {
+    started_at: Option<String>,
+    minutes: Option<i64>,
+    note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkLogsResponse1 {
+    work_logs: Vec<WorkLog>,
+    total_minutes: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeTotalsQuery1 {
+    task_id: Option<i64>,
+    user_id: Option<i64>,
+    project_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeTotalsResponse1 {
+    total_minutes: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeReportQuery1 {
+    // YYYY-MM-DD dates, both included.
+    from: String,
+    to: String,
+    #[serde(default)]
+    group_by: GroupBy,
+    // IANA name of the timezone days are counted in; UTC by default.
+    timezone: Option<String>,
+    task_id: Option<i64>,
+    user_id: Option<i64>,
+    project_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimeReportResponse1 {
+    from: String,
+    to: String,
+    group_by: GroupBy,
+    timezone: String,
+    // A week may start before `from`, but only the time from `from` on is counted.
+    periods: Vec<Period>,
+    total_minutes: i64,
+}
+
+fn check_work_log_minutes(minutes: i64) -> Result<i32, String> {
+    if !(1..=MAX_WORK_LOG_MINUTES).contains(&minutes) {
+        return Err(format!(
+            "Minutes must be from 1 to {}",
+            MAX_WORK_LOG_MINUTES
+        ));
+    }
+    Ok(minutes as i32)
+}
+
+// Time is logged once it has been spent, so entries can't start in the future.
+fn parse_work_log_start(started_at: &str) -> Result<String, String> {
+    let parsed = parse_timestamp(started_at, "Start")?;
+    match chrono::DateTime::parse_from_rfc3339(&parsed) {
+        Ok(time) if time > chrono::Utc::now() => Err("Start can't be in the future".to_string()),
+        _ => Ok(parsed),
+    }
+}
+
+fn check_work_log_note(note: &str) -> Result<(), String> {
+    if note.chars().count() > MAX_WORK_LOG_NOTE_LEN {
+        return Err(format!(
+            "A note can be at most {} characters long",
+            MAX_WORK_LOG_NOTE_LEN
+        ));
+    }
+    Ok(())
+}
+
+// The task as the user sees it; what cannot be seen cannot be acted on either.
+async fn visible_task(
+    state: &Arc<AppState>,
+    task_id: i64,
+    user_id: i64,
+) -> Result<proto::GetTaskResponse, Response> {
+    let viewer = match viewer(state, user_id).await {
+        Ok(viewer) => viewer,
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    };
+    let mut client = state.tasks_client.clone();
+    let req = proto::GetTaskRequest {
+        task_id,
+        viewer: Some(viewer),
+        ..Default::default()
+    };
+    match client.get_task(tonic::Request::new(req)).await {
+        Ok(response) => Ok(response.into_inner()),
+        Err(e) => Err(grpc_error_response(e)),
+    }
+}
+
+// The entry if it is the user's; others' time can be seen but not changed.
+async fn own_work_log(
+    state: &Arc<AppState>,
+    user_id: i64,
+    work_log_id: i64,
+) -> Result<WorkLog, Response> {
+    match worklogs::get(&state.pool, work_log_id).await {
+        Ok(Some(work_log)) if work_log.user_id == user_id => Ok(work_log),
+        Ok(Some(_)) => {
+            Err((StatusCode::FORBIDDEN, "Only your own time can be changed").into_response())
+        }
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Work log entry doesn't exist").into_response()),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    }
+}
+
+// What a total or a report may cover: the user's own time anywhere, and everyone's time on
+// the tasks the user may see and in the projects they are a member of.
+async fn time_scope(
+    state: &Arc<AppState>,
+    user_id: i64,
+    task_id: Option<i64>,
+    other_user_id: Option<i64>,
+    project_id: Option<i64>,
+) -> Result<Scope, Response> {
+    if let Some(task_id) = task_id {
+        visible_task(state, task_id, user_id).await?;
+    }
+    if let Some(project_id) = project_id {
+        match project_ids_of_user(state, user_id).await {
+            Ok(project_ids) if project_ids.contains(&project_id) => {}
+            Ok(_) => {
+                return Err(
+                    (StatusCode::FORBIDDEN, "You aren't a member of the project").into_response(),
+                );
+            }
+            Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+        }
+    }
+    let scope = match (task_id, other_user_id, project_id) {
+        (None, None, None) => Scope {
+            user_id: Some(user_id),
+            ..Default::default()
+        },
+        (None, Some(other_user_id), None) if other_user_id != user_id => {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "The time of others can only be seen on a task or in a project",
+            )
+                .into_response());
+        }
+        (task_id, user_id, project_id) => Scope {
+            task_id,
+            user_id,
+            project_id,
+        },
+    };
+    Ok(scope)
+}
+
+// Starts a timer of the user on the task. A user has one timer at a time.
+async fn start_timer(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(task_id): Path<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let user_id = id_and_username.0;
+    let task = match visible_task(&state, task_id, user_id).await {
+        Ok(task) => task,
+        Err(response) => return response,
+    };
+    match worklogs::start_timer(&state.pool, user_id, task_id, task.project_id).await {
+        Ok(Some(work_log)) => (StatusCode::CREATED, Json(work_log)).into_response(),
+        Ok(None) => match worklogs::running_timer(&state.pool, user_id).await {
+            Ok(Some(running)) => (
+                StatusCode::CONFLICT,
+                format!(
+                    "A timer is already running on task {}; stop it first",
+                    running.task_id
+                ),
+            )
+                .into_response(),
+            // Stopped in the meantime.
+            Ok(None) => (StatusCode::CONFLICT, "Try again").into_response(),
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+        },
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+async fn get_timer(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    match worklogs::running_timer(&state.pool, id_and_username.0).await {
+        Ok(Some(work_log)) => (StatusCode::OK, Json(work_log)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "No timer is running").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// Turns the running timer into a work log entry of the time since it was started.
+async fn stop_timer(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    match worklogs::stop_timer(&state.pool, id_and_username.0).await {
+        Ok(Some(work_log)) => (StatusCode::OK, Json(work_log)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "No timer is running").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// Logs time spent on the task without a timer.
+async fn create_work_log(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(task_id): Path<i64>,
+    Json(input_payload): Json<CreateWorkLogRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let started_at = match input_payload.started_at.as_deref() {
+        Some(started_at) => parse_work_log_start(started_at),
+        None => Ok(chrono::Utc::now().to_rfc3339()),
+    };
+    let checked = started_at.and_then(|started_at| {
+        check_work_log_note(&input_payload.note)?;
+        Ok((started_at, check_work_log_minutes(input_payload.minutes)?))
+    });
+    let (started_at, minutes) = match checked {
+        Ok(checked) => checked,
+        Err(e) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+    };
+    let user_id = id_and_username.0;
+    let task = match visible_task(&state, task_id, user_id).await {
+        Ok(task) => task,
+        Err(response) => return response,
+    };
+
+    let created = worklogs::create(
+        &state.pool,
+        user_id,
+        task_id,
+        task.project_id,
+        &started_at,
+        minutes,
+        &input_payload.note,
+    )
+    .await;
+    match created {
+        Ok(work_log) => (StatusCode::CREATED, Json(work_log)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// Everyone's time on the task, running timers included.
+async fn list_work_logs(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(task_id): Path<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if let Err(response) = visible_task(&state, task_id, id_and_username.0).await {
+        return response;
+    }
+    let scope = Scope {
+        task_id: Some(task_id),
+        ..Default::default()
+    };
+    match (
+        worklogs::list_of_task(&state.pool, task_id).await,
+        worklogs::total(&state.pool, &scope).await,
+    ) {
+        (Ok(work_logs), Ok(total_minutes)) => (
+            StatusCode::OK,
+            Json(WorkLogsResponse1 {
+                work_logs,
+                total_minutes,
+            }),
+        )
+            .into_response(),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// The time of a running timer is only known once it is stopped, so only its note and start
+// can change.
+async fn update_work_log(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(work_log_id): Path<i64>,
+    Json(input_payload): Json<UpdateWorkLogRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let work_log = match own_work_log(&state, id_and_username.0, work_log_id).await {
+        Ok(work_log) => work_log,
+        Err(response) => return response,
+    };
+    if work_log.minutes.is_none() && input_payload.minutes.is_some() {
+        return (StatusCode::CONFLICT, "The timer is still running").into_response();
+    }
+    let started_at = match input_payload.started_at.as_deref() {
+        Some(started_at) => parse_work_log_start(started_at),
+        None => Ok(work_log.started_at),
+    };
+    let note = input_payload.note.unwrap_or(work_log.note);
+    let checked = started_at.and_then(|started_at| {
+        check_work_log_note(&note)?;
+        let minutes = match input_payload.minutes {
+            Some(minutes) => Some(check_work_log_minutes(minutes)?),
+            None => work_log.minutes,
+        };
+        Ok((started_at, minutes))
+    });
+    let (started_at, minutes) = match checked {
+        Ok(checked) => checked,
+        Err(e) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+    };
+
+    match worklogs::update(&state.pool, work_log_id, &started_at, minutes, &note).await {
+        Ok(work_log) => (StatusCode::OK, Json(work_log)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// Deleting a running timer discards it.
+async fn delete_work_log(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(work_log_id): Path<i64>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if let Err(response) = own_work_log(&state, id_and_username.0, work_log_id).await {
+        return response;
+    }
+    match worklogs::delete(&state.pool, work_log_id).await {
+        Ok(()) => (StatusCode::OK).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// Minutes logged on a task, by a user or in a project, or any combination of them; the
+// user's own time without any of them.
+async fn time_totals(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<TimeTotalsQuery1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let scope = match time_scope(
+        &state,
+        id_and_username.0,
+        params.task_id,
+        params.user_id,
+        params.project_id,
+    )
+    .await
+    {
+        Ok(scope) => scope,
+        Err(response) => return response,
+    };
+    match worklogs::total(&state.pool, &scope).await {
+        Ok(total_minutes) => {
+            (StatusCode::OK, Json(TimeTotalsResponse1 { total_minutes })).into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// The time of the same scopes as /time_totals, per day or week of a date range.
+async fn time_report(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<TimeReportQuery1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let (from, to) = match (
+        NaiveDate::parse_from_str(&params.from, "%Y-%m-%d"),
+        NaiveDate::parse_from_str(&params.to, "%Y-%m-%d"),
+    ) {
+        (Ok(from), Ok(to)) => (from, to),
+        _ => {
+            return (
+                StatusCode::NOT_ACCEPTABLE,
+                "from and to must be YYYY-MM-DD dates",
+            )
+                .into_response();
+        }
+    };
+    if to < from || (to - from).num_days() >= MAX_REPORT_DAYS {
+        return (
+            StatusCode::NOT_ACCEPTABLE,
+            format!(
+                "to must not be before from, and a report covers at most {} days",
+                MAX_REPORT_DAYS
+            ),
+        )
+            .into_response();
+    }
+    let timezone = params.timezone.unwrap_or_else(|| "UTC".to_string());
+    if let Err(e) = rrule::parse_timezone(&timezone) {
+        return (StatusCode::NOT_ACCEPTABLE, e).into_response();
+    }
+    let scope = match time_scope(
+        &state,
+        id_and_username.0,
+        params.task_id,
+        params.user_id,
+        params.project_id,
+    )
+    .await
+    {
+        Ok(scope) => scope,
+        Err(response) => return response,
+    };
+
+    let (from, to) = (from.to_string(), to.to_string());
+    let periods =
+        match worklogs::report(&state.pool, &scope, &from, &to, &timezone, params.group_by).await {
+            Ok(periods) => periods,
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+        };
+    let resp = TimeReportResponse1 {
+        total_minutes: periods.iter().map(|period| period.minutes).sum(),
+        from,
+        to,
+        group_by: params.group_by,
+        timezone,
+        periods,
+    };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+const COMMENT_PREVIEW_LEN: usize = 100;
+
+// Tells the watchers of the task what the user did to it. The change has already been made,
+// so a failure is only logged.
+async fn notify_watchers(
+    state: &Arc<AppState>,
+    task: &proto::GetTaskResponse,
+    actor: &(i64, String),
+    event: Event,
+    detail: Option<&str>,
+) {
+    let task_event = TaskEvent::new(task, actor.0, &actor.1, event, detail);
+    if let Err(e) = notifications::publish(&state.pool, &task_event).await {
+        println!(
+            "Failed to notify the watchers of task {}: {}",
+            task.task_id, e
+        );
+    }
+}
+
+// The same for a task that has to be looked up first.
+async fn notify_task_watchers(
+    state: &Arc<AppState>,
+    task_id: i64,
+    actor: &(i64, String),
+    event: Event,
+    detail: Option<&str>,
+) {
+    let mut client = state.tasks_client.clone();
+    let req = proto::GetTaskRequest {
+        task_id,
+        ..Default::default()
+    };
+    match client.get_task(tonic::Request::new(req)).await {
+        Ok(response) => notify_watchers(state, &response.into_inner(), actor, event, detail).await,
+        Err(e) => println!(
+            "Failed to notify the watchers of task {}: {}",
+            task_id,
+            e.message()
+        ),
+    }
+}
+
+// The start of a comment or a text, to quote in a notification.
+fn preview(text: &str) -> String {
+    let mut preview: String = text.chars().take(COMMENT_PREVIEW_LEN).collect();
+    if preview.len() < text.len() {
+        preview.push_str("...");
+    }
+    preview
+}
+
+// Stores who the text of a task or a comment mentions and notifies the users mentioned there
+// for the first time, so that editing the text doesn't notify them again.
+async fn sync_mentions(
+    state: &Arc<AppState>,
+    source: mentions::Source,
+    source_id: i64,
+    task_id: i64,
+    text: &str,
+    actor: &(i64, String),
+) {
+    let stored = async {
+        let mentioned = mentions::resolve(&state.pool, &mentions::usernames(text)).await?;
+        let user_ids: Vec<i64> = mentioned.iter().map(|mention| mention.user_id).collect();
+        mentions::store(&state.pool, source, source_id, task_id, &user_ids).await
+    }
+    .await;
+    let new_user_ids = match stored {
+        Ok(user_ids) if user_ids.is_empty() => return,
+        Ok(user_ids) => user_ids,
+        Err(e) => {
+            println!("Failed to store the mentions of task {}: {}", task_id, e);
+            return;
+        }
+    };
+    // Only those who may read the task hear about it.
+    let mut readers = Vec::with_capacity(new_user_ids.len());
+    for user_id in new_user_ids {
+        if visible_task(state, task_id, user_id).await.is_ok() {
+            readers.push(user_id);
+        }
+    }
+    if readers.is_empty() {
+        return;
+    }
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::GetTaskRequest {
+        task_id,
+        ..Default::default()
+    };
+    let task = match client.get_task(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => {
+            println!(
+                "Failed to notify the users mentioned in task {}: {}",
+                task_id,
+                e.message()
+            );
+            return;
+        }
+    };
+    let detail = match source {
+        mentions::Source::Task => None,
+        mentions::Source::Comment => Some(preview(text)),
+    };
+    let task_event = TaskEvent::new(
+        &task,
+        actor.0,
+        &actor.1,
+        Event::Mentioned,
+        detail.as_deref(),
+    );
+    if let Err(e) = notifications::publish_to(&state.pool, &task_event, &readers).await {
+        println!(
+            "Failed to notify the users mentioned in task {}: {}",
+            task_id, e
+        );
+    }
+}
+
+// The same for the text a create or an update of a batch or an import sets.
+async fn sync_operation_mentions(
+    state: &Arc<AppState>,
+    actor: &(i64, String),
+    task_id: i64,
+    op: &proto::bulk_operation::Op,
+) {
+    use proto::bulk_operation::Op;
+    let text = match op {
+        Op::Create(create) => Some(&create.text),
+        Op::Update(update) => update.new_text.as_ref(),
+        _ => None,
+    };
+    if let Some(text) = text {
+        sync_mentions(state, mentions::Source::Task, task_id, task_id, text, actor).await;
+    }
+}
+
+async fn fill_comment_mentions(
+    state: &Arc<AppState>,
+    comments: &mut [CommentResponse1],
+) -> Result<(), sqlx::Error> {
+    let comment_ids: Vec<i64> = comments.iter().map(|comment| comment.comment_id).collect();
+    let mut mentions = mentions::load(&state.pool, mentions::Source::Comment, &comment_ids).await?;
+    for comment in comments.iter_mut() {
+        if let (Some(content), Some(comment_mentions)) =
+            (&comment.content, mentions.remove(&comment.comment_id))
+        {
+            let rendered = mentions::render(content, &comment_mentions);
+            comment.content_html = Some(markdown::to_html(&rendered));
+            comment.rendered_content = Some(rendered);
+            comment.mentions = comment_mentions;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserResponse1 {
+    user_id: i64,
+    username: String,
+}
+
+// Public part of a user's profile; mentions link here.
+async fn get_user(State(state): State<Arc<AppState>>, Path(user_id): Path<i64>) -> Response {
+    let row = sqlx::query("SELECT username FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&state.pool)
+        .await;
+    match row {
+        Ok(Some(row)) => match row.try_get("username") {
+            Ok(username) => {
+                (StatusCode::OK, Json(UserResponse1 { user_id, username })).into_response()
+            }
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+        },
+        Ok(None) => (StatusCode::NOT_FOUND, "User doesn't exist").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WatchersResponse1 {
+    watchers: Vec<i64>,
+}
+
+async fn list_watchers(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(task_id): Path<i64>,
+) -> Response {
+    if let Err(response) = check_task_readable(&state, headers, task_id).await {
+        return response;
+    }
+    let mut client = state.tasks_client.clone();
+    let req = proto::GetTaskRequest {
+        task_id,
+        ..Default::default()
+    };
+    let task = match client.get_task(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+    match notifications::watchers(&state.pool, task_id, task.author_id, task.assignee_id).await {
+        Ok(watchers) => (StatusCode::OK, Json(WatchersResponse1 { watchers })).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+async fn change_watching(
+    state: &Arc<AppState>,
+    headers: HeaderMap,
+    task_id: i64,
+    watching: bool,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    // Watchers are told what happens to the task, so only its readers can watch it.
+    if let Err(response) = visible_task(state, task_id, id_and_username.0).await {
+        return response;
+    }
+    match notifications::set_watching(&state.pool, task_id, id_and_username.0, watching).await {
+        Ok(()) => (StatusCode::OK).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+async fn watch_task(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(task_id): Path<i64>,
+) -> Response {
+    change_watching(&state, headers, task_id, true).await
+}
+
+// Also works for the author and the assignee, who otherwise watch the task without asking.
+async fn unwatch_task(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(task_id): Path<i64>,
+) -> Response {
+    change_watching(&state, headers, task_id, false).await
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotificationsQuery1 {
+    #[serde(default)]
+    include_read: bool,
+    #[serde(default)]
+    offset: i64,
+    #[serde(default = "default_page_size")]
+    limit: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotificationsResponse1 {
+    notifications: Vec<NotificationRecord>,
+    unread_count: i64,
+}
+
+// The unread notifications of the user, newest first, or all of them with `include_read`.
+async fn list_notifications(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<NotificationsQuery1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if let Err(e) = check_page(params.offset, params.limit) {
+        return (StatusCode::NOT_ACCEPTABLE, e).into_response();
+    }
+
+    let user_id = id_and_username.0;
+    let listed = notifications::list(
+        &state.pool,
+        user_id,
+        params.include_read,
+        params.offset,
+        params.limit,
+    )
+    .await;
+    let (notifications, unread_count) = match (
+        listed,
+        notifications::unread_count(&state.pool, user_id).await,
+    ) {
+        (Ok(notifications), Ok(unread_count)) => (notifications, unread_count),
+        _ => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+    let resp = NotificationsResponse1 {
+        notifications,
+        unread_count,
+    };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+async fn change_read(
+    state: &Arc<AppState>,
+    headers: HeaderMap,
+    notification_id: i64,
+    read: bool,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    match notifications::set_read(&state.pool, notification_id, id_and_username.0, read).await {
+        Ok(true) => (StatusCode::OK).into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Notification doesn't exist").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+async fn mark_notification_read(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(notification_id): Path<i64>,
+) -> Response {
+    change_read(&state, headers, notification_id, true).await
+}
+
+async fn mark_notification_unread(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(notification_id): Path<i64>,
+) -> Response {
+    change_read(&state, headers, notification_id, false).await
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MarkAllReadResponse1 {
+    marked: u64,
+}
+
+async fn mark_all_notifications_read(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    match notifications::mark_all_read(&state.pool, id_and_username.0).await {
+        Ok(marked) => (StatusCode::OK, Json(MarkAllReadResponse1 { marked })).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreferenceRequest1 {
+    event: String,
+    in_app: Option<bool>,
+    external: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdatePreferencesRequest1 {
+    preferences: Vec<PreferenceRequest1>,
+}
+
+async fn get_notification_preferences(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    match notifications::preferences(&state.pool, id_and_username.0).await {
+        Ok(preferences) => (StatusCode::OK, Json(preferences)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+// Changes how the user hears about each kind of event; returns the preferences for all of them.
+async fn update_notification_preferences(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<UpdatePreferencesRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let mut changes = vec![];
+    for preference in input_payload.preferences {
+        match Event::parse(&preference.event) {
+            Some(event) => changes.push((event, preference.in_app, preference.external)),
+            None => {
+                let events: Vec<&str> = Event::ALL.iter().map(Event::as_str).collect();
+                return (
+                    StatusCode::NOT_ACCEPTABLE,
+                    format!(
+                        "Unknown event {}, must be one of {}",
+                        preference.event,
+                        events.join(", ")
+                    ),
+                )
+                    .into_response();
+            }
+        }
+    }
+
+    let user_id = id_and_username.0;
+    for (event, in_app, external) in changes {
+        if notifications::set_preference(&state.pool, user_id, event, in_app, external)
+            .await
+            .is_err()
+        {
+            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+        }
+    }
+    match notifications::preferences(&state.pool, user_id).await {
+        Ok(preferences) => (StatusCode::OK, Json(preferences)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubtasksRequest1 {
+    task_id: i64,
+    #[serde(flatten)]
+    query: TaskListQuery1,
+}
+
+async fn subtasks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<SubtasksRequest1>,
+) -> Response {
+    let viewer = match optional_viewer(&state, headers).await {
+        Ok(viewer) => viewer,
+        Err(response) => return response,
+    };
+    let req = match task_list_request(&state.workflow, input_payload.query) {
+        Ok(req) => req,
+        Err(e) => return (StatusCode::NOT_ACCEPTABLE, e).into_response(),
+    };
+    send_list_tasks(
+        &state,
+        proto::ListTasksRequest {
+            kind: proto::TaskListKind::Subtasks.into(),
+            parent_id: input_payload.task_id,
+            viewer: Some(viewer),
+            ..req
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyRequest1 {
+    // `blocker_id` has to be done before `blocked_id` can be.
+    blocker_id: i64,
+    blocked_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyResponse1 {
+    blocker_id: i64,
+    blocked_id: i64,
+}
+
+impl From<proto::Dependency> for DependencyResponse1 {
+    fn from(dependency: proto::Dependency) -> Self {
+        DependencyResponse1 {
+            blocker_id: dependency.blocker_id,
+            blocked_id: dependency.blocked_id,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskDependenciesResponse1 {
+    blocked_by: Vec<i64>,
+    blocks: Vec<i64>,
+}
+
+async fn change_dependency(
+    state: Arc<AppState>,
+    headers: HeaderMap,
+    input_payload: DependencyRequest1,
+    add: bool,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    // Both ends of a dependency have to be seen to be linked.
+    let blocked = match visible_task(&state, input_payload.blocked_id, id_and_username.0).await {
+        Ok(task) => task,
+        Err(response) => return response,
+    };
+    if let Err(response) = visible_task(&state, input_payload.blocker_id, id_and_username.0).await {
+        return response;
+    }
+    if let Some(project_id) = blocked.project_id {
+        if let Err(response) =
+            check_project_access(&state, project_id, id_and_username.0, true).await
+        {
+            return response;
+        }
+    }
+
+    let granted = match edit_granted(&state, input_payload.blocked_id, id_and_username.0).await {
+        Ok(granted) => granted,
+        Err(response) => return response,
+    };
+    let mut client = state.tasks_client.clone();
+    let req = proto::DependencyRequest {
+        user_id: id_and_username.0,
+        blocker_id: input_payload.blocker_id,
+        blocked_id: input_payload.blocked_id,
+        granted,
+    };
+    let result = if add {
+        client.add_dependency(tonic::Request::new(req)).await
+    } else {
+        client.remove_dependency(tonic::Request::new(req)).await
+    };
+    match result {
+        Ok(_) => (StatusCode::OK).into_response(),
+        Err(e) => grpc_error_response(e),
+    }
+}
+
+async fn add_dependency(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<DependencyRequest1>,
+) -> Response {
+    change_dependency(state, headers, input_payload, true).await
+}
+
+async fn remove_dependency(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<DependencyRequest1>,
+) -> Response {
+    change_dependency(state, headers, input_payload, false).await
+}
+
+async fn task_dependencies(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<GetTaskRequest1>,
+) -> Response {
+    if let Err(response) = check_task_readable(&state, headers, input_payload.task_id).await {
+        return response;
+    }
+    let mut client = state.tasks_client.clone();
+    let req = proto::ListDependenciesRequest {
+        task_id: input_payload.task_id,
+        ..Default::default()
+    };
+    let response = match client.list_dependencies(tonic::Request::new(req)).await {
+        Ok(response) => response,
+        Err(e) => return grpc_error_response(e),
+    };
+
+    let mut resp = TaskDependenciesResponse1 {
+        blocked_by: vec![],
+        blocks: vec![],
+    };
+    for dependency in response.into_inner().dependencies {
+        if dependency.blocked_id == input_payload.task_id {
+            resp.blocked_by.push(dependency.blocker_id);
+        } else {
+            resp.blocks.push(dependency.blocked_id);
+        }
+    }
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyGraphRequest1 {
+    project_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyNodeResponse1 {
+    task_id: i64,
+    key: Option<String>,
+    title: String,
+    status: String,
+    estimate_minutes: Option<i64>,
+    done: bool,
+    blocked: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CriticalPathResponse1 {
+    task_ids: Vec<i64>,
+    // Remaining work along the path; done tasks count as zero.
+    total_minutes: i64,
+    // Tasks on the path without an estimate, so the total is a lower bound.
+    unestimated_task_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyGraphResponse1 {
+    nodes: Vec<DependencyNodeResponse1>,
+    edges: Vec<DependencyResponse1>,
+    critical_path: CriticalPathResponse1,
+}
+
+async fn dependency_graph(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<DependencyGraphRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let project_id = input_payload.project_id;
+    if let Err(response) = check_project_access(&state, project_id, id_and_username.0, false).await
+    {
+        return response;
+    }
+
+    let mut client = state.tasks_client.clone();
+    let mut tasks: Vec<GetTaskResponse1> =
+        match list_board_tasks(&state, project_id, id_and_username.0).await {
+            Ok(tasks) => tasks.into_iter().map(GetTaskResponse1::from).collect(),
+            Err(response) => return response,
+        };
+    let req = proto::ListDependenciesRequest {
+        project_id,
+        ..Default::default()
+    };
+    let dependencies: Vec<proto::Dependency> =
+        match client.list_dependencies(tonic::Request::new(req)).await {
+            Ok(response) => response.into_inner().dependencies,
+            Err(e) => return grpc_error_response(e),
+        };
+    // Dependencies on tasks hidden from the user are left out with the tasks.
+    let task_ids: HashSet<i64> = tasks.iter().map(|task| task.task_id).collect();
+    let dependencies: Vec<proto::Dependency> = dependencies
+        .into_iter()
+        .filter(|d| task_ids.contains(&d.blocker_id) && task_ids.contains(&d.blocked_id))
+        .collect();
+    fill_task_relations(&state.workflow, &mut tasks);
+    if fill_task_links(&state, &mut tasks).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+
+    let nodes: Vec<DependencyNodeResponse1> = tasks
+        .into_iter()
+        .map(|task| DependencyNodeResponse1 {
+            done: state.workflow.is_done(&task.status),
+            task_id: task.task_id,
+            key: task.key,
+            title: task.title,
+            status: task.status,
+            estimate_minutes: task.estimate_minutes,
+            blocked: task.blocked,
+        })
+        .collect();
+    let weights: Vec<(i64, i64)> = nodes
+        .iter()
+        .map(|node| {
+            let remaining = if node.done {
+                0
+            } else {
+                node.estimate_minutes.unwrap_or(0)
+            };
+            (node.task_id, remaining)
+        })
+        .collect();
+    let edges: Vec<(i64, i64)> = dependencies
+        .iter()
+        .map(|dependency| (dependency.blocker_id, dependency.blocked_id))
+        .collect();
+    let path = graph::critical_path(&weights, &edges);
+    let unestimated_task_ids = nodes
+        .iter()
+        .filter(|node| {
+            !node.done && node.estimate_minutes.is_none() && path.task_ids.contains(&node.task_id)
+        })
+        .map(|node| node.task_id)
+        .collect();
+
+    let resp = DependencyGraphResponse1 {
+        nodes,
+        edges: dependencies
+            .into_iter()
+            .map(DependencyResponse1::from)
+            .collect(),
+        critical_path: CriticalPathResponse1 {
+            task_ids: path.task_ids,
+            total_minutes: path.total_minutes,
+            unestimated_task_ids,
+        },
+    };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+const MAX_COMMENT_LEN: usize = 10000;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateCommentRequest1 {
+    task_id: i64,
+    parent_id: Option<i64>,
+    content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EditCommentRequest1 {
+    comment_id: i64,
+    content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteCommentRequest1 {
+    comment_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListCommentsRequest1 {
+    task_id: i64,
+    parent_id: Option<i64>,
+    offset: i64,
+    limit: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommentResponse1 {
+    comment_id: i64,
+    task_id: i64,
+    parent_id: Option<i64>,
+    author_id: i64,
+    content: Option<String>,
+    created_at: String,
+    edited_at: Option<String>,
+    deleted: bool,
+    reply_count: i64,
+    mentions: Vec<Mention>,
+    rendered_content: Option<String>,
+    content_html: Option<String>,
+}
+
+impl From<proto::Comment> for CommentResponse1 {
+    fn from(comment: proto::Comment) -> Self {
+        let content = if comment.deleted {
+            None
+        } else {
+            Some(comment.content)
+        };
+        CommentResponse1 {
+            comment_id: comment.comment_id,
+            task_id: comment.task_id,
+            parent_id: comment.parent_id,
+            author_id: comment.author_id,
+            rendered_content: content.clone(),
+            content_html: content.as_deref().map(markdown::to_html),
+            content,
+            created_at: comment.created_at,
+            edited_at: comment.edited_at,
+            deleted: comment.deleted,
+            reply_count: comment.reply_count,
+            mentions: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListCommentsResponse1 {
+    comments: Vec<CommentResponse1>,
+    total_count: i64,
+}
+
+fn check_comment(content: &str) -> Result<(), String> {
+    if content.trim().is_empty() {
+        return Err("Comment must not be empty".to_string());
+    }
+    if content.chars().count() > MAX_COMMENT_LEN {
+        return Err(format!(
+            "Comment must be at most {} symbols",
+            MAX_COMMENT_LEN
+        ));
+    }
+    Ok(())
+}
+
+fn check_page(offset: i64, limit: i64) -> Result<(), String> {
+    if offset < 0 || !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(format!(
+            "Offset must be non-negative and limit from 1 to {}",
+            MAX_PAGE_SIZE
+        ));
+    }
+    Ok(())
+}
+
+async fn is_moderator(state: &Arc<AppState>, user_id: i64) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query("SELECT is_moderator FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&state.pool)
+        .await?;
+    Ok(match row {
+        Some(row) => row.try_get("is_moderator")?,
+        None => false,
+    })
+}
+
+// Everyone who may see a task through its visibility may comment on it; those it is only
+// shared with need at least a comment grant.
+async fn check_may_comment(
+    state: &Arc<AppState>,
+    client: &mut TaskServiceClient<tonic::transport::Channel>,
+    task_id: i64,
+    user_id: i64,
+) -> Result<(), Response> {
+    let mut viewer = match viewer(state, user_id).await {
+        Ok(viewer) => viewer,
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    };
+    viewer.shared_task_ids.clear();
+    let req = proto::GetTaskRequest {
+        task_id,
+        viewer: Some(viewer),
+        ..Default::default()
+    };
+    let not_visible = match client.get_task(tonic::Request::new(req)).await {
+        Ok(_) => return Ok(()),
+        Err(e) if e.code() == tonic::Code::NotFound => grpc_error_response(e),
+        Err(e) => return Err(grpc_error_response(e)),
+    };
+    match grants::permission(&state.pool, task_id, user_id).await {
+        Ok(Some(Permission::Comment | Permission::Edit)) => Ok(()),
+        Ok(Some(Permission::View)) => Err((
+            StatusCode::FORBIDDEN,
+            "The task is only shared with you for viewing",
+        )
+            .into_response()),
+        Ok(None) => Err(not_visible),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    }
+}
+
+async fn create_comment(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<CreateCommentRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if let Err(e) = check_comment(&input_payload.content) {
+        return (StatusCode::NOT_ACCEPTABLE, e).into_response();
+    }
+
+    let mut client = state.tasks_client.clone();
+    if let Err(response) = check_may_comment(
+        &state,
+        &mut client,
+        input_payload.task_id,
+        id_and_username.0,
+    )
+    .await
+    {
+        return response;
+    }
+    let req = proto::CreateCommentRequest {
+        task_id: input_payload.task_id,
+        parent_id: input_payload.parent_id,
+        author_id: id_and_username.0,
+        content: input_payload.content,
+    };
+    let comment = match client.create_comment(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+    notify_task_watchers(
+        &state,
+        comment.task_id,
+        &id_and_username,
+        Event::Commented,
+        Some(&preview(&comment.content)),
+    )
+    .await;
+    sync_mentions(
+        &state,
+        mentions::Source::Comment,
+        comment.comment_id,
+        comment.task_id,
+        &comment.content,
+        &id_and_username,
+    )
+    .await;
+    let mut resp = CommentResponse1::from(comment);
+    if fill_comment_mentions(&state, std::slice::from_mut(&mut resp))
+        .await
+        .is_err()
+    {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+    (StatusCode::CREATED, Json(resp)).into_response()
+}
+
+async fn edit_comment(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<EditCommentRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    if let Err(e) = check_comment(&input_payload.content) {
+        return (StatusCode::NOT_ACCEPTABLE, e).into_response();
+    }
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::EditCommentRequest {
+        comment_id: input_payload.comment_id,
+        user_id: id_and_username.0,
+        content: input_payload.content,
+    };
+    let comment = match client.edit_comment(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+    sync_mentions(
+        &state,
+        mentions::Source::Comment,
+        comment.comment_id,
+        comment.task_id,
+        &comment.content,
+        &id_and_username,
+    )
+    .await;
+    let mut resp = CommentResponse1::from(comment);
+    if fill_comment_mentions(&state, std::slice::from_mut(&mut resp))
+        .await
+        .is_err()
+    {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+async fn delete_comment(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<DeleteCommentRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let is_moderator = match is_moderator(&state, id_and_username.0).await {
+        Ok(is_moderator) => is_moderator,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::DeleteCommentRequest {
+        comment_id: input_payload.comment_id,
+        user_id: id_and_username.0,
+        is_moderator,
+    };
+    match client.delete_comment(tonic::Request::new(req)).await {
+        Ok(_) => (StatusCode::OK).into_response(),
+        Err(e) => grpc_error_response(e),
+    }
+}
+
+// Lists one level of a thread: top-level comments of the task, or replies to `parent_id`.
+async fn list_comments(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<ListCommentsRequest1>,
+) -> Response {
+    if let Err(e) = check_page(input_payload.offset, input_payload.limit) {
+        return (StatusCode::NOT_ACCEPTABLE, e).into_response();
+    }
+
+    if let Err(response) = check_task_readable(&state, headers, input_payload.task_id).await {
+        return response;
+    }
+    let mut client = state.tasks_client.clone();
+    let req = proto::ListCommentsRequest {
+        task_id: input_payload.task_id,
+        parent_id: input_payload.parent_id,
+        offset: input_payload.offset,
+        limit: input_payload.limit,
+    };
+    let response = match client.list_comments(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return grpc_error_response(e),
+    };
+
+    let mut comments: Vec<CommentResponse1> = response
+        .comments
+        .into_iter()
+        .map(CommentResponse1::from)
+        .collect();
+    if fill_comment_mentions(&state, &mut comments).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+    let resp = ListCommentsResponse1 {
+        comments,
+        total_count: response.total_count,
+    };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+const MAX_LABEL_NAME_LEN: usize = 50;
+const MAX_LABEL_DESCRIPTION_LEN: usize = 200;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateLabelRequest1 {
+    name: String,
+    color: String,
+    #[serde(default)]
+    description: String,
+    // Without a team the label belongs to the caller.
+    team_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateLabelRequest1 {
+    label_id: i64,
+    name: Option<String>,
+    color: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LabelIdRequest1 {
+    label_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MergeLabelsRequest1 {
+    source_label_id: i64,
+    target_label_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskLabelRequest1 {
+    task_id: i64,
+    label_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LabelResponse1 {
+    label_id: i64,
+    team_id: Option<i64>,
+    owner_id: Option<i64>,
+    name: String,
+    color: String,
+    description: String,
+}
+
+impl From<proto::Label> for LabelResponse1 {
+    fn from(label: proto::Label) -> Self {
+        let is_team = label.owner_kind() == proto::OwnerKind::Team;
+        LabelResponse1 {
+            label_id: label.label_id,
+            team_id: if is_team { Some(label.owner_id) } else { None },
+            owner_id: if is_team { None } else { Some(label.owner_id) },
+            name: label.name,
+            color: label.color,
+            description: label.description,
+        }
+    }
+}
+
+fn check_label_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() || name.chars().count() > MAX_LABEL_NAME_LEN {
+        return Err(format!(
+            "Label name must be from 1 to {} symbols",
+            MAX_LABEL_NAME_LEN
+        ));
+    }
+    Ok(())
+}
+
+fn check_label_color(color: &str) -> Result<(), String> {
+    let is_hex = color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if !is_hex {
+        return Err("Color must be in #rrggbb format".to_string());
+    }
+    Ok(())
+}
+
+fn check_label_description(description: &str) -> Result<(), String> {
+    if description.chars().count() > MAX_LABEL_DESCRIPTION_LEN {
+        return Err(format!(
+            "Label description must be at most {} symbols",
+            MAX_LABEL_DESCRIPTION_LEN
+        ));
+    }
+    Ok(())
+}
+
+// Personal labels are usable by their owner, team labels by every team member.
+async fn can_use_label(
+    state: &Arc<AppState>,
+    user_id: i64,
+    label: &proto::Label,
+) -> Result<bool, sqlx::Error> {
+    match label.owner_kind() {
+        proto::OwnerKind::User => Ok(label.owner_id == user_id),
+        proto::OwnerKind::Team => is_team_member(state, label.owner_id, user_id).await,
+    }
+}
+
+async fn get_usable_label(
+    state: &Arc<AppState>,
+    client: &mut TaskServiceClient<tonic::transport::Channel>,
+    user_id: i64,
+    label_id: i64,
+) -> Result<proto::Label, Response> {
+    let req = proto::LabelIdRequest { label_id };
+    let label = match client.get_label(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return Err(grpc_error_response(e)),
+    };
+    match can_use_label(state, user_id, &label).await {
+        Ok(true) => Ok(label),
+        Ok(false) => Err((StatusCode::FORBIDDEN, "Label belongs to someone else").into_response()),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    }
+}
+
+async fn create_label(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<CreateLabelRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let name = input_payload.name.trim().to_string();
+    let checks = check_label_name(&name)
+        .and(check_label_color(&input_payload.color))
+        .and(check_label_description(&input_payload.description));
+    if let Err(e) = checks {
+        return (StatusCode::NOT_ACCEPTABLE, e).into_response();
+    }
+    let (owner_kind, owner_id) = match input_payload.team_id {
+        Some(team_id) => match is_team_member(&state, team_id, id_and_username.0).await {
+            Ok(true) => (proto::OwnerKind::Team, team_id),
+            Ok(false) => return (StatusCode::FORBIDDEN, "Not a team member").into_response(),
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+        },
+        None => (proto::OwnerKind::User, id_and_username.0),
+    };
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::CreateLabelRequest {
+        owner_kind: owner_kind.into(),
+        owner_id,
+        name,
+        color: input_payload.color.to_ascii_lowercase(),
+        description: input_payload.description,
+    };
+    match client.create_label(tonic::Request::new(req)).await {
+        Ok(response) => (
+            StatusCode::CREATED,
+            Json(LabelResponse1::from(response.into_inner())),
+        )
+            .into_response(),
+        Err(e) => grpc_error_response(e),
+    }
+}
+
+// Renames or recolors a label; tasks refer to labels by id, so all of them follow.
+async fn update_label(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<UpdateLabelRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let name = input_payload.name.map(|name| name.trim().to_string());
+    let checks = name
+        .as_deref()
+        .map_or(Ok(()), check_label_name)
+        .and(
+            input_payload
+                .color
+                .as_deref()
+                .map_or(Ok(()), check_label_color),
+        )
+        .and(
+            input_payload
+                .description
+                .as_deref()
+                .map_or(Ok(()), check_label_description),
+        );
+    if let Err(e) = checks {
+        return (StatusCode::NOT_ACCEPTABLE, e).into_response();
+    }
+
+    let mut client = state.tasks_client.clone();
+    if let Err(response) = get_usable_label(
+        &state,
+        &mut client,
+        id_and_username.0,
+        input_payload.label_id,
+    )
+    .await
+    {
+        return response;
+    }
+    let req = proto::UpdateLabelRequest {
+        label_id: input_payload.label_id,
+        name,
+        color: input_payload.color.map(|color| color.to_ascii_lowercase()),
+        description: input_payload.description,
+    };
+    match client.update_label(tonic::Request::new(req)).await {
+        Ok(response) => (
+            StatusCode::OK,
+            Json(LabelResponse1::from(response.into_inner())),
+        )
+            .into_response(),
+        Err(e) => grpc_error_response(e),
+    }
+}
+
+async fn delete_label(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<LabelIdRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+    if let Err(response) = get_usable_label(
+        &state,
+        &mut client,
+        id_and_username.0,
+        input_payload.label_id,
+    )
+    .await
+    {
+        return response;
+    }
+    let req = proto::LabelIdRequest {
+        label_id: input_payload.label_id,
+    };
+    match client.delete_label(tonic::Request::new(req)).await {
+        Ok(_) => (StatusCode::OK).into_response(),
+        Err(e) => grpc_error_response(e),
+    }
+}
+
+// Moves every task from the source label to the target one and removes the source.
+async fn merge_labels(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<MergeLabelsRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+    for label_id in [input_payload.source_label_id, input_payload.target_label_id] {
+        if let Err(response) =
+            get_usable_label(&state, &mut client, id_and_username.0, label_id).await
+        {
+            return response;
+        }
+    }
+    let req = proto::MergeLabelsRequest {
+        source_label_id: input_payload.source_label_id,
+        target_label_id: input_payload.target_label_id,
+    };
+    match client.merge_labels(tonic::Request::new(req)).await {
+        Ok(response) => (
+            StatusCode::OK,
+            Json(LabelResponse1::from(response.into_inner())),
+        )
+            .into_response(),
+        Err(e) => grpc_error_response(e),
+    }
+}
+
+async fn list_labels(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let team_ids = match team_ids_of_user(&state, id_and_username.0).await {
+        Ok(team_ids) => team_ids,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+
+    let mut client = state.tasks_client.clone();
+    let req = proto::ListLabelsRequest {
+        user_id: id_and_username.0,
+        team_ids,
+    };
+    match client.list_labels(tonic::Request::new(req)).await {
+        Ok(response) => {
+            let labels: Vec<LabelResponse1> = response
+                .into_inner()
+                .labels
+                .into_iter()
+                .map(LabelResponse1::from)
+                .collect();
+            (StatusCode::OK, Json(labels)).into_response()
+        }
+        Err(e) => grpc_error_response(e),
+    }
+}
+
+async fn change_task_label(
+    state: Arc<AppState>,
+    headers: HeaderMap,
+    input_payload: TaskLabelRequest1,
+    attach: bool,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+    if attach {
+        if let Err(response) = get_usable_label(
+            &state,
+            &mut client,
+            id_and_username.0,
+            input_payload.label_id,
+        )
+        .await
+        {
+            return response;
+        }
+    }
+    let granted = match edit_granted(&state, input_payload.task_id, id_and_username.0).await {
+        Ok(granted) => granted,
+        Err(response) => return response,
+    };
+    let req = tonic::Request::new(proto::TaskLabelRequest {
+        user_id: id_and_username.0,
+        task_id: input_payload.task_id,
+        label_id: input_payload.label_id,
+        granted,
+    });
+    let result = if attach {
+        client.attach_label(req).await
+    } else {
+        client.detach_label(req).await
+    };
+    match result {
+        Ok(_) => (StatusCode::OK).into_response(),
+        Err(e) => grpc_error_response(e),
+    }
+}
+
+async fn attach_label(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<TaskLabelRequest1>,
+) -> Response {
+    change_task_label(state, headers, input_payload, true).await
+}
+
+async fn detach_label(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<TaskLabelRequest1>,
+) -> Response {
+    change_task_label(state, headers, input_payload, false).await
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoardColumnResponse1 {
+    status: String,
+    wip_limit: Option<i64>,
+    tasks: Vec<GetTaskResponse1>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BoardResponse1 {
+    project_id: i64,
+    key: String,
+    columns: Vec<BoardColumnResponse1>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoveTaskRequest1 {
+    task_id: i64,
+    status: String,
+    // The card is placed right after this one; without it, at the top of the column.
+    after_task_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WipLimitRequest1 {
+    status: String,
+    wip_limit: Option<i64>,
+}
+
+async fn board_wip_limits(
+    state: &Arc<AppState>,
+    project_id: i64,
+) -> Result<HashMap<String, i64>, sqlx::Error> {
+    let rows = sqlx::query("SELECT status, wip_limit FROM board_wip_limits WHERE project_id = $1")
+        .bind(project_id)
+        .fetch_all(&state.pool)
+        .await?;
+    let mut limits = HashMap::new();
+    for row in rows {
+        limits.insert(row.try_get("status")?, row.try_get("wip_limit")?);
+    }
+    Ok(limits)
+}
+
+async fn resolve_board_project(
+    state: &Arc<AppState>,
+    key: &str,
+    user_id: i64,
+    for_write: bool,
+) -> Result<i64, Response> {
+    let project_id = match project_id_by_key(state, key).await {
+        Ok(Some(project_id)) => project_id,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Project doesn't exist").into_response()),
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    };
+    check_project_access(state, project_id, user_id, for_write).await?;
+    Ok(project_id)
+}
+
+// The tasks of the project the user may see.
+async fn list_board_tasks(
+    state: &Arc<AppState>,
+    project_id: i64,
+    user_id: i64,
+) -> Result<Vec<proto::Task>, Response> {
+    let viewer = match viewer(state, user_id).await {
+        Ok(viewer) => viewer,
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR).into_response()),
+    };
+    let req = proto::ListBoardTasksRequest {
+        project_id,
+        viewer: Some(viewer),
+    };
+    let mut client = state.tasks_client.clone();
+    match client.list_board_tasks(tonic::Request::new(req)).await {
+        Ok(response) => Ok(response.into_inner().tasks),
+        Err(e) => Err(grpc_error_response(e)),
+    }
+}
+
+async fn get_board(
+    State(state): State<Arc<AppState>>,
+    Path(project): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let project_id = match resolve_board_project(&state, &project, id_and_username.0, false).await {
+        Ok(project_id) => project_id,
+        Err(response) => return response,
+    };
+    let wip_limits = match board_wip_limits(&state, project_id).await {
+        Ok(wip_limits) => wip_limits,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+
+    let mut tasks: Vec<GetTaskResponse1> =
+        match list_board_tasks(&state, project_id, id_and_username.0).await {
+            Ok(tasks) => tasks.into_iter().map(GetTaskResponse1::from).collect(),
+            Err(response) => return response,
+        };
+    fill_task_relations(&state.workflow, &mut tasks);
+    if fill_task_links(&state, &mut tasks).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    }
+
+    // Columns follow the workflow; statuses dropped from the config keep their own column.
+    let mut columns: Vec<BoardColumnResponse1> = state
+        .workflow
+        .statuses
+        .iter()
+        .map(|status| BoardColumnResponse1 {
+            status: status.clone(),
+            wip_limit: wip_limits.get(status).copied(),
+            tasks: vec![],
+        })
+        .collect();
+    for task in tasks {
+        match columns
+            .iter_mut()
+            .find(|column| column.status == task.status)
+        {
+            Some(column) => column.tasks.push(task),
+            None => columns.push(BoardColumnResponse1 {
+                status: task.status.clone(),
+                wip_limit: wip_limits.get(&task.status).copied(),
+                tasks: vec![task],
+            }),
+        }
+    }
+
+    let resp = BoardResponse1 {
+        project_id,
+        key: project,
+        columns,
+    };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+// Changes the column and the position of a card in one step.
+async fn move_board_task(
+    State(state): State<Arc<AppState>>,
+    Path(project): Path<String>,
+    headers: HeaderMap,
+    Json(input_payload): Json<MoveTaskRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let project_id = match resolve_board_project(&state, &project, id_and_username.0, true).await {
+        Ok(project_id) => project_id,
+        Err(response) => return response,
+    };
+
+    let mut client = state.tasks_client.clone();
+    let tasks = match list_board_tasks(&state, project_id, id_and_username.0).await {
+        Ok(tasks) => tasks,
+        Err(response) => return response,
+    };
+    let task = match tasks
+        .iter()
+        .find(|task| task.task_id == input_payload.task_id)
+    {
+        Some(task) => task,
+        None => return (StatusCode::NOT_FOUND, "Task is not on this board").into_response(),
+    };
+
+    let from_status = task.status.clone();
+    let to_status = input_payload.status;
+    if from_status != to_status {
+        let roles = task_roles(task.author_id, task.assignee_id, id_and_username.0);
+        if let Err(e) = state
+            .workflow
+            .check_transition(&from_status, &to_status, &roles)
+        {
+            return transition_error_response(e);
+        }
+    }
+
+    let column: Vec<&proto::Task> = tasks
+        .iter()
+        .filter(|t| t.status == to_status && t.task_id != input_payload.task_id)
+        .collect();
+    let position = match input_payload.after_task_id {
+        Some(after_task_id) => match column.iter().position(|t| t.task_id == after_task_id) {
+            Some(index) => index + 1,
+            None => {
+                return (
+                    StatusCode::NOT_ACCEPTABLE,
+                    "after_task_id is not in the column",
+                )
+                    .into_response();
+            }
+        },
+        None => 0,
+    };
+
+    // Cards that were never moved have no rank yet; they are all at the bottom of the
+    // column, so they get ranks once, in their current order, when one is needed.
+    let mut ranks: Vec<String> = column.iter().map(|t| t.rank.clone()).collect();
+    let mut rank_updates = vec![];
+    let needs_ranks = ranks
+        .iter()
+        .enumerate()
+        .any(|(i, rank)| rank.is_empty() && (i + 1 == position || i == position));
+    if needs_ranks {
+        let mut previous: Option<String> = None;
+        for (i, rank) in ranks.iter_mut().enumerate() {
+            if rank.is_empty() {
+                *rank = rank::between(previous.as_deref(), None);
+                rank_updates.push(proto::TaskRank {
+                    task_id: column[i].task_id,
+                    rank: rank.clone(),
+                });
+            }
+            previous = Some(rank.clone());
+        }
+    }
+    let lower = if position > 0 {
+        Some(ranks[position - 1].as_str())
+    } else {
+        None
+    };
+    let upper = ranks.get(position).map(|rank| rank.as_str());
+    let new_rank = rank::between(lower, upper);
+
+    let wip_limit = match board_wip_limits(&state, project_id).await {
+        Ok(wip_limits) => wip_limits.get(&to_status).copied(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+    // Reordering a column is not worth a notification, moving to another one is.
+    let detail =
+        (from_status != to_status).then(|| format!("from {} to {}", from_status, to_status));
+    let req = proto::MoveTaskRequest {
+        task_id: input_payload.task_id,
+        actor_id: id_and_username.0,
+        project_id,
+        from_status,
+        to_status,
+        rank: new_rank,
+        wip_limit,
+        rank_updates,
+    };
+    if let Err(e) = client.move_task(tonic::Request::new(req)).await {
+        return grpc_error_response(e);
+    }
+    if let Some(detail) = detail {
+        notify_task_watchers(
+            &state,
+            input_payload.task_id,
+            &id_and_username,
+            Event::StatusChanged,
+            Some(&detail),
+        )
+        .await;
+    }
+    (StatusCode::OK).into_response()
+}
+
+async fn set_wip_limit(
+    State(state): State<Arc<AppState>>,
+    Path(project): Path<String>,
+    headers: HeaderMap,
+    Json(input_payload): Json<WipLimitRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+    let project_id = match project_id_by_key(&state, &project).await {
+        Ok(Some(project_id)) => project_id,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Project doesn't exist").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    };
+    if let Err(response) = check_project_owner(&state, project_id, id_and_username.0).await {
+        return response;
+    }
+    if let Err(e) = check_status(&state.workflow, &input_payload.status) {
+        return (StatusCode::NOT_ACCEPTABLE, e).into_response();
+    }
+
+    let query_result =
+        match input_payload.wip_limit {
+            Some(wip_limit) if wip_limit < 1 => {
+                return (StatusCode::NOT_ACCEPTABLE, "WIP limit must be positive").into_response();
+            }
+            Some(wip_limit) => sqlx::query(
+                "INSERT INTO board_wip_limits (project_id, status, wip_limit) VALUES ($1, $2, $3) \
+                 ON CONFLICT (project_id, status) DO UPDATE SET wip_limit = EXCLUDED.wip_limit",
+            )
+            .bind(project_id)
+            .bind(&input_payload.status)
+            .bind(wip_limit)
+            .execute(&state.pool)
+            .await,
+            None => {
+                sqlx::query("DELETE FROM board_wip_limits WHERE project_id = $1 AND status = $2")
+                    .bind(project_id)
+                    .bind(&input_payload.status)
+                    .execute(&state.pool)
+                    .await
+            }
+        };
+    match query_result {
+        Ok(_) => (StatusCode::OK).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR).into_response(),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LikeOrViewRequest1 {
+    task_id: i64,
+}
+
+async fn like(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<LikeOrViewRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+
+    let req = proto::SendLikeOrViewRequest {
+        task_id: input_payload.task_id,
+        liker_id: id_and_username.0,
+    };
+    let request = tonic::Request::new(req);
+    match client.send_like(request).await {
+        Ok(_) => {}
+        Err(e) => {
+            println!("{:?}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+        }
+    };
+    notify_task_watchers(
+        &state,
+        input_payload.task_id,
+        &id_and_username,
+        Event::Liked,
+        None,
+    )
+    .await;
+
+    (StatusCode::OK).into_response()
+}
+
+async fn view(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(input_payload): Json<LikeOrViewRequest1>,
+) -> Response {
+    let id_and_username = match check_authorization(headers).await {
+        CheckAuthorizationResult::IdAndUsername(username) => username,
+        CheckAuthorizationResult::NoToken => {
+            return (StatusCode::UNAUTHORIZED, "Token is missing").into_response();
+        }
+        CheckAuthorizationResult::Invalid => {
+            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+        }
+    };
+
+    let mut client = state.tasks_client.clone();
+
+    let req = proto::SendLikeOrViewRequest {
+        task_id: input_payload.task_id,
+        liker_id: id_and_username.0,
+    };
+    let request = tonic::Request::new(req);
+    match client.send_view(request).await {
         Ok(_) => {}
         Err(_) => {
             return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
@@ -791,17 +7646,17 @@ async fn get_username_by_id(state: &Arc<AppState>, user_id: i64) -> Option<Strin
             None => {
                 eprintln!("234");
                 None
-            },
+            }
         },
         Err(_) => {
             eprintln!("345");
             None
-        },
+        }
     }
 }
 
 async fn most_popular_tasks(
-    State(state): State<Arc<AppState>>,
+    State(_state): State<Arc<AppState>>,
     Json(input_payload): Json<Top5TasksRequest1>,
 ) -> Response {
     let url = "http://stat_service:50052";
@@ -1,5 +1,11 @@
 use dotenv::dotenv;
-use main_service;
+use main_service::attachments::AttachmentLimits;
+use main_service::jobs::{self, JobConfig, JobContext};
+use main_service::notifier;
+use main_service::recurrence::{self, RecurrenceConfig};
+use main_service::storage;
+use main_service::trash::{self, TrashConfig};
+use main_service::workflow::Workflow;
 use std::env;
 
 #[tokio::main]
@@ -26,7 +32,78 @@ async fn main() {
             std::process::exit(1);
         }
     };
-    let app = main_service::create_app(&db_url, false).await;
+    let workflow = match env::var("WORKFLOW_CONFIG") {
+        Ok(path) => match Workflow::load(&path) {
+            Ok(workflow) => workflow,
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        Err(_) => Workflow::default(),
+    };
+    let trash_config = match TrashConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let recurrence_config = match RecurrenceConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let job_config = match JobConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let notifier = match notifier::from_env() {
+        Ok(notifier) => notifier,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let attachment_limits = match AttachmentLimits::from_env() {
+        Ok(limits) => limits,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let storage = match storage::from_env().await {
+        Ok(storage) => storage,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    tokio::spawn(trash::run_purge_job(trash_config, storage.clone()));
+    let job_context = JobContext {
+        pool: main_service::create_pool(&db_url).await,
+        notifier,
+        tasks_client: main_service::tasks_client(),
+    };
+    tokio::spawn(jobs::run_scheduler(job_config, job_context));
+    tokio::spawn(recurrence::run_scheduler(
+        recurrence_config,
+        workflow.clone(),
+    ));
+    let app = main_service::create_app(
+        &db_url,
+        workflow,
+        trash_config,
+        storage,
+        attachment_limits,
+        false,
+    )
+    .await;
 
     let listener = tokio::net::TcpListener::bind(host).await.unwrap();
     axum::serve(listener, app).await.unwrap();
@@ -0,0 +1,112 @@
+use pulldown_cmark::{html, CowStr, Event, Options, Parser};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+// Task text and comments are CommonMark with the GitHub extensions: tables, task lists and
+// strikethrough.
+pub fn options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH
+}
+
+// Only what the Markdown itself produces gets through, and links only to the web, e-mail
+// or pages of the tracker.
+fn sanitizer() -> &'static ammonia::Builder<'static> {
+    static SANITIZER: OnceLock<ammonia::Builder<'static>> = OnceLock::new();
+    SANITIZER.get_or_init(|| {
+        let tags = [
+            "a",
+            "blockquote",
+            "br",
+            "code",
+            "del",
+            "em",
+            "h1",
+            "h2",
+            "h3",
+            "h4",
+            "h5",
+            "h6",
+            "hr",
+            "img",
+            "input",
+            "li",
+            "ol",
+            "p",
+            "pre",
+            "strong",
+            "table",
+            "tbody",
+            "td",
+            "th",
+            "thead",
+            "tr",
+            "ul",
+        ];
+        let tag_attributes = HashMap::from([
+            ("a", HashSet::from(["href", "title"])),
+            ("img", HashSet::from(["src", "alt", "title"])),
+            (
+                "input",
+                HashSet::from(["type", "checked", "disabled", "data-index"]),
+            ),
+            ("ol", HashSet::from(["start"])),
+        ]);
+        let mut builder = ammonia::Builder::empty();
+        builder
+            .tags(HashSet::from(tags))
+            .tag_attributes(tag_attributes)
+            .url_schemes(HashSet::from(["http", "https", "mailto"]))
+            .url_relative(ammonia::UrlRelative::PassThrough)
+            .link_rel(Some("noopener noreferrer nofollow"))
+            .attribute_filter(|element, attribute, value| match (element, attribute) {
+                ("input", "type") if value != "checkbox" => None,
+                _ => Some(value.into()),
+            });
+        builder
+    })
+}
+
+// Sanitised HTML of the Markdown. Raw HTML in the source is shown as text, and the checkboxes
+// of task list items carry their index for `set_task_item`.
+pub fn to_html(markdown: &str) -> String {
+    let mut index = 0;
+    let events = Parser::new_ext(markdown, options()).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        Event::TaskListMarker(checked) => {
+            let checkbox = format!(
+                "<input type=\"checkbox\" disabled=\"\" data-index=\"{}\"{}/>",
+                index,
+                if checked { " checked=\"\"" } else { "" }
+            );
+            index += 1;
+            Event::InlineHtml(CowStr::from(checkbox))
+        }
+        event => event,
+    });
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events);
+    sanitizer().clean(&unsafe_html).to_string()
+}
+
+pub fn task_item_count(markdown: &str) -> usize {
+    Parser::new_ext(markdown, options())
+        .filter(|event| matches!(event, Event::TaskListMarker(_)))
+        .count()
+}
+
+// Checks or unchecks the task list item at `index`, counting from 0 in document order, by
+// editing its `[ ]` in the source; None if there is no such item.
+pub fn set_task_item(markdown: &str, index: usize, checked: bool) -> Option<String> {
+    let range = Parser::new_ext(markdown, options())
+        .into_offset_iter()
+        .filter(|(event, _)| matches!(event, Event::TaskListMarker(_)))
+        .nth(index)?
+        .1;
+    let mark = if checked { "[x]" } else { "[ ]" };
+    Some(format!(
+        "{}{}{}",
+        &markdown[..range.start],
+        mark,
+        &markdown[range.end..]
+    ))
+}
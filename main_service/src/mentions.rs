@@ -0,0 +1,201 @@
+use crate::markdown;
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+use std::collections::HashMap;
+use std::ops::Range;
+
+const MIN_USERNAME_LEN: usize = 2;
+const MAX_USERNAME_LEN: usize = 20;
+
+// Where a mention was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Task,
+    Comment,
+}
+
+impl Source {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Source::Task => "task",
+            Source::Comment => "comment",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mention {
+    pub user_id: i64,
+    pub username: String,
+}
+
+// Where a mentioned user links to.
+pub fn user_link(user_id: i64) -> String {
+    format!("/users/{}", user_id)
+}
+
+fn is_username_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit()
+}
+
+// The `@username`s in Markdown text with their byte ranges, `@` included. Code spans, code
+// blocks, raw HTML and link texts are left out, as are e-mail addresses and escaped `\@`.
+pub fn find(text: &str) -> Vec<(Range<usize>, &str)> {
+    // Parts of the source that are plain text; the parser splits text at some characters,
+    // so neighbouring pieces are joined back.
+    let mut ranges: Vec<Range<usize>> = vec![];
+    let mut code_blocks = 0;
+    let mut links = 0;
+    let parser = Parser::new_ext(text, markdown::options());
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => code_blocks += 1,
+            Event::End(TagEnd::CodeBlock) => code_blocks -= 1,
+            Event::Start(Tag::Link { .. }) => links += 1,
+            Event::End(TagEnd::Link) => links -= 1,
+            Event::Text(_) if code_blocks == 0 && links == 0 => match ranges.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => ranges.push(range),
+            },
+            _ => {}
+        }
+    }
+
+    let mut mentions = vec![];
+    for range in ranges {
+        let segment = &text[range.clone()];
+        for (offset, _) in segment.match_indices('@') {
+            let at = range.start + offset;
+            let preceded = text[..at]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || "_.@\\".contains(c));
+            if preceded {
+                continue;
+            }
+            let rest = &segment[offset + 1..];
+            let len = rest
+                .find(|c: char| !is_username_char(c))
+                .unwrap_or(rest.len());
+            let followed = rest[len..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_');
+            if followed || !(MIN_USERNAME_LEN..=MAX_USERNAME_LEN).contains(&len) {
+                continue;
+            }
+            mentions.push((at..at + 1 + len, &rest[..len]));
+        }
+    }
+    mentions
+}
+
+// The distinct usernames mentioned in the text, in order of first mention.
+pub fn usernames(text: &str) -> Vec<String> {
+    let mut usernames: Vec<String> = vec![];
+    for (_, username) in find(text) {
+        if !usernames.iter().any(|known| known == username) {
+            usernames.push(username.to_string());
+        }
+    }
+    usernames
+}
+
+// The text with the given mentions turned into Markdown links to the users.
+pub fn render(text: &str, mentions: &[Mention]) -> String {
+    let user_ids: HashMap<&str, i64> = mentions
+        .iter()
+        .map(|mention| (mention.username.as_str(), mention.user_id))
+        .collect();
+    let mut rendered = String::with_capacity(text.len());
+    let mut end = 0;
+    for (range, username) in find(text) {
+        if let Some(user_id) = user_ids.get(username) {
+            rendered.push_str(&text[end..range.start]);
+            rendered.push_str(&format!("[@{}]({})", username, user_link(*user_id)));
+            end = range.end;
+        }
+    }
+    rendered.push_str(&text[end..]);
+    rendered
+}
+
+// The registered users among the usernames; the rest are not mentions.
+pub async fn resolve(
+    pool: &Pool<Postgres>,
+    usernames: &[String],
+) -> Result<Vec<Mention>, sqlx::Error> {
+    if usernames.is_empty() {
+        return Ok(vec![]);
+    }
+    let rows = sqlx::query("SELECT id, username FROM users WHERE username = ANY($1) ORDER BY id")
+        .bind(usernames)
+        .fetch_all(pool)
+        .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(Mention {
+                user_id: row.try_get("id")?,
+                username: row.try_get("username")?,
+            })
+        })
+        .collect()
+}
+
+// Replaces the stored mentions of a task's text or a comment and returns the users that
+// weren't mentioned there before.
+pub async fn store(
+    pool: &Pool<Postgres>,
+    source: Source,
+    source_id: i64,
+    task_id: i64,
+    user_ids: &[i64],
+) -> Result<Vec<i64>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query(
+        "DELETE FROM mentions WHERE source = $1 AND source_id = $2 AND NOT user_id = ANY($3)",
+    )
+    .bind(source.as_str())
+    .bind(source_id)
+    .bind(user_ids)
+    .execute(&mut transaction)
+    .await?;
+    let rows = sqlx::query(
+        "INSERT INTO mentions (source, source_id, task_id, user_id) \
+         SELECT $1, $2, $3, unnest($4::bigint[]) ON CONFLICT DO NOTHING RETURNING user_id",
+    )
+    .bind(source.as_str())
+    .bind(source_id)
+    .bind(task_id)
+    .bind(user_ids)
+    .fetch_all(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    rows.iter().map(|row| row.try_get("user_id")).collect()
+}
+
+// The stored mentions of the tasks or comments, by their id.
+pub async fn load(
+    pool: &Pool<Postgres>,
+    source: Source,
+    source_ids: &[i64],
+) -> Result<HashMap<i64, Vec<Mention>>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT m.source_id, u.id, u.username FROM mentions m JOIN users u ON u.id = m.user_id \
+         WHERE m.source = $1 AND m.source_id = ANY($2) ORDER BY m.source_id, u.id",
+    )
+    .bind(source.as_str())
+    .bind(source_ids)
+    .fetch_all(pool)
+    .await?;
+    let mut mentions: HashMap<i64, Vec<Mention>> = HashMap::new();
+    for row in &rows {
+        let source_id: i64 = row.try_get("source_id")?;
+        mentions.entry(source_id).or_default().push(Mention {
+            user_id: row.try_get("id")?,
+            username: row.try_get("username")?,
+        });
+    }
+    Ok(mentions)
+}
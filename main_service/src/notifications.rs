@@ -0,0 +1,409 @@
+use crate::jobs::{self, JobContext, JobOutcome};
+use crate::notifier::Notification;
+use crate::proto;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+
+pub const JOB_KIND: &str = "notification";
+
+// Activity on a task that its watchers are told about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Updated,
+    Assigned,
+    StatusChanged,
+    Commented,
+    Liked,
+    Deleted,
+    Restored,
+    Mentioned,
+}
+
+impl Event {
+    pub const ALL: [Event; 8] = [
+        Event::Updated,
+        Event::Assigned,
+        Event::StatusChanged,
+        Event::Commented,
+        Event::Liked,
+        Event::Deleted,
+        Event::Restored,
+        Event::Mentioned,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Event::Updated => "updated",
+            Event::Assigned => "assigned",
+            Event::StatusChanged => "status_changed",
+            Event::Commented => "commented",
+            Event::Liked => "liked",
+            Event::Deleted => "deleted",
+            Event::Restored => "restored",
+            Event::Mentioned => "mentioned",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Event> {
+        Event::ALL.into_iter().find(|event| event.as_str() == value)
+    }
+
+    fn verb(&self) -> &'static str {
+        match self {
+            Event::Updated => "updated",
+            Event::Assigned => "changed the assignee of",
+            Event::StatusChanged => "moved",
+            Event::Commented => "commented on",
+            Event::Liked => "liked",
+            Event::Deleted => "deleted",
+            Event::Restored => "restored",
+            Event::Mentioned => "mentioned you in",
+        }
+    }
+
+    // Liking a task twice changes nothing, so it is only worth one notification per user.
+    fn once_per_actor(&self) -> bool {
+        matches!(self, Event::Liked)
+    }
+}
+
+pub struct TaskEvent {
+    pub task_id: i64,
+    pub title: String,
+    pub author_id: i64,
+    pub assignee_id: Option<i64>,
+    pub project_id: Option<i64>,
+    pub visibility: String,
+    pub actor_id: i64,
+    pub event: Event,
+    pub message: String,
+}
+
+impl TaskEvent {
+    // `detail` is appended to the message, such as the new status or the start of a comment.
+    pub fn new(
+        task: &proto::GetTaskResponse,
+        actor_id: i64,
+        actor_name: &str,
+        event: Event,
+        detail: Option<&str>,
+    ) -> Self {
+        let mut message = format!("{} {} \"{}\"", actor_name, event.verb(), task.title);
+        if let Some(detail) = detail {
+            message = format!("{}: {}", message, detail);
+        }
+        TaskEvent {
+            task_id: task.task_id,
+            title: task.title.clone(),
+            author_id: task.author_id,
+            assignee_id: task.assignee_id,
+            project_id: task.project_id,
+            visibility: task.visibility.clone(),
+            actor_id,
+            event,
+            message,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotificationRecord {
+    pub notification_id: i64,
+    pub task_id: i64,
+    pub actor_id: i64,
+    pub event: String,
+    pub message: String,
+    pub read: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Preference {
+    pub event: String,
+    // Kept in the notification centre.
+    pub in_app: bool,
+    // Also delivered through the configured notifier, such as a webhook.
+    pub external: bool,
+}
+
+const DEFAULT_PREFERENCE: (bool, bool) = (true, false);
+
+// Authors and assignees watch their tasks unless they have stopped; anyone else watches a
+// task once they start to.
+const WATCHERS: &str = "SELECT user_id FROM task_watchers WHERE task_id = $1 AND watching \
+     UNION SELECT id FROM users WHERE id = ANY($2) AND NOT EXISTS \
+     (SELECT 1 FROM task_watchers w WHERE w.task_id = $1 AND w.user_id = users.id)";
+
+// Recipients `r` who may read the task as it is now, given its visibility as $8, its author as
+// $9, its assignee as $10 and its project as $11; the rule of the Viewer message.
+const READERS: &str = "($8 = 'public' OR r.user_id = $9 OR r.user_id = $10 \
+     OR ($8 = 'team' AND (EXISTS (SELECT 1 FROM team_members own \
+     JOIN team_members other ON other.team_id = own.team_id \
+     WHERE own.user_id = r.user_id AND other.user_id = $9) \
+     OR EXISTS (SELECT 1 FROM project_members WHERE user_id = r.user_id AND project_id = $11))) \
+     OR EXISTS (SELECT 1 FROM task_grants g WHERE g.task_id = $1 AND (g.user_id = r.user_id \
+     OR g.team_id IN (SELECT team_id FROM team_members WHERE user_id = r.user_id))))";
+
+fn implicit_watchers(author_id: i64, assignee_id: Option<i64>) -> Vec<i64> {
+    let mut user_ids = vec![author_id];
+    user_ids.extend(assignee_id);
+    user_ids
+}
+
+pub async fn watchers(
+    pool: &Pool<Postgres>,
+    task_id: i64,
+    author_id: i64,
+    assignee_id: Option<i64>,
+) -> Result<Vec<i64>, sqlx::Error> {
+    let rows = sqlx::query(&format!("{} ORDER BY 1", WATCHERS))
+        .bind(task_id)
+        .bind(implicit_watchers(author_id, assignee_id))
+        .fetch_all(pool)
+        .await?;
+    rows.iter().map(|row| row.try_get(0)).collect()
+}
+
+pub async fn set_watching(
+    pool: &Pool<Postgres>,
+    task_id: i64,
+    user_id: i64,
+    watching: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO task_watchers (task_id, user_id, watching) VALUES ($1, $2, $3) \
+         ON CONFLICT (task_id, user_id) DO UPDATE SET watching = excluded.watching",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .bind(watching)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// Records a notification for every watcher of the task but the one who acted, as their
+// preferences for the event say, and queues the deliveries through the notifier.
+pub async fn publish(pool: &Pool<Postgres>, event: &TaskEvent) -> Result<(), sqlx::Error> {
+    let watchers = implicit_watchers(event.author_id, event.assignee_id);
+    deliver(pool, event, WATCHERS, watchers).await
+}
+
+// The same for the given users instead of the watchers, such as the ones mentioned in a comment.
+pub async fn publish_to(
+    pool: &Pool<Postgres>,
+    event: &TaskEvent,
+    user_ids: &[i64],
+) -> Result<(), sqlx::Error> {
+    let recipients = "SELECT unnest($2::bigint[])";
+    deliver(pool, event, recipients, user_ids.to_vec()).await
+}
+
+// `recipients` is a query of user ids given the task id as $1 and `user_ids` as $2. Those who
+// can't read the task, such as watchers who have lost access to it, get nothing.
+async fn deliver(
+    pool: &Pool<Postgres>,
+    event: &TaskEvent,
+    recipients: &str,
+    user_ids: Vec<i64>,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let rows = sqlx::query(&format!(
+        "SELECT r.user_id, coalesce(p.in_app, $5) AS in_app, coalesce(p.external, $6) AS external \
+         FROM ({}) r (user_id) \
+         LEFT JOIN notification_preferences p ON p.user_id = r.user_id AND p.event = $3 \
+         WHERE r.user_id <> $4 AND NOT ($7 AND EXISTS (SELECT 1 FROM notifications n \
+         WHERE n.user_id = r.user_id AND n.task_id = $1 AND n.actor_id = $4 AND n.event = $3)) \
+         AND {}",
+        recipients, READERS
+    ))
+    .bind(event.task_id)
+    .bind(user_ids)
+    .bind(event.event.as_str())
+    .bind(event.actor_id)
+    .bind(DEFAULT_PREFERENCE.0)
+    .bind(DEFAULT_PREFERENCE.1)
+    .bind(event.event.once_per_actor())
+    .bind(&event.visibility)
+    .bind(event.author_id)
+    .bind(event.assignee_id)
+    .bind(event.project_id)
+    .fetch_all(&mut transaction)
+    .await?;
+    for row in &rows {
+        let user_id: i64 = row.try_get("user_id")?;
+        let in_app: bool = row.try_get("in_app")?;
+        let external: bool = row.try_get("external")?;
+        if in_app {
+            sqlx::query(
+                "INSERT INTO notifications (user_id, task_id, actor_id, event, message) \
+                 VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(user_id)
+            .bind(event.task_id)
+            .bind(event.actor_id)
+            .bind(event.event.as_str())
+            .bind(&event.message)
+            .execute(&mut transaction)
+            .await?;
+        }
+        if external {
+            let notification = Notification {
+                user_id,
+                kind: event.event.as_str().to_string(),
+                task_id: event.task_id,
+                title: event.title.clone(),
+                message: event.message.clone(),
+            };
+            let payload = serde_json::to_value(&notification)
+                .map_err(|e| sqlx::Error::Protocol(e.to_string()))?;
+            jobs::enqueue(&mut transaction, JOB_KIND, &payload, None).await?;
+        }
+    }
+    transaction.commit().await
+}
+
+fn record(row: &sqlx::postgres::PgRow) -> Result<NotificationRecord, sqlx::Error> {
+    Ok(NotificationRecord {
+        notification_id: row.try_get("id")?,
+        task_id: row.try_get("task_id")?,
+        actor_id: row.try_get("actor_id")?,
+        event: row.try_get("event")?,
+        message: row.try_get("message")?,
+        read: row.try_get("read")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+// Newest first; only the unread ones unless `include_read`.
+pub async fn list(
+    pool: &Pool<Postgres>,
+    user_id: i64,
+    include_read: bool,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<NotificationRecord>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, task_id, actor_id, event, message, read_at IS NOT NULL AS read, \
+         to_json(created_at) #>> '{}' AS created_at FROM notifications \
+         WHERE user_id = $1 AND ($2 OR read_at IS NULL) \
+         ORDER BY id DESC OFFSET $3 LIMIT $4",
+    )
+    .bind(user_id)
+    .bind(include_read)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(record).collect()
+}
+
+pub async fn unread_count(pool: &Pool<Postgres>, user_id: i64) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT count(*) AS count FROM notifications \
+         WHERE user_id = $1 AND read_at IS NULL",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    row.try_get("count")
+}
+
+// Marks the notification of the user read or unread; false if there is no such notification.
+pub async fn set_read(
+    pool: &Pool<Postgres>,
+    notification_id: i64,
+    user_id: i64,
+    read: bool,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE notifications SET read_at = CASE WHEN $3 THEN coalesce(read_at, now()) END \
+         WHERE id = $1 AND user_id = $2",
+    )
+    .bind(notification_id)
+    .bind(user_id)
+    .bind(read)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn mark_all_read(pool: &Pool<Postgres>, user_id: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE notifications SET read_at = now() WHERE user_id = $1 AND read_at IS NULL",
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+// The preferences of the user for every event, defaults included.
+pub async fn preferences(
+    pool: &Pool<Postgres>,
+    user_id: i64,
+) -> Result<Vec<Preference>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT event, in_app, external FROM notification_preferences WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    let mut stored = std::collections::HashMap::new();
+    for row in &rows {
+        let event: String = row.try_get("event")?;
+        let in_app: bool = row.try_get("in_app")?;
+        let external: bool = row.try_get("external")?;
+        stored.insert(event, (in_app, external));
+    }
+    Ok(Event::ALL
+        .iter()
+        .map(|event| {
+            let (in_app, external) = stored
+                .get(event.as_str())
+                .copied()
+                .unwrap_or(DEFAULT_PREFERENCE);
+            Preference {
+                event: event.as_str().to_string(),
+                in_app,
+                external,
+            }
+        })
+        .collect())
+}
+
+// Changes the given settings; the ones left out keep their current value.
+pub async fn set_preference(
+    pool: &Pool<Postgres>,
+    user_id: i64,
+    event: Event,
+    in_app: Option<bool>,
+    external: Option<bool>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO notification_preferences (user_id, event, in_app, external) \
+         VALUES ($1, $2, coalesce($3, $5), coalesce($4, $6)) \
+         ON CONFLICT (user_id, event) DO UPDATE SET \
+         in_app = coalesce($3, notification_preferences.in_app), \
+         external = coalesce($4, notification_preferences.external)",
+    )
+    .bind(user_id)
+    .bind(event.as_str())
+    .bind(in_app)
+    .bind(external)
+    .bind(DEFAULT_PREFERENCE.0)
+    .bind(DEFAULT_PREFERENCE.1)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn run_job(
+    context: &mut JobContext,
+    payload: &serde_json::Value,
+) -> Result<JobOutcome, String> {
+    let notification: Notification =
+        serde_json::from_value(payload.clone()).map_err(|e| e.to_string())?;
+    context.notifier.notify(&notification).await?;
+    Ok(JobOutcome::Done)
+}
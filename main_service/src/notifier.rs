@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{env, sync::Arc, time::Duration};
+
+// Something a user should hear about, such as a reminder of a task.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub user_id: i64,
+    pub kind: String,
+    pub task_id: i64,
+    pub title: String,
+    pub message: String,
+}
+
+// Delivers notifications to users. Delivery happens from background jobs, which run at least
+// once, so a notifier may see the same notification twice.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<(), String>;
+}
+
+// Picks the notifier by `NOTIFIER`: `log` (default, prints to stdout) or `webhook` (POSTs each
+// notification as JSON to `NOTIFIER_WEBHOOK_URL`).
+pub fn from_env() -> Result<Arc<dyn Notifier>, String> {
+    match env::var("NOTIFIER").as_deref() {
+        Ok("log") | Err(_) => Ok(Arc::new(LogNotifier)),
+        Ok("webhook") => {
+            let url = env::var("NOTIFIER_WEBHOOK_URL")
+                .map_err(|_| "NOTIFIER_WEBHOOK_URL not set".to_string())?;
+            Ok(Arc::new(WebhookNotifier::new(url)))
+        }
+        Ok(other) => Err(format!(
+            "Unknown NOTIFIER {}, must be log or webhook",
+            other
+        )),
+    }
+}
+
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), String> {
+        println!(
+            "Notification for user {}: {}",
+            notification.user_id, notification.message
+        );
+        Ok(())
+    }
+}
+
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String) -> Self {
+        // A hanging endpoint must not stall the job scheduler.
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+        WebhookNotifier { client, url }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), String> {
+        let body = serde_json::to_vec(notification).map_err(|e| e.to_string())?;
+        let response = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Webhook answered {}", response.status()));
+        }
+        Ok(())
+    }
+}
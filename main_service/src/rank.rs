@@ -0,0 +1,38 @@
+// Lexicographic ranks for manual ordering of board columns. A new rank is always
+// generated strictly between its neighbours, so moving a card never renumbers others.
+// Generated ranks never end with the smallest digit, which keeps room before any rank.
+
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+const BASE: usize = DIGITS.len();
+
+fn digit(c: u8) -> usize {
+    DIGITS.iter().position(|d| *d == c).unwrap_or(0)
+}
+
+// Returns a rank greater than `lower` and less than `upper`; `None` means unbounded.
+pub fn between(lower: Option<&str>, upper: Option<&str>) -> String {
+    let lower = lower.unwrap_or("").as_bytes();
+    let mut upper = upper.map(|upper| upper.as_bytes());
+    let mut rank = Vec::new();
+    let mut i = 0;
+    loop {
+        let lo = lower.get(i).map_or(0, |c| digit(*c));
+        let hi = match upper.and_then(|upper| upper.get(i)) {
+            Some(c) => digit(*c),
+            None => {
+                upper = None;
+                BASE
+            }
+        };
+        if hi > lo + 1 {
+            rank.push(DIGITS[(lo + hi) / 2]);
+            return String::from_utf8(rank).unwrap();
+        }
+        rank.push(DIGITS[lo]);
+        if hi > lo {
+            // Already below `upper` at this position, so it no longer bounds the rest.
+            upper = None;
+        }
+        i += 1;
+    }
+}
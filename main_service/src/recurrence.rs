@@ -0,0 +1,126 @@
+use crate::positive_env;
+use crate::proto::{self, task_service_client::TaskServiceClient};
+use crate::rrule::{self, Rule};
+use crate::tasks_client;
+use crate::workflow::Workflow;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tonic::transport::Channel;
+
+// How often the scheduler looks for series whose next occurrence is due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecurrenceConfig {
+    pub interval: Duration,
+}
+
+impl Default for RecurrenceConfig {
+    fn default() -> Self {
+        RecurrenceConfig {
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RecurrenceConfig {
+    // Reads `RECURRENCE_INTERVAL_SECONDS`; unset keeps the default.
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = RecurrenceConfig::default();
+        if let Some(seconds) = positive_env("RECURRENCE_INTERVAL_SECONDS")? {
+            config.interval = Duration::from_secs(seconds);
+        }
+        Ok(config)
+    }
+}
+
+pub fn done_statuses(workflow: &Workflow) -> Vec<String> {
+    workflow
+        .statuses
+        .iter()
+        .filter(|status| workflow.is_done(status))
+        .cloned()
+        .collect()
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| e.to_string())
+}
+
+// Creates the next occurrence of a series that is due and moves the series on to the one after.
+// When occurrences were missed, only the latest one that has started is created.
+pub async fn advance(
+    client: &mut TaskServiceClient<Channel>,
+    workflow: &Workflow,
+    series: &proto::TaskSeries,
+    now: DateTime<Utc>,
+) -> Result<i64, String> {
+    let Some(next_at) = &series.next_at else {
+        return Err("The series has ended".to_string());
+    };
+    let rule: Rule = series.rrule.parse()?;
+    let tz = rrule::parse_timezone(&series.timezone)?;
+    let start = parse_time(&series.dtstart)?.with_timezone(&tz);
+    let expected = parse_time(next_at)?;
+    let occurrences = rule.occurrences(start).skip_while(|time| *time < expected);
+    let (occurrence, following) =
+        rrule::next_due(occurrences, now).ok_or("The rule has no more occurrences")?;
+    let req = proto::MaterializeOccurrenceRequest {
+        series_id: series.series_id,
+        expected_next_at: next_at.clone(),
+        occurrence_at: occurrence.to_rfc3339(),
+        next_at: following.map(|time| time.to_rfc3339()),
+        status: workflow.initial_status.clone(),
+    };
+    match client
+        .materialize_occurrence(tonic::Request::new(req))
+        .await
+    {
+        Ok(response) => Ok(response.into_inner().task_id),
+        Err(e) => Err(e.message().to_string()),
+    }
+}
+
+// Advances the due series, all of them or only `series_id`, and returns the created tasks.
+pub async fn advance_due(
+    client: &mut TaskServiceClient<Channel>,
+    workflow: &Workflow,
+    series_id: Option<i64>,
+) -> Result<Vec<i64>, String> {
+    let req = proto::ListDueSeriesRequest {
+        done_statuses: done_statuses(workflow),
+        series_id,
+    };
+    let due = match client.list_due_series(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner().series,
+        Err(e) => return Err(e.message().to_string()),
+    };
+    let mut task_ids = vec![];
+    for series in &due {
+        match advance(client, workflow, series, Utc::now()).await {
+            Ok(task_id) => task_ids.push(task_id),
+            Err(e) => println!(
+                "Failed to create the next occurrence of series {}: {}",
+                series.series_id, e
+            ),
+        }
+    }
+    Ok(task_ids)
+}
+
+pub async fn run_scheduler(config: RecurrenceConfig, workflow: Workflow) {
+    let mut client = tasks_client();
+    let mut interval = tokio::time::interval(config.interval);
+    loop {
+        interval.tick().await;
+        match advance_due(&mut client, &workflow, None).await {
+            Ok(task_ids) if task_ids.is_empty() => {}
+            Ok(task_ids) => println!(
+                "Created {} occurrences of recurring tasks: {:?}",
+                task_ids.len(),
+                task_ids
+            ),
+            Err(e) => println!("Failed to look for due recurring tasks: {}", e),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use crate::jobs::{self, JobContext, JobOutcome};
+use crate::notifier::Notification;
+use crate::proto;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+
+pub const JOB_KIND: &str = "reminder";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Reminder {
+    pub reminder_id: i64,
+    pub task_id: i64,
+    pub remind_at: Option<String>,
+    pub minutes_before_due: Option<i64>,
+    // pending, sent or failed.
+    pub status: String,
+    pub sent_at: Option<String>,
+    pub created_at: String,
+}
+
+// Timestamps are read through JSON, which gives them in RFC 3339.
+const REMINDER_COLUMNS: &str = "r.id, r.task_id, to_json(r.remind_at) #>> '{}' AS remind_at, \
+     r.minutes_before_due, to_json(r.sent_at) #>> '{}' AS sent_at, \
+     to_json(r.created_at) #>> '{}' AS created_at, j.status AS job_status";
+
+fn reminder(row: &sqlx::postgres::PgRow) -> Result<Reminder, sqlx::Error> {
+    let sent_at: Option<String> = row.try_get("sent_at")?;
+    let job_status: Option<String> = row.try_get("job_status")?;
+    let status = match (&sent_at, job_status.as_deref()) {
+        (Some(_), _) => "sent",
+        (None, Some("failed")) => "failed",
+        _ => "pending",
+    };
+    Ok(Reminder {
+        reminder_id: row.try_get("id")?,
+        task_id: row.try_get("task_id")?,
+        remind_at: row.try_get("remind_at")?,
+        minutes_before_due: row.try_get("minutes_before_due")?,
+        status: status.to_string(),
+        sent_at,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+// Adds a reminder of the task for the user together with the job that sends it.
+pub async fn create(
+    pool: &Pool<Postgres>,
+    task_id: i64,
+    user_id: i64,
+    remind_at: Option<&str>,
+    minutes_before_due: Option<i64>,
+    fire_at: &str,
+) -> Result<Reminder, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let row = sqlx::query(
+        "INSERT INTO reminders (task_id, user_id, remind_at, minutes_before_due) \
+         VALUES ($1, $2, $3::timestamptz, $4) RETURNING id",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .bind(remind_at)
+    .bind(minutes_before_due)
+    .fetch_one(&mut transaction)
+    .await?;
+    let reminder_id: i64 = row.try_get("id")?;
+    let payload = serde_json::json!({ "reminder_id": reminder_id });
+    let job_id = jobs::enqueue(&mut transaction, JOB_KIND, &payload, Some(fire_at)).await?;
+    sqlx::query("UPDATE reminders SET job_id = $2 WHERE id = $1")
+        .bind(reminder_id)
+        .bind(job_id)
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    get(pool, reminder_id)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)
+}
+
+pub async fn get(pool: &Pool<Postgres>, reminder_id: i64) -> Result<Option<Reminder>, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM reminders r LEFT JOIN jobs j ON j.id = r.job_id WHERE r.id = $1",
+        REMINDER_COLUMNS
+    ))
+    .bind(reminder_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(reminder).transpose()
+}
+
+pub async fn list(
+    pool: &Pool<Postgres>,
+    task_id: i64,
+    user_id: i64,
+) -> Result<Vec<Reminder>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM reminders r LEFT JOIN jobs j ON j.id = r.job_id \
+         WHERE r.task_id = $1 AND r.user_id = $2 ORDER BY r.id",
+        REMINDER_COLUMNS
+    ))
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(reminder).collect()
+}
+
+// Deletes the reminder of the user and its job if it hasn't run; false if there is no such reminder.
+pub async fn delete(
+    pool: &Pool<Postgres>,
+    reminder_id: i64,
+    user_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let row = sqlx::query("DELETE FROM reminders WHERE id = $1 AND user_id = $2 RETURNING job_id")
+        .bind(reminder_id)
+        .bind(user_id)
+        .fetch_optional(&mut transaction)
+        .await?;
+    let Some(row) = row else {
+        return Ok(false);
+    };
+    let job_id: Option<i64> = row.try_get("job_id")?;
+    sqlx::query("DELETE FROM jobs WHERE id = $1 AND status = 'pending'")
+        .bind(job_id)
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
+pub async fn count(pool: &Pool<Postgres>, task_id: i64, user_id: i64) -> Result<i64, sqlx::Error> {
+    let row =
+        sqlx::query("SELECT count(*) AS count FROM reminders WHERE task_id = $1 AND user_id = $2")
+            .bind(task_id)
+            .bind(user_id)
+            .fetch_one(pool)
+            .await?;
+    row.try_get("count")
+}
+
+// The due date of the task has changed: the jobs of its unsent reminders relative to the due
+// date run now, work out their new time and wait for it again.
+pub async fn due_date_changed(pool: &Pool<Postgres>, task_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE jobs SET status = 'pending', attempts = 0, run_at = now(), updated_at = now() \
+         FROM reminders r WHERE r.job_id = jobs.id AND r.task_id = $1 \
+         AND r.minutes_before_due IS NOT NULL AND r.sent_at IS NULL",
+    )
+    .bind(task_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// When a reminder fires: at its time, or the given number of minutes before the due date.
+pub fn fire_at(
+    remind_at: Option<&str>,
+    minutes_before_due: Option<i64>,
+    due_date: Option<&str>,
+) -> Option<DateTime<Utc>> {
+    let parse = |value: &str| {
+        DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|time| time.with_timezone(&Utc))
+    };
+    match (remind_at, minutes_before_due) {
+        (Some(remind_at), _) => parse(remind_at),
+        (None, Some(minutes)) => Some(parse(due_date?)? - Duration::minutes(minutes)),
+        (None, None) => None,
+    }
+}
+
+pub async fn run_job(
+    context: &mut JobContext,
+    payload: &serde_json::Value,
+) -> Result<JobOutcome, String> {
+    let reminder_id = payload["reminder_id"]
+        .as_i64()
+        .ok_or("The job has no reminder_id")?;
+    let row = sqlx::query(
+        "SELECT task_id, user_id, to_json(remind_at) #>> '{}' AS remind_at, minutes_before_due, \
+         sent_at IS NOT NULL AS sent FROM reminders WHERE id = $1",
+    )
+    .bind(reminder_id)
+    .fetch_optional(&context.pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    // Deleted or already delivered.
+    let Some(row) = row else {
+        return Ok(JobOutcome::Done);
+    };
+    if row.try_get::<bool, _>("sent").map_err(|e| e.to_string())? {
+        return Ok(JobOutcome::Done);
+    }
+    let task_id: i64 = row.try_get("task_id").map_err(|e| e.to_string())?;
+    let user_id: i64 = row.try_get("user_id").map_err(|e| e.to_string())?;
+    let remind_at: Option<String> = row.try_get("remind_at").map_err(|e| e.to_string())?;
+    let minutes_before_due: Option<i64> = row
+        .try_get("minutes_before_due")
+        .map_err(|e| e.to_string())?;
+
+    let req = proto::GetTaskRequest {
+        task_id,
+        ..Default::default()
+    };
+    let task = match context
+        .tasks_client
+        .get_task(tonic::Request::new(req))
+        .await
+    {
+        Ok(response) => response.into_inner(),
+        // Nothing to remind of once the task is gone.
+        Err(e) if e.code() == tonic::Code::NotFound => return Ok(JobOutcome::Done),
+        Err(e) => return Err(e.message().to_string()),
+    };
+    // A reminder relative to a due date that has been removed waits for a new one.
+    let Some(fire_at) = fire_at(
+        remind_at.as_deref(),
+        minutes_before_due,
+        task.due_date.as_deref(),
+    ) else {
+        return Ok(JobOutcome::Done);
+    };
+    if fire_at > Utc::now() {
+        return Ok(JobOutcome::RunAt(fire_at.to_rfc3339()));
+    }
+
+    let message = match &task.due_date {
+        Some(due_date) => format!("Reminder: \"{}\" is due {}", task.title, due_date),
+        None => format!("Reminder: \"{}\"", task.title),
+    };
+    let notification = Notification {
+        user_id,
+        kind: JOB_KIND.to_string(),
+        task_id,
+        title: task.title,
+        message,
+    };
+    context.notifier.notify(&notification).await?;
+    sqlx::query("UPDATE reminders SET sent_at = now() WHERE id = $1")
+        .bind(reminder_id)
+        .execute(&context.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(JobOutcome::Done)
+}
@@ -0,0 +1,382 @@
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, Months, NaiveDate, NaiveDateTime, NaiveTime,
+    TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use std::{collections::VecDeque, str::FromStr};
+
+// The subset of iCalendar (RFC 5545) recurrence rules that tasks can repeat on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+// A day of BYDAY. Monthly rules can pick its place in the month: `1` is the first one, `-1` the last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByDay {
+    pub nth: Option<i32>,
+    pub weekday: Weekday,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Until {
+    // The last day on which an occurrence may start, in the timezone of the series.
+    Date(NaiveDate),
+    Time(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<ByDay>,
+    pub by_month_day: Vec<i32>,
+    pub count: Option<u32>,
+    pub until: Option<Until>,
+    pub week_start: Weekday,
+}
+
+const MAX_INTERVAL: u32 = 1000;
+const MAX_COUNT: u32 = 10000;
+// A rule that can't produce anything for this many periods in a row never will.
+const MAX_EMPTY_PERIODS: u32 = 1000;
+
+fn parse_weekday(value: &str) -> Result<Weekday, String> {
+    match value {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => Err(format!("Invalid weekday {}", value)),
+    }
+}
+
+fn parse_by_day(value: &str) -> Result<ByDay, String> {
+    // The weekday is the last two characters, which need not be single bytes.
+    let split = value
+        .char_indices()
+        .rev()
+        .nth(1)
+        .map_or(0, |(index, _)| index);
+    let (nth, weekday) = value.split_at(split);
+    let weekday = parse_weekday(weekday)?;
+    if nth.is_empty() {
+        return Ok(ByDay { nth: None, weekday });
+    }
+    match nth.parse::<i32>() {
+        Ok(nth) if (1..=5).contains(&nth.abs()) => Ok(ByDay {
+            nth: Some(nth),
+            weekday,
+        }),
+        _ => Err(format!("Invalid BYDAY value {}", value)),
+    }
+}
+
+fn parse_until(value: &str) -> Result<Until, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return Ok(Until::Date(date));
+    }
+    match NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ") {
+        Ok(time) => Ok(Until::Time(time.and_utc())),
+        Err(_) => {
+            Err("UNTIL must be a date (YYYYMMDD) or a UTC time (YYYYMMDDTHHMMSSZ)".to_string())
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    // Parses the value of an RRULE property, with or without the `RRULE:` prefix.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim().to_ascii_uppercase();
+        let value = value.strip_prefix("RRULE:").unwrap_or(&value);
+        let mut frequency = None;
+        let mut rule = Rule {
+            frequency: Frequency::Daily,
+            interval: 1,
+            by_day: vec![],
+            by_month_day: vec![],
+            count: None,
+            until: None,
+            week_start: Weekday::Mon,
+        };
+        let mut seen = vec![];
+        for part in value.split(';') {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid RRULE part {}", part))?;
+            if seen.contains(&name) {
+                return Err(format!("{} is given more than once", name));
+            }
+            seen.push(name);
+            match name {
+                "FREQ" => {
+                    frequency = Some(match value {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        _ => return Err("FREQ must be DAILY, WEEKLY or MONTHLY".to_string()),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|i| (1..=MAX_INTERVAL).contains(i))
+                        .ok_or(format!("INTERVAL must be between 1 and {}", MAX_INTERVAL))?
+                }
+                "COUNT" => {
+                    rule.count = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|c| (1..=MAX_COUNT).contains(c))
+                            .ok_or(format!("COUNT must be between 1 and {}", MAX_COUNT))?,
+                    )
+                }
+                "UNTIL" => rule.until = Some(parse_until(value)?),
+                "BYDAY" => {
+                    rule.by_day = value
+                        .split(',')
+                        .map(parse_by_day)
+                        .collect::<Result<_, _>>()?
+                }
+                "BYMONTHDAY" => {
+                    rule.by_month_day = value
+                        .split(',')
+                        .map(|day| {
+                            day.parse()
+                                .ok()
+                                .filter(|day: &i32| *day != 0 && (-31..=31).contains(day))
+                                .ok_or(format!("Invalid BYMONTHDAY value {}", day))
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                "WKST" => rule.week_start = parse_weekday(value)?,
+                _ => return Err(format!("{} is not supported", name)),
+            }
+        }
+        rule.frequency = frequency.ok_or("FREQ is required")?;
+        if rule.count.is_some() && rule.until.is_some() {
+            return Err("COUNT and UNTIL can't be used together".to_string());
+        }
+        if rule.frequency != Frequency::Monthly && rule.by_day.iter().any(|d| d.nth.is_some()) {
+            return Err("Numbered BYDAY values need FREQ=MONTHLY".to_string());
+        }
+        if rule.frequency == Frequency::Weekly && !rule.by_month_day.is_empty() {
+            return Err("BYMONTHDAY can't be used with FREQ=WEEKLY".to_string());
+        }
+        Ok(rule)
+    }
+}
+
+pub fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.parse()
+        .map_err(|_| format!("Unknown timezone {}", name))
+}
+
+// The instant a wall-clock time in `tz` stands for. Ambiguous times take the earlier offset,
+// and times skipped by a DST change move forward by the gap.
+pub fn localize(tz: Tz, time: NaiveDateTime) -> Option<DateTime<Tz>> {
+    match tz.from_local_datetime(&time) {
+        LocalResult::Single(time) => Some(time),
+        LocalResult::Ambiguous(earliest, _) => Some(earliest),
+        LocalResult::None => tz
+            .from_local_datetime(&(time + Duration::hours(1)))
+            .earliest(),
+    }
+}
+
+// Reads an RFC 3339 timestamp, or a local date and time (or a date, at midnight) in `tz`.
+pub fn parse_start(value: &str, tz: Tz) -> Result<DateTime<Tz>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&tz));
+    }
+    let local = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_time(NaiveTime::MIN))
+        });
+    local.and_then(|local| localize(tz, local)).ok_or(
+        "Start must be an RFC 3339 timestamp, a local YYYY-MM-DDTHH:MM time or a YYYY-MM-DD date"
+            .to_string(),
+    )
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let next = first + Months::new(1);
+    (next - first).num_days() as u32
+}
+
+impl ByDay {
+    fn matches(&self, date: NaiveDate) -> bool {
+        if date.weekday() != self.weekday {
+            return false;
+        }
+        match self.nth {
+            None => true,
+            Some(nth) if nth > 0 => (date.day() as i32 - 1) / 7 + 1 == nth,
+            Some(nth) => {
+                let days = days_in_month(date.year(), date.month()) as i32;
+                (days - date.day() as i32) / 7 + 1 == -nth
+            }
+        }
+    }
+}
+
+impl Rule {
+    fn matches_month_day(&self, date: NaiveDate) -> bool {
+        let days = days_in_month(date.year(), date.month()) as i32;
+        let day = date.day() as i32;
+        self.by_month_day
+            .iter()
+            .any(|d| *d == day || days + 1 + *d == day)
+    }
+
+    fn matches(&self, date: NaiveDate) -> bool {
+        (self.by_day.is_empty() || self.by_day.iter().any(|d| d.matches(date)))
+            && (self.by_month_day.is_empty() || self.matches_month_day(date))
+    }
+
+    // Dates of the `period`-th day, week or month of the rule counting from the one of `start`.
+    fn period_dates(&self, start: NaiveDate, period: u32) -> Option<Vec<NaiveDate>> {
+        let step = period.checked_mul(self.interval)?;
+        match self.frequency {
+            Frequency::Daily => {
+                let date = start.checked_add_signed(Duration::days(step as i64))?;
+                Some(
+                    vec![date]
+                        .into_iter()
+                        .filter(|d| self.matches(*d))
+                        .collect(),
+                )
+            }
+            Frequency::Weekly => {
+                let offset = |weekday: Weekday| {
+                    (weekday.num_days_from_monday() + 7 - self.week_start.num_days_from_monday())
+                        % 7
+                };
+                let week = start
+                    .checked_sub_signed(Duration::days(offset(start.weekday()) as i64))?
+                    .checked_add_signed(Duration::weeks(step as i64))?;
+                let dates = (0..7).map(|day| week + Duration::days(day));
+                Some(if self.by_day.is_empty() {
+                    dates.filter(|d| d.weekday() == start.weekday()).collect()
+                } else {
+                    dates.filter(|d| self.matches(*d)).collect()
+                })
+            }
+            Frequency::Monthly => {
+                let month = start.with_day(1)?.checked_add_months(Months::new(step))?;
+                let days = days_in_month(month.year(), month.month());
+                let dates = (1..=days).filter_map(|day| month.with_day(day));
+                Some(if self.by_day.is_empty() && self.by_month_day.is_empty() {
+                    // Months without the day of the start are skipped.
+                    dates.filter(|d| d.day() == start.day()).collect()
+                } else {
+                    dates.filter(|d| self.matches(*d)).collect()
+                })
+            }
+        }
+    }
+
+    // Starts of the occurrences from `start` on, in order. Each one keeps the wall-clock time of
+    // `start` in its timezone, across DST changes too.
+    pub fn occurrences(&self, start: DateTime<Tz>) -> Occurrences<'_> {
+        Occurrences {
+            rule: self,
+            start,
+            period: 0,
+            pending: VecDeque::new(),
+            emitted: 0,
+            empty_periods: 0,
+            finished: false,
+        }
+    }
+}
+
+pub struct Occurrences<'a> {
+    rule: &'a Rule,
+    start: DateTime<Tz>,
+    period: u32,
+    pending: VecDeque<DateTime<Tz>>,
+    emitted: u32,
+    empty_periods: u32,
+    finished: bool,
+}
+
+impl Iterator for Occurrences<'_> {
+    type Item = DateTime<Utc>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            if self.rule.count.is_some_and(|count| self.emitted >= count) {
+                break;
+            }
+            if let Some(time) = self.pending.pop_front() {
+                let past_until = match self.rule.until {
+                    Some(Until::Date(date)) => time.date_naive() > date,
+                    Some(Until::Time(until)) => time > until,
+                    None => false,
+                };
+                if past_until {
+                    break;
+                }
+                self.emitted += 1;
+                return Some(time.with_timezone(&Utc));
+            }
+            if self.empty_periods >= MAX_EMPTY_PERIODS {
+                break;
+            }
+            let tz = self.start.timezone();
+            let local = self.start.naive_local();
+            let dates = match self.rule.period_dates(local.date(), self.period) {
+                Some(dates) => dates,
+                None => break,
+            };
+            self.period += 1;
+            let starts: Vec<_> = dates
+                .into_iter()
+                .filter_map(|date| localize(tz, date.and_time(local.time())))
+                .filter(|time| *time >= self.start)
+                .collect();
+            self.empty_periods = if starts.is_empty() {
+                self.empty_periods + 1
+            } else {
+                0
+            };
+            self.pending.extend(starts);
+        }
+        self.finished = true;
+        None
+    }
+}
+
+// Picks the occurrence to create next out of `occurrences`: the latest one that has already
+// started by `now`, so that missed ones are skipped, or the first one if none has. Returns it
+// together with the occurrence after it.
+pub fn next_due(
+    mut occurrences: impl Iterator<Item = DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Option<(DateTime<Utc>, Option<DateTime<Utc>>)> {
+    let mut occurrence = occurrences.next()?;
+    let mut following = occurrences.next();
+    while let Some(time) = following {
+        if time > now {
+            break;
+        }
+        occurrence = time;
+        following = occurrences.next();
+    }
+    Some((occurrence, following))
+}
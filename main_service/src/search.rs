@@ -0,0 +1,48 @@
+// Translates a search box query into a Postgres `tsquery`:
+//   rotate keys      tasks mentioning both words
+//   "rotate keys"    the words next to each other, in this order
+//   cert*            any word starting with `cert`
+//   -staging         tasks not mentioning the word
+// Anything but letters and digits separates words, so user input can't inject operators.
+pub fn to_tsquery(query: &str) -> Option<String> {
+    let mut terms = vec![];
+    let mut has_positive = false;
+    // Text between quotes (at odd positions after splitting) is a phrase.
+    for (i, segment) in query.split('"').enumerate() {
+        let tokens: Vec<&str> = if i % 2 == 1 {
+            vec![segment]
+        } else {
+            segment.split_whitespace().collect()
+        };
+        for token in tokens {
+            let negated = i % 2 == 0 && token.starts_with('-');
+            let prefix = i % 2 == 0 && token.ends_with('*');
+            let words: Vec<String> = token
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(|word| word.to_lowercase())
+                .collect();
+            let mut term = match words.len() {
+                0 => continue,
+                1 => words[0].clone(),
+                _ => format!("({})", words.join(" <-> ")),
+            };
+            if prefix {
+                term = match words.len() {
+                    1 => format!("{}:*", term),
+                    _ => format!("{}:*)", &term[..term.len() - 1]),
+                };
+            }
+            if negated {
+                terms.push(format!("!{}", term));
+            } else {
+                has_positive = true;
+                terms.push(term);
+            }
+        }
+    }
+    if !has_positive {
+        return None;
+    }
+    Some(terms.join(" & "))
+}
@@ -0,0 +1,227 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{stream::BoxStream, StreamExt, TryStreamExt};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio_util::io::ReaderStream;
+
+pub type BlobStream = BoxStream<'static, io::Result<Bytes>>;
+
+// Content-addressed storage of attachment contents: every blob is keyed by its SHA-256, so
+// identical files uploaded to different tasks are stored once.
+#[async_trait]
+pub trait BlobStorage: Send + Sync {
+    async fn exists(&self, sha256: &str) -> io::Result<bool>;
+    // Stores the contents of the local file `path` of `size` bytes. The file may be moved away.
+    async fn put(&self, sha256: &str, path: &Path, size: u64) -> io::Result<()>;
+    async fn get(&self, sha256: &str) -> io::Result<BlobStream>;
+    // Deleting a missing blob is not an error.
+    async fn delete(&self, sha256: &str) -> io::Result<()>;
+}
+
+// Picks the backend by `STORAGE_BACKEND`: `local` (default, files under `ATTACHMENTS_DIR`)
+// or `s3` (any S3-compatible service, configured by the `S3_*` variables).
+pub async fn from_env() -> Result<Arc<dyn BlobStorage>, String> {
+    match env::var("STORAGE_BACKEND").as_deref() {
+        Ok("local") | Err(_) => {
+            let root = env::var("ATTACHMENTS_DIR").unwrap_or_else(|_| "attachments".to_string());
+            Ok(Arc::new(LocalStorage::new(root)))
+        }
+        Ok("s3") => {
+            let var = |name: &str| env::var(name).map_err(|_| format!("{} not set", name));
+            let storage = S3Storage {
+                client: reqwest::Client::new(),
+                endpoint: var("S3_ENDPOINT")?.trim_end_matches('/').to_string(),
+                bucket: var("S3_BUCKET")?,
+                region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                access_key: var("S3_ACCESS_KEY")?,
+                secret_key: var("S3_SECRET_KEY")?,
+            };
+            storage
+                .create_bucket()
+                .await
+                .map_err(|e| format!("Failed to create the S3 bucket: {}", e))?;
+            Ok(Arc::new(storage))
+        }
+        Ok(other) => Err(format!("Unknown STORAGE_BACKEND {}", other)),
+    }
+}
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    // Blobs are spread over 256 directories by the first byte of the hash.
+    fn path(&self, sha256: &str) -> PathBuf {
+        self.root.join(&sha256[..2]).join(sha256)
+    }
+}
+
+#[async_trait]
+impl BlobStorage for LocalStorage {
+    async fn exists(&self, sha256: &str) -> io::Result<bool> {
+        tokio::fs::try_exists(self.path(sha256)).await
+    }
+
+    async fn put(&self, sha256: &str, path: &Path, _size: u64) -> io::Result<()> {
+        let target = self.path(sha256);
+        tokio::fs::create_dir_all(target.parent().unwrap()).await?;
+        // Renaming fails across filesystems, copying always works.
+        if tokio::fs::rename(path, &target).await.is_err() {
+            let partial = target.with_extension("partial");
+            tokio::fs::copy(path, &partial).await?;
+            tokio::fs::rename(&partial, &target).await?;
+        }
+        Ok(())
+    }
+
+    async fn get(&self, sha256: &str) -> io::Result<BlobStream> {
+        let file = tokio::fs::File::open(self.path(sha256)).await?;
+        Ok(ReaderStream::new(file).boxed())
+    }
+
+    async fn delete(&self, sha256: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(sha256)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Talks to S3 or a compatible service (MinIO and the like) with path-style URLs and
+// Signature Version 4. Payloads are not signed so that uploads can be streamed.
+pub struct S3Storage {
+    client: reqwest::Client,
+    endpoint: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Storage {
+    async fn create_bucket(&self) -> io::Result<()> {
+        let response = self.request(reqwest::Method::PUT, None).send().await;
+        match response.map_err(io::Error::other)?.status().as_u16() {
+            // 409 means the bucket already exists.
+            200 | 409 => Ok(()),
+            status => Err(io::Error::other(format!("S3 responded with {}", status))),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, key: Option<&str>) -> reqwest::RequestBuilder {
+        let path = match key {
+            Some(key) => format!("/{}/{}", self.bucket, key),
+            None => format!("/{}", self.bucket),
+        };
+        let url = format!("{}{}", self.endpoint, path);
+        let host = match reqwest::Url::parse(&url) {
+            Ok(url) => match url.port() {
+                Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+                None => url.host_str().unwrap_or_default().to_string(),
+            },
+            Err(_) => String::new(),
+        };
+
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:UNSIGNED-PAYLOAD\nx-amz-date:{}\n\n{}\nUNSIGNED-PAYLOAD",
+            method, path, host, amz_date, signed_headers
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac_sha256(&key, part);
+        }
+        let signature = hex::encode(hmac_sha256(&key, &string_to_sign));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        );
+
+        self.client
+            .request(method, url)
+            .header("x-amz-content-sha256", "UNSIGNED-PAYLOAD")
+            .header("x-amz-date", amz_date)
+            .header("authorization", authorization)
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn s3_error(status: reqwest::StatusCode) -> io::Error {
+    match status {
+        reqwest::StatusCode::NOT_FOUND => io::Error::from(io::ErrorKind::NotFound),
+        status => io::Error::other(format!("S3 responded with {}", status)),
+    }
+}
+
+#[async_trait]
+impl BlobStorage for S3Storage {
+    async fn exists(&self, sha256: &str) -> io::Result<bool> {
+        let response = self.request(reqwest::Method::HEAD, Some(sha256)).send();
+        let status = response.await.map_err(io::Error::other)?.status();
+        match status {
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(s3_error(status)),
+        }
+    }
+
+    async fn put(&self, sha256: &str, path: &Path, size: u64) -> io::Result<()> {
+        let file = tokio::fs::File::open(path).await?;
+        let body = reqwest::Body::wrap_stream(ReaderStream::new(file));
+        let response = self
+            .request(reqwest::Method::PUT, Some(sha256))
+            .header("content-length", size)
+            .body(body)
+            .send();
+        let status = response.await.map_err(io::Error::other)?.status();
+        if !status.is_success() {
+            return Err(s3_error(status));
+        }
+        Ok(())
+    }
+
+    async fn get(&self, sha256: &str) -> io::Result<BlobStream> {
+        let response = self.request(reqwest::Method::GET, Some(sha256)).send();
+        let response = response.await.map_err(io::Error::other)?;
+        if !response.status().is_success() {
+            return Err(s3_error(response.status()));
+        }
+        Ok(response.bytes_stream().map_err(io::Error::other).boxed())
+    }
+
+    async fn delete(&self, sha256: &str) -> io::Result<()> {
+        let response = self.request(reqwest::Method::DELETE, Some(sha256)).send();
+        let status = response.await.map_err(io::Error::other)?.status();
+        // S3 answers 204 even for missing keys.
+        if !status.is_success() && status != reqwest::StatusCode::NOT_FOUND {
+            return Err(s3_error(status));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+use std::collections::HashMap;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubtaskTemplate {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+// What a template fills in; `owner_id` or `team_id` tell whose it is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateFields {
+    pub name: String,
+    pub title: String,
+    pub description: String,
+    pub priority: Option<String>,
+    pub label_ids: Vec<i64>,
+    pub checklist: Vec<String>,
+    pub subtasks: Vec<SubtaskTemplate>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Template {
+    pub template_id: i64,
+    pub owner_id: Option<i64>,
+    pub team_id: Option<i64>,
+    #[serde(flatten)]
+    pub fields: TemplateFields,
+    // The placeholders used in the texts, in order of first use.
+    pub variables: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl TemplateFields {
+    fn texts(&self) -> impl Iterator<Item = &str> {
+        [self.title.as_str(), self.description.as_str()]
+            .into_iter()
+            .chain(self.checklist.iter().map(String::as_str))
+            .chain(
+                self.subtasks
+                    .iter()
+                    .flat_map(|subtask| [subtask.title.as_str(), subtask.description.as_str()]),
+            )
+    }
+
+    // The distinct placeholders of all the texts, or what is wrong with one of them.
+    pub fn variables(&self) -> Result<Vec<String>, String> {
+        let mut variables: Vec<String> = vec![];
+        for text in self.texts() {
+            for (_, name) in placeholders(text)? {
+                if !variables.iter().any(|known| known == name) {
+                    variables.push(name.to_string());
+                }
+            }
+        }
+        Ok(variables)
+    }
+}
+
+fn is_variable_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+// The `{{name}}` placeholders of a text with their byte ranges, braces included. Spaces around
+// the name are allowed; a name is made of ASCII letters, digits and underscores.
+pub fn placeholders(text: &str) -> Result<Vec<(Range<usize>, &str)>, String> {
+    let mut found = vec![];
+    let mut start = 0;
+    while let Some(offset) = text[start..].find("{{") {
+        let open = start + offset;
+        let Some(length) = text[open + 2..].find("}}") else {
+            return Err("A placeholder is missing its closing }}".to_string());
+        };
+        let close = open + 2 + length + 2;
+        let name = text[open + 2..close - 2].trim();
+        if name.is_empty() || !name.chars().all(is_variable_char) {
+            return Err(format!(
+                "Placeholder {} must be a name of letters, digits and underscores",
+                &text[open..close]
+            ));
+        }
+        found.push((open..close, name));
+        start = close;
+    }
+    Ok(found)
+}
+
+// The text with its placeholders replaced by the values of the variables.
+pub fn fill(text: &str, variables: &HashMap<String, String>) -> Result<String, String> {
+    let mut filled = String::with_capacity(text.len());
+    let mut end = 0;
+    for (range, name) in placeholders(text)? {
+        let Some(value) = variables.get(name) else {
+            return Err(format!("No value given for the variable {}", name));
+        };
+        filled.push_str(&text[end..range.start]);
+        filled.push_str(value);
+        end = range.end;
+    }
+    filled.push_str(&text[end..]);
+    Ok(filled)
+}
+
+// The checklist as Markdown task list items, unchecked.
+pub fn checklist_markdown(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("- [ ] {}\n", item))
+        .collect()
+}
+
+// Timestamps are read through JSON, which gives them in RFC 3339; subtasks come as JSON text.
+const TEMPLATE_COLUMNS: &str = "id, owner_id, team_id, name, title, description, priority, \
+     label_ids, checklist, subtasks::text AS subtasks, \
+     to_json(created_at) #>> '{}' AS created_at, to_json(updated_at) #>> '{}' AS updated_at";
+
+fn template(row: &sqlx::postgres::PgRow) -> Result<Template, sqlx::Error> {
+    let subtasks: String = row.try_get("subtasks")?;
+    let fields = TemplateFields {
+        name: row.try_get("name")?,
+        title: row.try_get("title")?,
+        description: row.try_get("description")?,
+        priority: row.try_get("priority")?,
+        label_ids: row.try_get("label_ids")?,
+        checklist: row.try_get("checklist")?,
+        subtasks: serde_json::from_str(&subtasks).map_err(|e| sqlx::Error::Decode(e.into()))?,
+    };
+    Ok(Template {
+        template_id: row.try_get("id")?,
+        owner_id: row.try_get("owner_id")?,
+        team_id: row.try_get("team_id")?,
+        variables: fields.variables().unwrap_or_default(),
+        fields,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn subtasks_json(fields: &TemplateFields) -> String {
+    serde_json::to_string(&fields.subtasks).unwrap_or_else(|_| "[]".to_string())
+}
+
+// Adds a template of the user, or of the team if `team_id` is given.
+pub async fn create(
+    pool: &Pool<Postgres>,
+    user_id: i64,
+    team_id: Option<i64>,
+    fields: &TemplateFields,
+) -> Result<Template, sqlx::Error> {
+    let owner_id = if team_id.is_some() {
+        None
+    } else {
+        Some(user_id)
+    };
+    let row = sqlx::query(&format!(
+        "INSERT INTO task_templates \
+         (owner_id, team_id, name, title, description, priority, label_ids, checklist, subtasks) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::jsonb) RETURNING {}",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(owner_id)
+    .bind(team_id)
+    .bind(&fields.name)
+    .bind(&fields.title)
+    .bind(&fields.description)
+    .bind(&fields.priority)
+    .bind(&fields.label_ids)
+    .bind(&fields.checklist)
+    .bind(subtasks_json(fields))
+    .fetch_one(pool)
+    .await?;
+    template(&row)
+}
+
+pub async fn get(pool: &Pool<Postgres>, template_id: i64) -> Result<Option<Template>, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM task_templates WHERE id = $1",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(template_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(template).transpose()
+}
+
+// The templates of the user and of their teams.
+pub async fn list(pool: &Pool<Postgres>, user_id: i64) -> Result<Vec<Template>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM task_templates WHERE owner_id = $1 \
+         OR team_id IN (SELECT team_id FROM team_members WHERE user_id = $1) \
+         ORDER BY name, id",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(template).collect()
+}
+
+pub async fn update(
+    pool: &Pool<Postgres>,
+    template_id: i64,
+    fields: &TemplateFields,
+) -> Result<Template, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "UPDATE task_templates SET name = $2, title = $3, description = $4, priority = $5, \
+         label_ids = $6, checklist = $7, subtasks = $8::jsonb, updated_at = now() \
+         WHERE id = $1 RETURNING {}",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(template_id)
+    .bind(&fields.name)
+    .bind(&fields.title)
+    .bind(&fields.description)
+    .bind(&fields.priority)
+    .bind(&fields.label_ids)
+    .bind(&fields.checklist)
+    .bind(subtasks_json(fields))
+    .fetch_one(pool)
+    .await?;
+    template(&row)
+}
+
+pub async fn delete(pool: &Pool<Postgres>, template_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM task_templates WHERE id = $1")
+        .bind(template_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use crate::proto::{self, task_service_client::TaskServiceClient};
+use crate::storage::BlobStorage;
+use crate::{positive_env, tasks_client};
+use std::{sync::Arc, time::Duration};
+use tonic::transport::Channel;
+
+const DAY: u64 = 24 * 60 * 60;
+
+// How long deleted tasks stay restorable and how often the expired ones are purged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrashConfig {
+    pub retention: Duration,
+    pub purge_interval: Duration,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            retention: Duration::from_secs(30 * DAY),
+            purge_interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl TrashConfig {
+    // Reads `TRASH_RETENTION_DAYS` and `TRASH_PURGE_INTERVAL_SECONDS`; unset ones keep the default.
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = TrashConfig::default();
+        if let Some(days) = positive_env("TRASH_RETENTION_DAYS")? {
+            config.retention = Duration::from_secs(days * DAY);
+        }
+        if let Some(seconds) = positive_env("TRASH_PURGE_INTERVAL_SECONDS")? {
+            config.purge_interval = Duration::from_secs(seconds);
+        }
+        Ok(config)
+    }
+}
+
+// Permanently deletes the tasks that have been in the trash longer than the retention period,
+// together with their attachments, and returns the ids of the tasks.
+pub async fn purge(
+    client: &mut TaskServiceClient<Channel>,
+    retention: Duration,
+    storage: &dyn BlobStorage,
+) -> Result<Vec<i64>, String> {
+    let req = proto::PurgeTrashRequest {
+        retention_seconds: retention.as_secs() as i64,
+    };
+    let response = match client.purge_trash(tonic::Request::new(req)).await {
+        Ok(response) => response.into_inner(),
+        Err(e) => return Err(e.message().to_string()),
+    };
+    for sha256 in &response.orphaned_blobs {
+        if let Err(e) = storage.delete(sha256).await {
+            println!("Failed to delete blob {}: {}", sha256, e);
+        }
+    }
+    Ok(response.task_ids)
+}
+
+pub async fn run_purge_job(config: TrashConfig, storage: Arc<dyn BlobStorage>) {
+    let mut client = tasks_client();
+    let mut interval = tokio::time::interval(config.purge_interval);
+    loop {
+        interval.tick().await;
+        match purge(&mut client, config.retention, storage.as_ref()).await {
+            Ok(task_ids) if task_ids.is_empty() => {}
+            Ok(task_ids) => println!(
+                "Purged {} expired tasks from the trash: {:?}",
+                task_ids.len(),
+                task_ids
+            ),
+            Err(e) => println!("Failed to purge the trash: {}", e),
+        }
+    }
+}
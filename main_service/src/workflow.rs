@@ -0,0 +1,200 @@
+use serde::Deserialize;
+use std::fs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Author,
+    Assignee,
+    Anyone,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Transition {
+    pub from: String,
+    pub to: String,
+    #[serde(default = "default_allowed_roles")]
+    pub allowed_roles: Vec<Role>,
+}
+
+fn default_allowed_roles() -> Vec<Role> {
+    vec![Role::Author, Role::Assignee]
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Workflow {
+    pub initial_status: String,
+    pub statuses: Vec<String>,
+    pub transitions: Vec<Transition>,
+    // Statuses counted as finished work; the last status when not configured.
+    #[serde(default)]
+    pub done_statuses: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransitionError {
+    UnknownStatus(String),
+    NotAllowed {
+        from: String,
+        to: String,
+        allowed: Vec<String>,
+    },
+    Forbidden {
+        from: String,
+        to: String,
+    },
+}
+
+impl std::fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransitionError::UnknownStatus(status) => write!(f, "Unknown status '{}'", status),
+            TransitionError::NotAllowed { from, to, allowed } if allowed.is_empty() => write!(
+                f,
+                "Cannot move task from '{}' to '{}': '{}' is a final status",
+                from, to, from
+            ),
+            TransitionError::NotAllowed { from, to, allowed } => write!(
+                f,
+                "Cannot move task from '{}' to '{}', allowed targets: {}",
+                from,
+                to,
+                allowed.join(", ")
+            ),
+            TransitionError::Forbidden { from, to } => write!(
+                f,
+                "You are not allowed to move this task from '{}' to '{}'",
+                from, to
+            ),
+        }
+    }
+}
+
+impl Default for Workflow {
+    fn default() -> Self {
+        let step = |from: &str, to: &str| Transition {
+            from: from.to_string(),
+            to: to.to_string(),
+            allowed_roles: default_allowed_roles(),
+        };
+        Workflow {
+            initial_status: "backlog".to_string(),
+            statuses: ["backlog", "todo", "in_progress", "review", "done"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            transitions: vec![
+                step("backlog", "todo"),
+                step("todo", "backlog"),
+                step("todo", "in_progress"),
+                step("in_progress", "todo"),
+                step("in_progress", "review"),
+                step("review", "in_progress"),
+                step("review", "done"),
+                step("done", "todo"),
+            ],
+            done_statuses: vec!["done".to_string()],
+        }
+    }
+}
+
+impl Workflow {
+    pub fn load(path: &str) -> Result<Workflow, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Can't read workflow config {}: {}", path, e))?;
+        let workflow: Workflow = serde_json::from_str(&content)
+            .map_err(|e| format!("Can't parse workflow config {}: {}", path, e))?;
+        workflow.validate()?;
+        Ok(workflow)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.statuses.is_empty() {
+            return Err("Workflow must have at least one status".to_string());
+        }
+        if !self.has_status(&self.initial_status) {
+            return Err(format!(
+                "Initial status '{}' is not in the status list",
+                self.initial_status
+            ));
+        }
+        for status in &self.done_statuses {
+            if !self.has_status(status) {
+                return Err(format!(
+                    "Done status '{}' is not in the status list",
+                    status
+                ));
+            }
+        }
+        for transition in &self.transitions {
+            for status in [&transition.from, &transition.to] {
+                if !self.has_status(status) {
+                    return Err(format!("Transition uses unknown status '{}'", status));
+                }
+            }
+            if transition.allowed_roles.is_empty() {
+                return Err(format!(
+                    "Transition from '{}' to '{}' has no allowed roles",
+                    transition.from, transition.to
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_status(&self, status: &str) -> bool {
+        self.statuses.iter().any(|s| s == status)
+    }
+
+    pub fn is_done(&self, status: &str) -> bool {
+        if self.done_statuses.is_empty() {
+            return self.statuses.last().is_some_and(|last| last == status);
+        }
+        self.done_statuses.iter().any(|s| s == status)
+    }
+
+    pub fn targets(&self, from: &str) -> Vec<String> {
+        self.transitions
+            .iter()
+            .filter(|t| t.from == from)
+            .map(|t| t.to.clone())
+            .collect()
+    }
+
+    // `roles` are the roles the actor has on the task; `Anyone` is implied.
+    pub fn check_transition(
+        &self,
+        from: &str,
+        to: &str,
+        roles: &[Role],
+    ) -> Result<(), TransitionError> {
+        if !self.has_status(to) {
+            return Err(TransitionError::UnknownStatus(to.to_string()));
+        }
+        let transition = match self
+            .transitions
+            .iter()
+            .find(|t| t.from == from && t.to == to)
+        {
+            Some(transition) => transition,
+            None => {
+                return Err(TransitionError::NotAllowed {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                    allowed: self.targets(from),
+                });
+            }
+        };
+        let permitted = transition
+            .allowed_roles
+            .iter()
+            .any(|role| *role == Role::Anyone || roles.contains(role));
+        if !permitted {
+            return Err(TransitionError::Forbidden {
+                from: from.to_string(),
+                to: to.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
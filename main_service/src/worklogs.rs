@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkLog {
+    pub work_log_id: i64,
+    pub user_id: i64,
+    pub task_id: i64,
+    pub project_id: Option<i64>,
+    pub started_at: String,
+    // None while the timer is running.
+    pub minutes: Option<i32>,
+    pub note: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+// Which entries a total or a report covers; unset fields don't restrict it.
+#[derive(Debug, Default)]
+pub struct Scope {
+    pub task_id: Option<i64>,
+    pub user_id: Option<i64>,
+    pub project_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    #[default]
+    Day,
+    // Weeks start on Monday.
+    Week,
+}
+
+impl GroupBy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            GroupBy::Day => "day",
+            GroupBy::Week => "week",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Period {
+    // First day of the period, YYYY-MM-DD.
+    pub start: String,
+    pub minutes: i64,
+}
+
+// Timestamps are read through JSON, which gives them in RFC 3339.
+const WORK_LOG_COLUMNS: &str = "id, user_id, task_id, project_id, \
+     to_json(started_at) #>> '{}' AS started_at, minutes, note, \
+     to_json(created_at) #>> '{}' AS created_at, to_json(updated_at) #>> '{}' AS updated_at";
+
+// Entries of the scope, with $1 to $3 bound to its task, user and project.
+const SCOPE_CONDITION: &str = "($1::bigint IS NULL OR task_id = $1) \
+     AND ($2::bigint IS NULL OR user_id = $2) AND ($3::bigint IS NULL OR project_id = $3)";
+
+fn work_log(row: &sqlx::postgres::PgRow) -> Result<WorkLog, sqlx::Error> {
+    Ok(WorkLog {
+        work_log_id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        task_id: row.try_get("task_id")?,
+        project_id: row.try_get("project_id")?,
+        started_at: row.try_get("started_at")?,
+        minutes: row.try_get("minutes")?,
+        note: row.try_get("note")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+// Starts a timer of the user on the task; None if one of theirs is already running.
+pub async fn start_timer(
+    pool: &Pool<Postgres>,
+    user_id: i64,
+    task_id: i64,
+    project_id: Option<i64>,
+) -> Result<Option<WorkLog>, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "INSERT INTO work_logs (user_id, task_id, project_id) VALUES ($1, $2, $3) \
+         ON CONFLICT (user_id) WHERE minutes IS NULL DO NOTHING RETURNING {}",
+        WORK_LOG_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(task_id)
+    .bind(project_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(work_log).transpose()
+}
+
+pub async fn running_timer(
+    pool: &Pool<Postgres>,
+    user_id: i64,
+) -> Result<Option<WorkLog>, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM work_logs WHERE user_id = $1 AND minutes IS NULL",
+        WORK_LOG_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(work_log).transpose()
+}
+
+// Stops the running timer of the user, rounding the time to whole minutes; a start moved past
+// now counts as no time.
+pub async fn stop_timer(
+    pool: &Pool<Postgres>,
+    user_id: i64,
+) -> Result<Option<WorkLog>, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "UPDATE work_logs \
+         SET minutes = greatest(0, round(extract(epoch FROM now() - started_at) / 60))::integer, \
+         updated_at = now() WHERE user_id = $1 AND minutes IS NULL RETURNING {}",
+        WORK_LOG_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(work_log).transpose()
+}
+
+pub async fn create(
+    pool: &Pool<Postgres>,
+    user_id: i64,
+    task_id: i64,
+    project_id: Option<i64>,
+    started_at: &str,
+    minutes: i32,
+    note: &str,
+) -> Result<WorkLog, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "INSERT INTO work_logs (user_id, task_id, project_id, started_at, minutes, note) \
+         VALUES ($1, $2, $3, $4::timestamptz, $5, $6) RETURNING {}",
+        WORK_LOG_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(task_id)
+    .bind(project_id)
+    .bind(started_at)
+    .bind(minutes)
+    .bind(note)
+    .fetch_one(pool)
+    .await?;
+    work_log(&row)
+}
+
+pub async fn get(pool: &Pool<Postgres>, work_log_id: i64) -> Result<Option<WorkLog>, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM work_logs WHERE id = $1",
+        WORK_LOG_COLUMNS
+    ))
+    .bind(work_log_id)
+    .fetch_optional(pool)
+    .await?;
+    row.as_ref().map(work_log).transpose()
+}
+
+pub async fn update(
+    pool: &Pool<Postgres>,
+    work_log_id: i64,
+    started_at: &str,
+    minutes: Option<i32>,
+    note: &str,
+) -> Result<WorkLog, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "UPDATE work_logs SET started_at = $2::timestamptz, minutes = $3, note = $4, \
+         updated_at = now() WHERE id = $1 RETURNING {}",
+        WORK_LOG_COLUMNS
+    ))
+    .bind(work_log_id)
+    .bind(started_at)
+    .bind(minutes)
+    .bind(note)
+    .fetch_one(pool)
+    .await?;
+    work_log(&row)
+}
+
+pub async fn delete(pool: &Pool<Postgres>, work_log_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM work_logs WHERE id = $1")
+        .bind(work_log_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// The entries of the task, running timers included, newest first.
+pub async fn list_of_task(
+    pool: &Pool<Postgres>,
+    task_id: i64,
+) -> Result<Vec<WorkLog>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM work_logs WHERE task_id = $1 ORDER BY started_at DESC, id DESC",
+        WORK_LOG_COLUMNS
+    ))
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    rows.iter().map(work_log).collect()
+}
+
+// Minutes logged in the scope; running timers don't count until they are stopped.
+pub async fn total(pool: &Pool<Postgres>, scope: &Scope) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "SELECT coalesce(sum(minutes), 0)::bigint AS minutes FROM work_logs WHERE {}",
+        SCOPE_CONDITION
+    ))
+    .bind(scope.task_id)
+    .bind(scope.user_id)
+    .bind(scope.project_id)
+    .fetch_one(pool)
+    .await?;
+    row.try_get("minutes")
+}
+
+// Minutes logged in the scope per day or week from `from` to `to`, both YYYY-MM-DD dates
+// included. Days are those of the timezone; periods without time are left out.
+pub async fn report(
+    pool: &Pool<Postgres>,
+    scope: &Scope,
+    from: &str,
+    to: &str,
+    timezone: &str,
+    group_by: GroupBy,
+) -> Result<Vec<Period>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT to_char(date_trunc($7, started_at AT TIME ZONE $6), 'YYYY-MM-DD') AS start, \
+         sum(minutes)::bigint AS minutes FROM work_logs WHERE {} AND minutes IS NOT NULL \
+         AND started_at >= $4::date::timestamp AT TIME ZONE $6 \
+         AND started_at < ($5::date + 1)::timestamp AT TIME ZONE $6 \
+         GROUP BY 1 ORDER BY 1",
+        SCOPE_CONDITION
+    ))
+    .bind(scope.task_id)
+    .bind(scope.user_id)
+    .bind(scope.project_id)
+    .bind(from)
+    .bind(to)
+    .bind(timezone)
+    .bind(group_by.as_str())
+    .fetch_all(pool)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(Period {
+                start: row.try_get("start")?,
+                minutes: row.try_get("minutes")?,
+            })
+        })
+        .collect()
+}
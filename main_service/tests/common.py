@@ -1,5 +1,6 @@
 import requests
 import json
+import base64
 
 host = 'http://localhost:4000'
 
@@ -16,6 +17,12 @@ def login(username: str, password: str):
     return response
 
 
+def user_id_from_token(token: str):
+    payload = token.split('.')[1]
+    payload += '=' * (-len(payload) % 4)
+    return json.loads(base64.urlsafe_b64decode(payload))["id"]
+
+
 def update_personal_data(token: str, json_data: dict):
     response = requests.put(f'{host}/personal_data', headers={"Authorization": token}, json=json_data)
     return response
@@ -26,24 +33,498 @@ def get_personal_data(token: str):
     return response
 
 
-def create_task(text: str, token: str):
-    json_data = {"text": text}
+def create_team(name: str, token: str):
+    response = requests.post(f'{host}/create_team', headers={"Authorization": token}, json={"name": name})
+    return response
+
+
+def add_team_member(team_id: int, user_id: int, token: str):
+    json_data = {"team_id": team_id, "user_id": user_id}
+    response = requests.post(f'{host}/add_team_member', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def my_teams(token: str):
+    response = requests.get(f'{host}/my_teams', headers={"Authorization": token})
+    return response
+
+
+def create_project(key: str, name: str, token: str):
+    json_data = {"key": key, "name": name}
+    response = requests.post(f'{host}/create_project', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def add_project_member(project_id: int, user_id: int, token: str):
+    json_data = {"project_id": project_id, "user_id": user_id}
+    response = requests.post(f'{host}/add_project_member', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def archive_project(project_id: int, archived: bool, token: str):
+    json_data = {"project_id": project_id, "archived": archived}
+    response = requests.put(f'{host}/archive_project', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def my_projects(token: str):
+    response = requests.get(f'{host}/my_projects', headers={"Authorization": token})
+    return response
+
+
+def project_tasks(project_id: int, offset: int, limit: int, token: str, **filters):
+    json_data = {"project_id": project_id, "offset": offset, "limit": limit, **filters}
+    response = requests.get(f'{host}/project_tasks', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def get_board(project_key: str, token: str):
+    response = requests.get(f'{host}/boards/{project_key}', headers={"Authorization": token})
+    return response
+
+
+def move_board_task(project_key: str, task_id: int, status: str, token: str, after_task_id: int = None):
+    json_data = {"task_id": task_id, "status": status, "after_task_id": after_task_id}
+    response = requests.post(f'{host}/boards/{project_key}/move', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def set_wip_limit(project_key: str, status: str, wip_limit: int, token: str):
+    json_data = {"status": status, "wip_limit": wip_limit}
+    response = requests.put(f'{host}/boards/{project_key}/wip_limits', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def create_task(text: str, token: str, **fields):
+    json_data = {"text": text, **fields}
     response = requests.post(f'{host}/create_task', headers={"Authorization": token}, json=json_data)
     return response
 
 
-def get_task(task_id: int):
+def get_task(task_id: int, token: str = None):
     json_data = {"task_id": task_id}
+    headers = {"Authorization": token} if token else {}
+    response = requests.get(f'{host}/get_task', headers=headers, json=json_data)
+    return response
+
+
+def get_task_by_key(key: str):
+    json_data = {"key": key}
     response = requests.get(f'{host}/get_task', json=json_data)
     return response
 
 
-def update_task(task_id: int, new_text: str, token: str):
-    json_data = {"task_id": task_id, "new_text": new_text}
+def update_task(task_id: int, new_text: str, token: str, **fields):
+    json_data = {"task_id": task_id, **fields}
+    if new_text is not None:
+        json_data["new_text"] = new_text
     response = requests.put(f'{host}/update_task', headers={"Authorization": token}, json=json_data)
     return response
 
 
+def update_task_if_match(task_id: int, etag: str, token: str, **fields):
+    json_data = {"task_id": task_id, **fields}
+    headers = {"Authorization": token, "If-Match": etag}
+    response = requests.put(f'{host}/update_task', headers=headers, json=json_data)
+    return response
+
+
+def transition_task(task_id: int, status: str, token: str):
+    json_data = {"task_id": task_id, "status": status}
+    response = requests.post(f'{host}/transition_task', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def task_transitions(task_id: int, token: str = None):
+    json_data = {"task_id": task_id}
+    headers = {"Authorization": token} if token else {}
+    response = requests.get(f'{host}/task_transitions', headers=headers, json=json_data)
+    return response
+
+
+def subtasks(task_id: int, offset: int = 0, limit: int = 100, token: str = None):
+    json_data = {"task_id": task_id, "offset": offset, "limit": limit}
+    headers = {"Authorization": token} if token else {}
+    response = requests.get(f'{host}/subtasks', headers=headers, json=json_data)
+    return response
+
+
+def add_dependency(blocker_id: int, blocked_id: int, token: str):
+    json_data = {"blocker_id": blocker_id, "blocked_id": blocked_id}
+    response = requests.post(f'{host}/add_dependency', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def remove_dependency(blocker_id: int, blocked_id: int, token: str):
+    json_data = {"blocker_id": blocker_id, "blocked_id": blocked_id}
+    response = requests.delete(f'{host}/remove_dependency', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def task_dependencies(task_id: int, token: str = None):
+    headers = {"Authorization": token} if token else {}
+    json_data = {"task_id": task_id}
+    response = requests.get(f'{host}/task_dependencies', headers=headers, json=json_data)
+    return response
+
+
+def dependency_graph(project_id: int, token: str):
+    json_data = {"project_id": project_id}
+    response = requests.get(f'{host}/dependency_graph', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def search(query: str, token: str, **filters):
+    json_data = {"query": query, **filters}
+    response = requests.get(f'{host}/search', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def task_revisions(task_id: int, token: str = None):
+    json_data = {"task_id": task_id}
+    headers = {"Authorization": token} if token else {}
+    response = requests.get(f'{host}/task_revisions', headers=headers, json=json_data)
+    return response
+
+
+def revision_diff(task_id: int, from_revision: int, to_revision: int, mode: str = None, token: str = None):
+    json_data = {"task_id": task_id, "from_revision": from_revision, "to_revision": to_revision, "mode": mode}
+    headers = {"Authorization": token} if token else {}
+    response = requests.get(f'{host}/revision_diff', headers=headers, json=json_data)
+    return response
+
+
+def restore_revision(task_id: int, revision_number: int, token: str):
+    json_data = {"task_id": task_id, "revision_number": revision_number}
+    response = requests.post(f'{host}/restore_revision', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def delete_task(task_id: int, token: str):
+    json_data = {"task_id": task_id}
+    response = requests.delete(f'{host}/delete_task', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def trash(token: str):
+    response = requests.get(f'{host}/trash', headers={"Authorization": token})
+    return response
+
+
+def restore_task(task_id: int, token: str):
+    json_data = {"task_id": task_id}
+    response = requests.post(f'{host}/restore_task', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def bulk_tasks(operations: list, token: str, mode: str = None):
+    json_data = {"operations": operations}
+    if mode is not None:
+        json_data["mode"] = mode
+    response = requests.post(f'{host}/bulk_tasks', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def export_tasks(format: str, token: str, project_id: int = None):
+    json_data = {"format": format}
+    if project_id is not None:
+        json_data["project_id"] = project_id
+    response = requests.get(f'{host}/export_tasks', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def import_tasks(format: str, data, token: str, mapping: dict = None, project_id: int = None,
+                 dry_run: bool = False):
+    json_data = {"format": format, "data": data, "dry_run": dry_run}
+    if mapping is not None:
+        json_data["mapping"] = mapping
+    if project_id is not None:
+        json_data["project_id"] = project_id
+    response = requests.post(f'{host}/import_tasks', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def create_series(title: str, rrule: str, start: str, token: str, **fields):
+    json_data = {"title": title, "rrule": rrule, "start": start, **fields}
+    response = requests.post(f'{host}/series', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def get_series(series_id: int, token: str = None):
+    headers = {"Authorization": token} if token else {}
+    response = requests.get(f'{host}/series/{series_id}', headers=headers)
+    return response
+
+
+def update_series(series_id: int, token: str, **fields):
+    response = requests.put(f'{host}/series/{series_id}', headers={"Authorization": token}, json=fields)
+    return response
+
+
+def stop_series(series_id: int, token: str):
+    response = requests.delete(f'{host}/series/{series_id}', headers={"Authorization": token})
+    return response
+
+
+def create_reminder(task_id: int, token: str, **fields):
+    response = requests.post(f'{host}/tasks/{task_id}/reminders', headers={"Authorization": token}, json=fields)
+    return response
+
+
+def list_reminders(task_id: int, token: str):
+    response = requests.get(f'{host}/tasks/{task_id}/reminders', headers={"Authorization": token})
+    return response
+
+
+def delete_reminder(reminder_id: int, token: str):
+    response = requests.delete(f'{host}/reminders/{reminder_id}', headers={"Authorization": token})
+    return response
+
+
+def get_user(user_id: int):
+    response = requests.get(f'{host}/users/{user_id}')
+    return response
+
+
+def set_checklist_item(task_id: int, index: int, checked: bool, token: str):
+    response = requests.put(f'{host}/tasks/{task_id}/checklist/{index}', headers={"Authorization": token},
+                            json={'checked': checked})
+    return response
+
+
+def create_template(token: str, **fields):
+    response = requests.post(f'{host}/templates', headers={"Authorization": token}, json=fields)
+    return response
+
+
+def list_templates(token: str):
+    response = requests.get(f'{host}/templates', headers={"Authorization": token})
+    return response
+
+
+def get_template(template_id: int, token: str):
+    response = requests.get(f'{host}/templates/{template_id}', headers={"Authorization": token})
+    return response
+
+
+def update_template(template_id: int, token: str, **fields):
+    response = requests.put(f'{host}/templates/{template_id}', headers={"Authorization": token}, json=fields)
+    return response
+
+
+def delete_template(template_id: int, token: str):
+    response = requests.delete(f'{host}/templates/{template_id}', headers={"Authorization": token})
+    return response
+
+
+def watch_task(task_id: int, token: str):
+    response = requests.post(f'{host}/tasks/{task_id}/watchers', headers={"Authorization": token})
+    return response
+
+
+def unwatch_task(task_id: int, token: str):
+    response = requests.delete(f'{host}/tasks/{task_id}/watchers', headers={"Authorization": token})
+    return response
+
+
+def list_watchers(task_id: int, token: str = None):
+    headers = {"Authorization": token} if token else {}
+    response = requests.get(f'{host}/tasks/{task_id}/watchers', headers=headers)
+    return response
+
+
+def share_task(task_id: int, permission: str, token: str, user_id: int = None, team_id: int = None):
+    json_data = {"user_id": user_id, "team_id": team_id, "permission": permission}
+    response = requests.post(f'{host}/tasks/{task_id}/grants', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def list_grants(task_id: int, token: str):
+    response = requests.get(f'{host}/tasks/{task_id}/grants', headers={"Authorization": token})
+    return response
+
+
+def revoke_grant(task_id: int, grant_id: int, token: str):
+    response = requests.delete(f'{host}/tasks/{task_id}/grants/{grant_id}', headers={"Authorization": token})
+    return response
+
+
+def start_timer(task_id: int, token: str):
+    response = requests.post(f'{host}/tasks/{task_id}/timer', headers={"Authorization": token})
+    return response
+
+
+def get_timer(token: str):
+    response = requests.get(f'{host}/timer', headers={"Authorization": token})
+    return response
+
+
+def stop_timer(token: str):
+    response = requests.post(f'{host}/timer/stop', headers={"Authorization": token})
+    return response
+
+
+def log_work(task_id: int, minutes: int, token: str, **fields):
+    json_data = {"minutes": minutes, **fields}
+    response = requests.post(f'{host}/tasks/{task_id}/work_logs', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def list_work_logs(task_id: int, token: str):
+    response = requests.get(f'{host}/tasks/{task_id}/work_logs', headers={"Authorization": token})
+    return response
+
+
+def update_work_log(work_log_id: int, token: str, **fields):
+    response = requests.put(f'{host}/work_logs/{work_log_id}', headers={"Authorization": token}, json=fields)
+    return response
+
+
+def delete_work_log(work_log_id: int, token: str):
+    response = requests.delete(f'{host}/work_logs/{work_log_id}', headers={"Authorization": token})
+    return response
+
+
+def time_totals(token: str, **params):
+    response = requests.get(f'{host}/time_totals', headers={"Authorization": token}, params=params)
+    return response
+
+
+def time_report(date_from: str, date_to: str, token: str, **params):
+    params = {"from": date_from, "to": date_to, **params}
+    response = requests.get(f'{host}/time_report', headers={"Authorization": token}, params=params)
+    return response
+
+
+def list_notifications(token: str, **params):
+    response = requests.get(f'{host}/notifications', headers={"Authorization": token}, params=params)
+    return response
+
+
+def mark_notification(notification_id: int, read: bool, token: str):
+    action = 'read' if read else 'unread'
+    response = requests.post(f'{host}/notifications/{notification_id}/{action}', headers={"Authorization": token})
+    return response
+
+
+def mark_all_notifications_read(token: str):
+    response = requests.post(f'{host}/notifications/read_all', headers={"Authorization": token})
+    return response
+
+
+def notification_preferences(token: str):
+    response = requests.get(f'{host}/notification_preferences', headers={"Authorization": token})
+    return response
+
+
+def update_notification_preferences(preferences: list, token: str):
+    response = requests.put(f'{host}/notification_preferences', headers={"Authorization": token},
+                            json={'preferences': preferences})
+    return response
+
+
+def upload_attachment(task_id: int, filename: str, content: bytes, token: str):
+    response = requests.post(f'{host}/tasks/{task_id}/attachments', params={"filename": filename},
+                             headers={"Authorization": token}, data=content)
+    return response
+
+
+def download_attachment(attachment_id: int, token: str = None):
+    headers = {"Authorization": token} if token else {}
+    response = requests.get(f'{host}/attachments/{attachment_id}', headers=headers)
+    return response
+
+
+def delete_attachment(attachment_id: int, token: str):
+    response = requests.delete(f'{host}/attachments/{attachment_id}', headers={"Authorization": token})
+    return response
+
+
+def assign_task(task_id: int, assignee_id: int, token: str):
+    json_data = {"task_id": task_id, "assignee_id": assignee_id}
+    response = requests.put(f'{host}/assign_task', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def unassign_task(task_id: int, token: str):
+    json_data = {"task_id": task_id}
+    response = requests.put(f'{host}/unassign_task', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def my_tasks(kind: str, token: str, offset: int = 0, limit: int = 100, **filters):
+    json_data = {"offset": offset, "limit": limit, **filters}
+    response = requests.get(f'{host}/{kind}', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def create_comment(task_id: int, content: str, token: str, parent_id: int = None):
+    json_data = {"task_id": task_id, "content": content, "parent_id": parent_id}
+    response = requests.post(f'{host}/create_comment', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def edit_comment(comment_id: int, content: str, token: str):
+    json_data = {"comment_id": comment_id, "content": content}
+    response = requests.put(f'{host}/edit_comment', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def delete_comment(comment_id: int, token: str):
+    json_data = {"comment_id": comment_id}
+    response = requests.delete(f'{host}/delete_comment', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def list_comments(task_id: int, offset: int, limit: int, parent_id: int = None, token: str = None):
+    json_data = {"task_id": task_id, "parent_id": parent_id, "offset": offset, "limit": limit}
+    headers = {"Authorization": token} if token else {}
+    response = requests.get(f'{host}/list_comments', headers=headers, json=json_data)
+    return response
+
+
+def create_label(name: str, color: str, token: str, team_id: int = None, description: str = ''):
+    json_data = {"name": name, "color": color, "team_id": team_id, "description": description}
+    response = requests.post(f'{host}/create_label', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def update_label(label_id: int, token: str, **fields):
+    json_data = {"label_id": label_id, **fields}
+    response = requests.put(f'{host}/update_label', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def merge_labels(source_label_id: int, target_label_id: int, token: str):
+    json_data = {"source_label_id": source_label_id, "target_label_id": target_label_id}
+    response = requests.post(f'{host}/merge_labels', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def list_labels(token: str):
+    response = requests.get(f'{host}/list_labels', headers={"Authorization": token})
+    return response
+
+
+def attach_label(task_id: int, label_id: int, token: str):
+    json_data = {"task_id": task_id, "label_id": label_id}
+    response = requests.post(f'{host}/attach_label', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def detach_label(task_id: int, label_id: int, token: str):
+    json_data = {"task_id": task_id, "label_id": label_id}
+    response = requests.delete(f'{host}/detach_label', headers={"Authorization": token}, json=json_data)
+    return response
+
+
+def list_tasks(user_id: int, offset: int, limit: int, token: str = None, **filters):
+    json_data = {"user_id": user_id, "offset": offset, "limit": limit, **filters}
+    headers = {"Authorization": token} if token else {}
+    response = requests.get(f'{host}/list_tasks', headers=headers, json=json_data)
+    return response
+
+
 def like(task_id: int, token: str):
     json_data = {"task_id": task_id}
     response = requests.post(f'{host}/like', headers={"Authorization": token}, json=json_data)
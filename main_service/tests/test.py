@@ -1,9 +1,13 @@
 from common import *
 import random
 from string import ascii_lowercase, digits, ascii_uppercase
+import csv
+import datetime
+import io
 import json
 import time
 import clickhouse_connect
+import psycopg2
 
 def random_str(length):
     return ''.join(random.choice(ascii_lowercase + digits) for _ in range(length))
@@ -13,6 +17,14 @@ def random_password(length):
     return ''.join(random.choice(ascii_lowercase + ascii_uppercase + digits) for _ in range(length))
 
 
+def make_moderator(username):
+    conn = psycopg2.connect(host='localhost', port=2345, dbname='main_service_soa',
+                            user='postgres', password='my_password')
+    with conn, conn.cursor() as cur:
+        cur.execute("UPDATE users SET is_moderator = true WHERE username = %s;", (username,))
+    conn.close()
+
+
 def test_signup_login_update():
     username = random_str(10)
     password = 'aaaaaA1*'
@@ -69,6 +81,1453 @@ def test_tasks():
     print('test_tasks OK')
 
 
+def test_rich_task():
+    username = random_str(10)
+    password = 'aaaaaA1*'
+
+    signup(username, password)
+    token = login(username, password).headers["Authorization"]
+
+    create_resp = create_task(None, token, title='Release 1.2', description='* bump version\n* tag',
+                              priority='high', due_date='2030-01-31')
+    assert create_resp.status_code == 201
+    task_id = json.loads(create_resp.text)["task_id"]
+
+    task = json.loads(get_task(task_id).text)
+    assert task['title'] == 'Release 1.2'
+    assert task['description'] == '* bump version\n* tag'
+    assert task['text'] == task['description']
+    assert task['status'] == 'backlog'
+    assert task['priority'] == 'high'
+    assert task['due_date'].startswith('2030-01-31T00:00:00')
+    assert task['assignee_id'] is None
+    assert task['created_at'] and task['updated_at']
+
+    assert update_task(task_id, None, token, priority='low', clear_due_date=True).status_code == 200
+    task = json.loads(get_task(task_id).text)
+    assert task['priority'] == 'low'
+    assert task['due_date'] is None
+    assert task['title'] == 'Release 1.2'
+    assert update_task(task_id, None, token).status_code == 406
+
+    # Old clients only send `text`: the title is derived from its first line.
+    legacy_id = json.loads(create_task('Fix login\nIt crashes', token).text)["task_id"]
+    legacy_task = json.loads(get_task(legacy_id).text)
+    assert legacy_task['title'] == 'Fix login'
+    assert legacy_task['text'] == 'Fix login\nIt crashes'
+    assert update_task(legacy_id, 'New text', token).status_code == 200
+    assert json.loads(get_task(legacy_id).text)['description'] == 'New text'
+
+    assert create_task('x', token, priority='whenever').status_code == 406
+    assert create_task('x', token, status='someday').status_code == 406
+    assert create_task('x', token, status='done').status_code == 406
+    assert create_task('x', token, due_date='tomorrow').status_code == 406
+    assert create_task(None, token, title='   ').status_code == 406
+    assert create_task('x', token, title='t' * 201).status_code == 406
+
+    print('test_rich_task OK')
+
+
+def test_workflow():
+    password = 'aaaaaA1*'
+    author, other = random_str(10), random_str(10)
+    signup(author, password)
+    signup(other, password)
+    author_token = login(author, password).headers["Authorization"]
+    other_token = login(other, password).headers["Authorization"]
+
+    task_id = json.loads(create_task('Workflow task', author_token).text)["task_id"]
+
+    assert transition_task(task_id, 'done', author_token).status_code == 409
+    assert transition_task(task_id, 'someday', author_token).status_code == 406
+    assert update_task(task_id, None, author_token, status='todo').status_code == 406
+
+    # backlog -> todo is open to anyone, todo -> in_progress only to author/assignee.
+    assert transition_task(task_id, 'todo', other_token).status_code == 200
+    assert transition_task(task_id, 'in_progress', other_token).status_code == 403
+    for status in ['in_progress', 'review', 'done']:
+        assert transition_task(task_id, status, author_token).status_code == 200
+    assert json.loads(get_task(task_id).text)['status'] == 'done'
+
+    history = json.loads(task_transitions(task_id).text)
+    assert [(t['from_status'], t['to_status']) for t in history] == [
+        ('backlog', 'todo'), ('todo', 'in_progress'), ('in_progress', 'review'), ('review', 'done')]
+    assert history[0]['actor_id'] != history[1]['actor_id']
+    assert all(t['created_at'] for t in history)
+
+    print('test_workflow OK')
+
+
+def test_assignment():
+    password = 'aaaaaA1*'
+    author, dev1, dev2 = random_str(10), random_str(10), random_str(10)
+    tokens = {}
+    for username in [author, dev1, dev2]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    dev1_id = user_id_from_token(tokens[dev1])
+    dev2_id = user_id_from_token(tokens[dev2])
+
+    task_id = json.loads(create_task('Assigned task', tokens[author]).text)["task_id"]
+    assert create_task('Ghost task', tokens[author], assignee_id=10 ** 12).status_code == 406
+    assert assign_task(task_id, 10 ** 12, tokens[author]).status_code == 406
+    assert assign_task(task_id, dev1_id, tokens[dev2]).status_code == 403
+
+    unassigned = json.loads(my_tasks('unassigned_tasks', tokens[author], order='desc').text)['tasks']
+    assert task_id in [task['task_id'] for task in unassigned]
+
+    assert assign_task(task_id, dev1_id, tokens[author]).status_code == 200
+    assert json.loads(get_task(task_id).text)['assignee_id'] == dev1_id
+    assigned = json.loads(my_tasks('assigned_to_me', tokens[dev1]).text)['tasks']
+    assert [task['task_id'] for task in assigned] == [task_id]
+
+    # The assignee can hand the task over.
+    assert assign_task(task_id, dev2_id, tokens[dev1]).status_code == 200
+    assert json.loads(my_tasks('assigned_to_me', tokens[dev1]).text)['tasks'] == []
+    assert [task['task_id'] for task in json.loads(my_tasks('assigned_to_me', tokens[dev2]).text)['tasks']] == [task_id]
+
+    created = json.loads(my_tasks('created_by_me', tokens[author]).text)['tasks']
+    assert [task['task_id'] for task in created] == [task_id]
+
+    assert unassign_task(task_id, tokens[author]).status_code == 200
+    assert json.loads(get_task(task_id).text)['assignee_id'] is None
+
+    print('test_assignment OK')
+
+
+def test_comments():
+    password = 'aaaaaA1*'
+    author, other, moderator = random_str(10), random_str(10), random_str(10)
+    tokens = {}
+    for username in [author, other, moderator]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    make_moderator(moderator)
+
+    task_id = json.loads(create_task('Discuss me', tokens[author]).text)["task_id"]
+    assert create_comment(100500100500, 'Lost', tokens[author]).status_code == 404
+    assert create_comment(task_id, '   ', tokens[author]).status_code == 406
+
+    root = json.loads(create_comment(task_id, 'First **point**', tokens[author]).text)
+    assert root['parent_id'] is None and root['edited_at'] is None
+    reply = json.loads(create_comment(task_id, 'Agree', tokens[other], root['comment_id']).text)
+    assert reply['parent_id'] == root['comment_id']
+    for i in range(3):
+        create_comment(task_id, f'Point {i}', tokens[other])
+
+    assert edit_comment(root['comment_id'], 'Hijacked', tokens[other]).status_code == 403
+    edit_resp = edit_comment(root['comment_id'], 'First *point*', tokens[author])
+    assert edit_resp.status_code == 200
+    assert json.loads(edit_resp.text)['edited_at'] is not None
+
+    page = json.loads(list_comments(task_id, 0, 2).text)
+    assert page['total_count'] == 4
+    assert [c['content'] for c in page['comments']] == ['First *point*', 'Point 0']
+    assert page['comments'][0]['reply_count'] == 1
+    page = json.loads(list_comments(task_id, 2, 2).text)
+    assert [c['content'] for c in page['comments']] == ['Point 1', 'Point 2']
+    replies = json.loads(list_comments(task_id, 0, 10, root['comment_id']).text)
+    assert [c['comment_id'] for c in replies['comments']] == [reply['comment_id']]
+    assert list_comments(task_id, 0, 1000).status_code == 406
+
+    assert delete_comment(root['comment_id'], tokens[other]).status_code == 403
+    assert delete_comment(root['comment_id'], tokens[moderator]).status_code == 200
+    tombstone = json.loads(list_comments(task_id, 0, 1).text)['comments'][0]
+    assert tombstone['deleted'] and tombstone['content'] is None
+    assert tombstone['reply_count'] == 1
+    assert edit_comment(root['comment_id'], 'Back', tokens[author]).status_code == 409
+    assert delete_comment(reply['comment_id'], tokens[other]).status_code == 200
+
+    print('test_comments OK')
+
+
+def test_labels():
+    password = 'aaaaaA1*'
+    lead, dev, outsider = random_str(10), random_str(10), random_str(10)
+    tokens = {}
+    for username in [lead, dev, outsider]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    lead_id = user_id_from_token(tokens[lead])
+
+    team_id = json.loads(create_team(random_str(10), tokens[lead]).text)["team_id"]
+    assert add_team_member(team_id, user_id_from_token(tokens[dev]), tokens[lead]).status_code == 200
+    assert add_team_member(team_id, lead_id, tokens[dev]).status_code == 403
+    assert [t['team_id'] for t in json.loads(my_teams(tokens[dev]).text)] == [team_id]
+
+    assert create_label('bug', 'red', tokens[lead]).status_code == 406
+    bug = json.loads(create_label('bug', '#FF0000', tokens[lead], team_id, 'Something is broken').text)
+    assert bug['team_id'] == team_id and bug['color'] == '#ff0000'
+    assert create_label('bug', '#00ff00', tokens[dev], team_id).status_code == 409
+    defect = json.loads(create_label('defect', '#aa0000', tokens[dev], team_id).text)
+    personal = json.loads(create_label('later', '#0000ff', tokens[lead]).text)
+    assert personal['owner_id'] == lead_id
+    assert create_label('x', '#000000', tokens[outsider], team_id).status_code == 403
+
+    visible = {label['name'] for label in json.loads(list_labels(tokens[dev]).text)}
+    assert visible == {'bug', 'defect'}
+
+    task1 = json.loads(create_task('Crash on start', tokens[lead]).text)["task_id"]
+    task2 = json.loads(create_task('Wrong colour', tokens[lead]).text)["task_id"]
+    assert attach_label(task1, bug['label_id'], tokens[lead]).status_code == 200
+    assert attach_label(task1, personal['label_id'], tokens[lead]).status_code == 200
+    assert attach_label(task2, defect['label_id'], tokens[lead]).status_code == 200
+    assert attach_label(task2, personal['label_id'], tokens[outsider]).status_code == 403
+
+    filtered = json.loads(list_tasks(lead_id, 0, 10, label_ids=[bug['label_id'], personal['label_id']]).text)['tasks']
+    assert [task['task_id'] for task in filtered] == [task1]
+
+    assert update_label(bug['label_id'], tokens[dev], name='crash').status_code == 200
+    assert [l['name'] for l in json.loads(get_task(task1).text)['labels']] == ['crash', 'later']
+
+    assert merge_labels(defect['label_id'], bug['label_id'], tokens[dev]).status_code == 200
+    assert merge_labels(personal['label_id'], bug['label_id'], tokens[lead]).status_code == 409
+    filtered = json.loads(list_tasks(lead_id, 0, 10, label_ids=[bug['label_id']]).text)['tasks']
+    assert [task['task_id'] for task in filtered] == [task1, task2]
+
+    assert detach_label(task1, bug['label_id'], tokens[lead]).status_code == 200
+    assert [l['name'] for l in json.loads(get_task(task1).text)['labels']] == ['later']
+
+    print('test_labels OK')
+
+
+def random_project_key():
+    return 'P' + ''.join(random.choice(ascii_uppercase + digits) for _ in range(7))
+
+
+def test_projects():
+    password = 'aaaaaA1*'
+    owner, member, outsider = random_str(10), random_str(10), random_str(10)
+    tokens = {}
+    for username in [owner, member, outsider]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+
+    assert create_project('ops', 'Operations', tokens[owner]).status_code == 406
+    key = random_project_key()
+    project_id = json.loads(create_project(key, 'Operations', tokens[owner]).text)["project_id"]
+    assert create_project(key, 'Duplicate', tokens[member]).status_code == 409
+    assert add_project_member(project_id, user_id_from_token(tokens[member]), tokens[member]).status_code == 403
+    assert add_project_member(project_id, user_id_from_token(tokens[member]), tokens[owner]).status_code == 200
+
+    first = json.loads(create_task('Rotate keys', tokens[owner], project_id=project_id).text)["task_id"]
+    second = json.loads(create_task('Renew certs', tokens[member], project_id=project_id).text)["task_id"]
+    assert create_task('Sneak in', tokens[outsider], project_id=project_id).status_code == 403
+
+    assert json.loads(get_task(first).text)['key'] == f'{key}-1'
+    by_key = json.loads(get_task_by_key(f'{key}-2').text)
+    assert by_key['task_id'] == second and by_key['project_id'] == project_id
+    assert get_task_by_key(f'{key}-3').status_code == 404
+    assert get_task_by_key('not-a-key').status_code == 406
+
+    listed = json.loads(project_tasks(project_id, 0, 10, tokens[member]).text)['tasks']
+    assert [task['key'] for task in listed] == [f'{key}-1', f'{key}-2']
+    assert project_tasks(project_id, 0, 10, tokens[outsider]).status_code == 403
+
+    assert archive_project(project_id, True, tokens[member]).status_code == 403
+    assert archive_project(project_id, True, tokens[owner]).status_code == 200
+    assert create_task('Too late', tokens[owner], project_id=project_id).status_code == 409
+    assert project_tasks(project_id, 0, 10, tokens[owner]).status_code == 200
+    projects = json.loads(my_projects(tokens[member]).text)
+    assert [(p['key'], p['archived']) for p in projects] == [(key, True)]
+
+    print('test_projects OK')
+
+
+def test_board():
+    password = 'aaaaaA1*'
+    owner, outsider = random_str(10), random_str(10)
+    tokens = {}
+    for username in [owner, outsider]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    key = random_project_key()
+    project_id = json.loads(create_project(key, 'Board', tokens[owner]).text)["project_id"]
+    first, second, third = [json.loads(create_task(f'Card {i}', tokens[owner], project_id=project_id).text)["task_id"]
+                            for i in range(3)]
+
+    def column(status):
+        board = json.loads(get_board(key, tokens[owner]).text)
+        return [task['task_id'] for c in board['columns'] if c['status'] == status for task in c['tasks']]
+
+    board = json.loads(get_board(key, tokens[owner]).text)
+    assert board['project_id'] == project_id
+    assert [c['status'] for c in board['columns']] == ['backlog', 'todo', 'in_progress', 'review', 'done']
+    assert column('backlog') == [first, second, third]
+    assert get_board(key, tokens[outsider]).status_code == 403
+    assert get_board('NOSUCHKEY', tokens[owner]).status_code == 404
+
+    assert move_board_task(key, third, 'backlog', tokens[owner]).status_code == 200
+    assert column('backlog') == [third, first, second]
+    assert move_board_task(key, third, 'backlog', tokens[owner], after_task_id=first).status_code == 200
+    assert column('backlog') == [first, third, second]
+    assert move_board_task(key, second, 'backlog', tokens[owner], after_task_id=second).status_code == 406
+    assert move_board_task(key, first, 'done', tokens[owner]).status_code == 409
+
+    assert set_wip_limit(key, 'todo', 1, tokens[outsider]).status_code == 403
+    assert set_wip_limit(key, 'nowhere', 1, tokens[owner]).status_code == 406
+    assert set_wip_limit(key, 'todo', 1, tokens[owner]).status_code == 200
+    assert move_board_task(key, second, 'todo', tokens[owner]).status_code == 200
+    assert move_board_task(key, first, 'todo', tokens[owner], after_task_id=second).status_code == 409
+    assert move_board_task(key, third, 'todo', tokens[owner]).status_code == 409
+    assert set_wip_limit(key, 'todo', None, tokens[owner]).status_code == 200
+    assert move_board_task(key, first, 'todo', tokens[owner], after_task_id=second).status_code == 200
+    assert column('todo') == [second, first]
+    assert json.loads(get_task(first).text)['status'] == 'todo'
+    assert [t['to_status'] for t in json.loads(task_transitions(first).text)] == ['todo']
+
+    print('test_board OK')
+
+
+def test_dependencies():
+    password = 'aaaaaA1*'
+    owner, outsider = random_str(10), random_str(10)
+    tokens = {}
+    for username in [owner, outsider]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    token = tokens[owner]
+    project_id = json.loads(create_project(random_project_key(), 'Deps', token).text)["project_id"]
+
+    epic = json.loads(create_task('Epic', token, project_id=project_id).text)["task_id"]
+    first = json.loads(create_task('Part 1', token, project_id=project_id, parent_id=epic,
+                                   estimate_minutes=60).text)["task_id"]
+    second = json.loads(create_task('Part 2', token, project_id=project_id, parent_id=epic,
+                                    estimate_minutes=30).text)["task_id"]
+    assert create_task('Stray', token, parent_id=epic).status_code == 406
+    assert create_task('Huge', token, estimate_minutes=-1).status_code == 406
+    assert json.loads(get_task(epic).text)['progress'] == {'subtasks': 2, 'done': 0, 'percent': 0}
+    assert [task['task_id'] for task in json.loads(subtasks(epic).text)['tasks']] == [first, second]
+    assert update_task(epic, None, token, parent_id=first).status_code == 409
+    assert update_task(epic, None, token, parent_id=epic).status_code == 409
+
+    assert add_dependency(first, second, tokens[outsider]).status_code == 403
+    assert add_dependency(first, second, token).status_code == 200
+    assert add_dependency(second, first, token).status_code == 409
+    assert add_dependency(first, first, token).status_code == 406
+    assert json.loads(get_task(second).text)['blocked']
+    assert not json.loads(get_task(first).text)['blocked']
+    assert json.loads(task_dependencies(second).text) == {'blocked_by': [first], 'blocks': []}
+
+    graph = json.loads(dependency_graph(project_id, token).text)
+    assert sorted(node['task_id'] for node in graph['nodes']) == [epic, first, second]
+    assert graph['edges'] == [{'blocker_id': first, 'blocked_id': second}]
+    assert graph['critical_path'] == {'task_ids': [first, second], 'total_minutes': 90, 'unestimated_task_ids': []}
+    assert dependency_graph(project_id, tokens[outsider]).status_code == 403
+
+    for status in ['todo', 'in_progress', 'review', 'done']:
+        assert transition_task(first, status, token).status_code == 200
+    assert not json.loads(get_task(second).text)['blocked']
+    assert json.loads(get_task(epic).text)['progress'] == {'subtasks': 2, 'done': 1, 'percent': 50}
+    assert json.loads(dependency_graph(project_id, token).text)['critical_path']['total_minutes'] == 30
+
+    assert remove_dependency(first, second, token).status_code == 200
+    assert remove_dependency(first, second, token).status_code == 404
+
+    print('test_dependencies OK')
+
+
+def test_search():
+    password = 'aaaaaA1*'
+    author, outsider = random_str(10), random_str(10)
+    tokens = {}
+    for username in [author, outsider]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    token = tokens[author]
+    author_id = user_id_from_token(token)
+    word = random_str(12)
+
+    titled = json.loads(create_task(f'Rotate {word} keys', token, description='Quarterly <b>chore</b>').text)["task_id"]
+    described = json.loads(create_task('Chore', token, description=f'Also rotate the {word} certificates').text)["task_id"]
+    commented = json.loads(create_task('Unrelated', token).text)["task_id"]
+    create_comment(commented, f'Blocked on {word} rotation', token)
+    project_id = json.loads(create_project(random_project_key(), 'Secret', token).text)["project_id"]
+    hidden = json.loads(create_task('Project task', token, description=word, project_id=project_id).text)["task_id"]
+
+    result = json.loads(search(word, token).text)
+    assert result['total_count'] == 4
+    hits = [hit['task']['task_id'] for hit in result['hits']]
+    assert hits[0] == titled and set(hits) == {titled, described, commented, hidden}
+    assert result['hits'][0]['title_snippet'] == f'Rotate <mark>{word}</mark> keys'
+    assert [hit['comment_snippet'] for hit in result['hits'] if hit['task']['task_id'] == commented] == \
+        [f'Blocked on <mark>{word}</mark> rotation']
+
+    outsider_hits = [hit['task']['task_id'] for hit in json.loads(search(word, tokens[outsider]).text)['hits']]
+    assert hidden not in outsider_hits and len(outsider_hits) == 3
+
+    phrase = json.loads(search(f'"rotate the {word}"', token).text)
+    assert [hit['task']['task_id'] for hit in phrase['hits']] == [described]
+    prefix = json.loads(search(f'{word[:6]}* certif*', token).text)
+    assert [hit['task']['task_id'] for hit in prefix['hits']] == [described]
+    excluded = json.loads(search(f'{word} -keys', token).text)
+    assert titled not in [hit['task']['task_id'] for hit in excluded['hits']]
+
+    filtered = json.loads(search(word, token, author_id=author_id, status='backlog',
+                                 created_after='2000-01-01', limit=2).text)
+    assert filtered['total_count'] == 4 and len(filtered['hits']) == 2
+    assert json.loads(search(word, token, created_before='2000-01-01').text)['total_count'] == 0
+    assert search('-only', token).status_code == 406
+    assert search(word, token, created_after='yesterday').status_code == 406
+
+    print('test_search OK')
+
+
+def test_task_listing():
+    password = 'aaaaaA1*'
+    username = random_str(10)
+    signup(username, password)
+    token = login(username, password).headers["Authorization"]
+    user_id = user_id_from_token(token)
+    project_id = json.loads(create_project(random_project_key(), 'Listing', token).text)["project_id"]
+
+    specs = [('low', '2030-01-03'), ('urgent', None), ('high', '2030-01-01'), ('medium', '2030-01-02'), ('urgent', None)]
+    task_ids = []
+    for i, (priority, due_date) in enumerate(specs):
+        fields = {'priority': priority, 'project_id': project_id}
+        if due_date:
+            fields['due_date'] = due_date
+        task_ids.append(json.loads(create_task(f'Listed {i}', token, **fields).text)["task_id"])
+    like(task_ids[3], token)
+
+    def collect(**query):
+        seen, cursor = [], None
+        while True:
+            page = json.loads(project_tasks(project_id, 0, 2, token, cursor=cursor, **query).text)
+            seen += [task['task_id'] for task in page['tasks']]
+            cursor = page['next_cursor']
+            if cursor is None:
+                return seen
+
+    assert collect() == task_ids
+    assert collect(order='desc') == task_ids[::-1]
+    assert collect(sort='priority', order='desc') == [task_ids[4], task_ids[1], task_ids[2], task_ids[3], task_ids[0]]
+    assert collect(sort='due') == [task_ids[2], task_ids[3], task_ids[0], task_ids[1], task_ids[4]]
+    assert collect(sort='due', order='desc')[:3] == [task_ids[0], task_ids[3], task_ids[2]]
+    assert collect(sort='due', due_after='2030-01-02') == [task_ids[3], task_ids[0]]
+
+    first_page = json.loads(project_tasks(project_id, 0, 2, token, include_total=True).text)
+    assert first_page['total_count'] == 5 and len(first_page['tasks']) == 2
+    # Tasks created after the first page was loaded don't shift the following pages.
+    create_task('Late', token, project_id=project_id)
+    second_page = json.loads(project_tasks(project_id, 0, 2, token, cursor=first_page['next_cursor']).text)
+    assert [task['task_id'] for task in second_page['tasks']] == task_ids[2:4]
+
+    assert project_tasks(project_id, 0, 101, token).status_code == 406
+    assert project_tasks(project_id, 2, 2, token, cursor=first_page['next_cursor']).status_code == 406
+    forged = base64.urlsafe_b64encode(json.dumps({'sort': 0, 'desc': False, 'key': 'x', 'id': 1}).encode()).decode()
+    assert project_tasks(project_id, 0, 2, token, cursor=forged).status_code == 406
+    assert project_tasks(project_id, 0, 2, token).status_code == 200
+    assert project_tasks(project_id, 0, 2, token, sort='likes', cursor=first_page['next_cursor']).status_code == 406
+    assert project_tasks(project_id, 0, 2, token, cursor='garbage').status_code == 406
+    assert project_tasks(project_id, 0, 2, token, sort='random').status_code == 406
+    assert project_tasks(project_id, 0, 2, token, statuses=['nowhere']).status_code == 406
+    assert json.loads(list_tasks(user_id, 0, 10, sort='likes', order='desc').text)['tasks'][0]['task_id'] == task_ids[3]
+    assert len(json.loads(list_tasks(user_id, 0, 10, statuses=['todo']).text)['tasks']) == 0
+
+    print('test_task_listing OK')
+
+
+def test_revisions():
+    password = 'aaaaaA1*'
+    author, other = random_str(10), random_str(10)
+    tokens = {}
+    for username in [author, other]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    token = tokens[author]
+
+    task_id = json.loads(create_task('Rotate keys', token, description='Rotate the old keys today').text)["task_id"]
+    assert update_task(task_id, None, token, description='Rotate the new keys', priority='high').status_code == 200
+    assert update_task(task_id, None, token, priority='high').status_code == 200
+    assert transition_task(task_id, 'todo', token).status_code == 200
+
+    revisions = json.loads(task_revisions(task_id).text)
+    assert [r['revision_number'] for r in revisions] == [1, 2, 3]
+    assert revisions[1]['changed_fields'] == ['text', 'priority']
+    assert revisions[2]['changed_fields'] == ['status']
+    assert revisions[2]['actor_id'] == user_id_from_token(token)
+
+    diff = json.loads(revision_diff(task_id, 1, 2, 'words').text)
+    assert [c['field'] for c in diff['changes']] == ['text', 'priority']
+    assert diff['changes'][0]['diff'] == 'Rotate the [-old-]{+new+} keys[- today-]'
+    assert diff['changes'][1] == {'field': 'priority', 'old': 'medium', 'new': 'high', 'diff': None}
+    unified = json.loads(revision_diff(task_id, 1, 3).text)['changes'][0]['diff']
+    assert '-Rotate the old keys today' in unified and '+Rotate the new keys' in unified
+    assert revision_diff(task_id, 1, 9).status_code == 404
+    assert revision_diff(task_id, 1, 2, 'chars').status_code == 406
+
+    assert restore_revision(task_id, 1, tokens[other]).status_code == 403
+    restored = json.loads(restore_revision(task_id, 1, token).text)
+    assert restored['revision_number'] == 4 and restored['changed_fields'] == ['text', 'priority']
+    task = json.loads(get_task(task_id).text)
+    assert task['description'] == 'Rotate the old keys today' and task['status'] == 'todo'
+    assert restore_revision(task_id, 4, token).status_code == 409
+
+    print('test_revisions OK')
+
+
+def test_trash():
+    password = 'aaaaaA1*'
+    author, other = random_str(10), random_str(10)
+    tokens = {}
+    for username in [author, other]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    token = tokens[author]
+
+    blocker = json.loads(create_task('Order parts', token).text)["task_id"]
+    blocked = json.loads(create_task('Assemble', token).text)["task_id"]
+    assert add_dependency(blocker, blocked, token).status_code == 200
+    assert json.loads(get_task(blocked).text)['blocked']
+
+    assert delete_task(blocker, tokens[other]).status_code == 403
+    assert delete_task(blocker, token).status_code == 200
+    assert get_task(blocker).status_code == 404
+    assert not json.loads(get_task(blocked).text)['blocked']
+    assert json.loads(task_dependencies(blocked).text)['blocked_by'] == []
+    created = json.loads(my_tasks('created_by_me', token).text)['tasks']
+    assert [t['task_id'] for t in created] == [blocked]
+    assert update_task(blocker, 'Order more parts', token).status_code == 403
+
+    trashed = json.loads(trash(token).text)
+    assert [t['task_id'] for t in trashed] == [blocker]
+    assert trashed[0]['title'] == 'Order parts' and trashed[0]['purge_at'] > trashed[0]['deleted_at']
+    assert json.loads(trash(tokens[other]).text) == []
+
+    assert restore_task(blocker, tokens[other]).status_code == 403
+    assert restore_task(blocker, token).status_code == 200
+    assert restore_task(blocker, token).status_code == 404
+    assert json.loads(trash(token).text) == []
+    assert json.loads(get_task(blocker).text)['title'] == 'Order parts'
+    assert json.loads(get_task(blocked).text)['blocked']
+
+    print('test_trash OK')
+
+
+def test_attachments():
+    password = 'aaaaaA1*'
+    author, other = random_str(10), random_str(10)
+    tokens = {}
+    for username in [author, other]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    token = tokens[author]
+    task_id = json.loads(create_task('Broken login page', token).text)["task_id"]
+    other_task_id = json.loads(create_task('Another task', token).text)["task_id"]
+
+    png = b'\x89PNG\r\n\x1a\n' + bytes(range(256)) * 4
+    resp = upload_attachment(task_id, '../screens/login.png', png, tokens[other])
+    assert resp.status_code == 201
+    screenshot = json.loads(resp.text)
+    assert screenshot['filename'] == 'login.png' and screenshot['content_type'] == 'image/png'
+    assert screenshot['size'] == len(png) and screenshot['uploader_id'] == user_id_from_token(tokens[other])
+    # The declared type is ignored, the contents decide.
+    log = json.loads(upload_attachment(task_id, 'error log.html', b'<script>alert(1)</script>', token).text)
+    assert log['content_type'] == 'text/plain; charset=utf-8'
+
+    resp = download_attachment(screenshot['attachment_id'])
+    assert resp.status_code == 200 and resp.content == png
+    assert resp.headers['Content-Type'] == 'image/png'
+    assert resp.headers['Content-Disposition'].startswith('attachment; filename="login.png"')
+    assert resp.headers['X-Content-Type-Options'] == 'nosniff'
+    assert download_attachment(100500).status_code == 404
+
+    task = json.loads(get_task(task_id).text)
+    assert [a['attachment_id'] for a in task['attachments']] == [screenshot['attachment_id'], log['attachment_id']]
+
+    # The same contents are stored once.
+    copy = json.loads(upload_attachment(other_task_id, 'copy.png', png, token).text)
+    assert copy['sha256'] == screenshot['sha256']
+    assert delete_attachment(screenshot['attachment_id'], tokens[other]).status_code == 200
+    assert download_attachment(screenshot['attachment_id']).status_code == 404
+    assert download_attachment(copy['attachment_id']).content == png
+    assert delete_attachment(copy['attachment_id'], tokens[other]).status_code == 403
+    assert delete_attachment(copy['attachment_id'], token).status_code == 200
+
+    assert upload_attachment(task_id, 'big.bin', b'x' * (1048576 + 1), token).status_code == 413
+    for _ in range(2):
+        assert upload_attachment(task_id, 'part.bin', b'\x00' * 1000000, token).status_code == 201
+    assert upload_attachment(task_id, 'part.bin', b'\x00' * 100000, token).status_code == 413
+    assert upload_attachment(task_id, '..', b'data', token).status_code == 406
+    assert upload_attachment(100500, 'a.txt', b'data', token).status_code == 404
+
+    print('test_attachments OK')
+
+
+def test_bulk():
+    password = 'aaaaaA1*'
+    author, other = random_str(10), random_str(10)
+    tokens = {}
+    for username in [author, other]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    token = tokens[author]
+    other_id = user_id_from_token(tokens[other])
+    task_id = json.loads(create_task('Triage me', token).text)["task_id"]
+    foreign_id = json.loads(create_task('Not yours', tokens[other]).text)["task_id"]
+
+    resp = bulk_tasks([
+        {'op': 'create', 'title': 'Bulk one'},
+        {'op': 'create', 'title': ''},
+        {'op': 'update', 'task_id': task_id, 'priority': 'high'},
+        {'op': 'status', 'task_id': task_id, 'status': 'todo'},
+        {'op': 'status', 'task_id': task_id, 'status': 'in_progress'},
+        {'op': 'assign', 'task_id': task_id, 'assignee_id': other_id},
+        {'op': 'delete', 'task_id': foreign_id},
+    ], token)
+    assert resp.status_code == 200
+    body = json.loads(resp.text)
+    assert body['committed']
+    assert [r['status'] for r in body['results']] == [201, 406, 200, 200, 200, 200, 403]
+    assert [r['index'] for r in body['results']] == list(range(7))
+    created_id = body['results'][0]['task_id']
+    assert json.loads(get_task(created_id).text)['title'] == 'Bulk one'
+    task = json.loads(get_task(task_id).text)
+    assert (task['priority'], task['status'], task['assignee_id']) == ('high', 'in_progress', other_id)
+    assert get_task(foreign_id).status_code == 200
+
+    # Nothing is applied when an operation is rejected up front...
+    body = json.loads(bulk_tasks([
+        {'op': 'update', 'task_id': task_id, 'priority': 'low'},
+        {'op': 'status', 'task_id': task_id, 'status': 'done'},
+    ], token, 'atomic').text)
+    assert not body['committed'] and [r['status'] for r in body['results']] == [424, 409]
+    # ...or fails in tasks_service.
+    body = json.loads(bulk_tasks([
+        {'op': 'create', 'title': 'Rolled back'},
+        {'op': 'update', 'task_id': task_id, 'priority': 'low'},
+        {'op': 'delete', 'task_id': foreign_id},
+        {'op': 'assign', 'task_id': task_id, 'assignee_id': None},
+    ], token, 'atomic').text)
+    assert not body['committed'] and [r['status'] for r in body['results']] == [424, 424, 403, 424]
+    task = json.loads(get_task(task_id).text)
+    assert (task['priority'], task['assignee_id']) == ('high', other_id)
+    created = json.loads(my_tasks('created_by_me', token).text)['tasks']
+    assert sorted(t['task_id'] for t in created) == sorted([task_id, created_id])
+
+    assert bulk_tasks([], token).status_code == 406
+    assert bulk_tasks([{'op': 'delete', 'task_id': task_id}] * 101, token).status_code == 406
+    assert bulk_tasks([{'op': 'delete', 'task_id': task_id}], 'bad token').status_code == 401
+
+    print('test_bulk OK')
+
+
+def test_import_export():
+    password = 'aaaaaA1*'
+    username = random_str(10)
+    signup(username, password)
+    token = login(username, password).headers["Authorization"]
+    report_id = json.loads(create_task('Write report', token, priority='high',
+                                       due_date='2030-01-31').text)["task_id"]
+    ship_id = json.loads(create_task('Ship it', token).text)["task_id"]
+    for status in ['todo', 'in_progress', 'review', 'done']:
+        assert transition_task(ship_id, status, token).status_code == 200
+
+    resp = export_tasks('csv', token)
+    assert resp.status_code == 200
+    assert resp.headers['Content-Type'].startswith('text/csv')
+    assert resp.headers['Content-Disposition'] == 'attachment; filename="tasks.csv"'
+    rows = list(csv.DictReader(io.StringIO(resp.text)))
+    assert sorted(r['title'] for r in rows) == ['Ship it', 'Write report']
+    report = next(r for r in rows if r['title'] == 'Write report')
+    assert report['priority'] == 'high' and report['due_date'].startswith('2030-01-31')
+
+    exported = json.loads(export_tasks('json', token).text)
+    assert sorted(t['task_id'] for t in exported) == sorted([report_id, ship_id])
+
+    checklist = export_tasks('markdown', token).text.splitlines()
+    assert any(line.startswith('- [x] ') and line.endswith('Ship it') for line in checklist)
+    assert any(line.startswith('- [ ] ') and line.endswith('Write report (due 2030-01-31)')
+               for line in checklist)
+
+    calendar = export_tasks('ical', token).text
+    assert calendar.startswith('BEGIN:VCALENDAR\r\n') and calendar.endswith('END:VCALENDAR\r\n')
+    assert calendar.count('BEGIN:VTODO') == 2
+    assert 'STATUS:COMPLETED' in calendar and 'DUE:20300131T000000Z' in calendar
+
+    assert export_tasks('xml', token).status_code == 406
+    assert export_tasks('csv', 'bad token').status_code == 401
+
+    data = ('ID,Name,Notes,Due\n'
+            'ext-1,Imported one,First,2030-02-01\n'
+            'ext-2,Imported two,,\n'
+            'ext-3,,No title,\n'
+            'ext-4,Bad due,,someday\n'
+            'ext-1,Again,,\n')
+    mapping = {'external_id': 'ID', 'title': 'Name', 'description': 'Notes', 'due_date': 'Due'}
+    body = json.loads(import_tasks('csv', data, token, mapping, dry_run=True).text)
+    assert body['dry_run']
+    assert (body['created'], body['updated'], body['unchanged'], body['failed']) == (2, 0, 0, 3)
+    assert [r['row'] for r in body['rows']] == [2, 3, 4, 5, 6]
+    assert [r['action'] for r in body['rows']] == ['create', 'create', 'error', 'error', 'error']
+    assert all(r['errors'] for r in body['rows'][2:])
+    assert len(json.loads(my_tasks('created_by_me', token).text)['tasks']) == 2
+
+    body = json.loads(import_tasks('csv', data, token, mapping).text)
+    assert (body['created'], body['failed']) == (2, 3)
+    imported_id = body['rows'][0]['task_id']
+    task = json.loads(get_task(imported_id).text)
+    assert (task['title'], task['description'], task['external_id']) == ('Imported one', 'First', 'ext-1')
+    assert task['due_date'].startswith('2030-02-01')
+
+    # Importing the same file again matches the rows to the tasks imported before.
+    body = json.loads(import_tasks('csv', data, token, mapping).text)
+    assert (body['created'], body['updated'], body['unchanged']) == (0, 0, 2)
+    assert body['rows'][0]['task_id'] == imported_id
+    body = json.loads(import_tasks('csv', data.replace('First', 'Changed'), token, mapping).text)
+    assert (body['updated'], body['unchanged']) == (1, 1)
+    assert json.loads(get_task(imported_id).text)['description'] == 'Changed'
+    assert len(json.loads(my_tasks('created_by_me', token).text)['tasks']) == 4
+    body = json.loads(import_tasks('json', [{'key': 'ext-1', 'status': 'done'}], token,
+                                   {'external_id': 'key', 'status': 'status'}).text)
+    assert body['failed'] == 1 and body['rows'][0]['task_id'] == imported_id
+    assert json.loads(get_task(imported_id).text)['status'] != 'done'
+
+    body = json.loads(import_tasks('json', [
+        {'key': 'j-1', 'title': 'From JSON', 'estimate_minutes': 30},
+        {'key': 'j-2', 'title': 'Bad estimate', 'estimate_minutes': 'soon'},
+        'not an object',
+    ], token, {'external_id': 'key'}).text)
+    assert (body['created'], body['failed']) == (1, 2)
+    assert json.loads(get_task(body['rows'][0]['task_id']).text)['estimate_minutes'] == 30
+
+    assert import_tasks('csv', data, token, {'owner': 'ID'}).status_code == 406
+    assert import_tasks('csv', data, token, {'title': 'Missing'}).status_code == 406
+    assert import_tasks('json', {'title': 'x'}, token).status_code == 406
+    assert import_tasks('csv', data, 'bad token').status_code == 401
+
+    print('test_import_export OK')
+
+
+def test_recurring():
+    password = 'aaaaaA1*'
+    author, other = random_str(10), random_str(10)
+    tokens = {}
+    for username in [author, other]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    token = tokens[author]
+    now = datetime.datetime.now(datetime.timezone.utc)
+
+    # Started a week ago: the missed occurrences are skipped and the latest one is created.
+    start = (now - datetime.timedelta(days=7)).strftime('%Y-%m-%d') + 'T09:00'
+    resp = create_series('Dependency review', 'FREQ=DAILY', start, token,
+                         timezone='Europe/Berlin', priority='low')
+    assert resp.status_code == 200
+    body = json.loads(resp.text)
+    series_id, first_id = body['series_id'], body['task_id']
+    series = json.loads(get_series(series_id, token).text)
+    assert series['occurrence_ids'] == [first_id] and series['timezone'] == 'Europe/Berlin'
+    first = json.loads(get_task(first_id).text)
+    assert (first['title'], first['priority'], first['series_id']) == ('Dependency review', 'low', series_id)
+    first_due = datetime.datetime.fromisoformat(first['due_date'])
+    next_at = datetime.datetime.fromisoformat(series['next_at'])
+    assert first_due <= now < next_at and next_at - first_due <= datetime.timedelta(days=1, hours=1)
+    upcoming = [datetime.datetime.fromisoformat(t) for t in series['upcoming']]
+    assert len(upcoming) == 5 and upcoming[0] == next_at
+    assert all((t.hour, t.minute) == (9, 0) for t in upcoming)
+
+    # Completing the occurrence creates the next one right away.
+    for status in ['todo', 'in_progress', 'review', 'done']:
+        assert transition_task(first_id, status, token).status_code == 200
+    series = json.loads(get_series(series_id, token).text)
+    assert len(series['occurrence_ids']) == 2
+    second_id = series['occurrence_ids'][1]
+    second = json.loads(get_task(second_id).text)
+    assert (second['title'], second['status']) == ('Dependency review', 'backlog')
+    assert datetime.datetime.fromisoformat(second['due_date']) == next_at
+    assert datetime.datetime.fromisoformat(series['next_at']) == upcoming[1]
+
+    # This occurrence only...
+    assert update_task(second_id, None, token, title='Review deps once').status_code == 200
+    assert json.loads(get_series(series_id, token).text)['title'] == 'Dependency review'
+    # ...or the whole series, which leaves the finished occurrences alone.
+    resp = update_series(series_id, token, title='Weekly review', priority='high')
+    assert resp.status_code == 200
+    assert json.loads(resp.text)['title'] == 'Weekly review'
+    assert json.loads(get_task(second_id).text)['title'] == 'Weekly review'
+    assert json.loads(get_task(first_id).text)['title'] == 'Dependency review'
+    assert update_series(series_id, tokens[other], title='Mine').status_code == 403
+
+    # A new rule keeps the wall-clock time of the start in the new timezone.
+    resp = update_series(series_id, token, rrule='FREQ=WEEKLY;BYDAY=MO', timezone='America/New_York')
+    assert resp.status_code == 200
+    upcoming = [datetime.datetime.fromisoformat(t) for t in json.loads(resp.text)['upcoming']]
+    assert all((t.weekday(), t.hour) == (0, 9) for t in upcoming)
+    assert upcoming[0] > datetime.datetime.fromisoformat(second['due_date'])
+
+    assert stop_series(series_id, tokens[other]).status_code == 403
+    assert stop_series(series_id, token).status_code == 200
+    series = json.loads(get_series(series_id, token).text)
+    assert series['next_at'] is None and series['upcoming'] == []
+
+    # The scheduler creates an occurrence when its start arrives.
+    start = (now - datetime.timedelta(days=1, seconds=-4)).strftime('%Y-%m-%dT%H:%M:%SZ')
+    series_id = json.loads(create_series('On-call handover', 'FREQ=DAILY;COUNT=2', start, token).text)['series_id']
+    for _ in range(20):
+        series = json.loads(get_series(series_id, token).text)
+        if len(series['occurrence_ids']) == 2:
+            break
+        time.sleep(1)
+    assert len(series['occurrence_ids']) == 2
+    assert series['next_at'] is None
+
+    for rrule in ['FREQ=YEARLY', 'FREQ=DAILY;COUNT=0', 'FREQ=WEEKLY;BYDAY=1MO', 'INTERVAL=2',
+                  'FREQ=DAILY;BYDAY=éA']:
+        assert create_series('x', rrule, start, token).status_code == 406
+    assert create_series('x', 'FREQ=DAILY', start, token, timezone='Mars/Olympus').status_code == 406
+    assert create_series('x', 'FREQ=DAILY', 'someday', token).status_code == 406
+    assert create_series('x', 'FREQ=DAILY', start, token, due_date='2030-01-01').status_code == 406
+    assert create_series('x', 'FREQ=DAILY', start, 'bad token').status_code == 401
+    assert get_series(10 ** 12, token).status_code == 404
+    assert get_series(series_id).status_code == 401
+    # A series is seen by those who may see its occurrences.
+    assert get_series(series_id, tokens[other]).status_code == 200
+    hidden_id = json.loads(create_series('Payroll', 'FREQ=DAILY', start, token, visibility='private').text)['series_id']
+    assert get_series(hidden_id, tokens[other]).status_code == 404
+    assert get_series(hidden_id, token).status_code == 200
+
+    print('test_recurring OK')
+
+
+def wait_for_reminders(task_id, token, statuses, timeout=20):
+    for _ in range(timeout):
+        reminders = json.loads(list_reminders(task_id, token).text)
+        if [r['status'] for r in reminders] == statuses:
+            return reminders
+        time.sleep(1)
+    assert False, reminders
+
+
+def test_reminders():
+    password = 'aaaaaA1*'
+    author, other = random_str(10), random_str(10)
+    tokens = {}
+    for username in [author, other]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    token = tokens[author]
+    now = datetime.datetime.now(datetime.timezone.utc)
+    due = now + datetime.timedelta(hours=2)
+    task_id = json.loads(create_task('Renew certificate', token, due_date=due.isoformat()).text)["task_id"]
+    undated_id = json.loads(create_task('Someday', token).text)["task_id"]
+
+    resp = create_reminder(task_id, token, remind_at=(now + datetime.timedelta(seconds=2)).isoformat())
+    assert resp.status_code == 201
+    soon = json.loads(resp.text)
+    assert (soon['task_id'], soon['status'], soon['sent_at']) == (task_id, 'pending', None)
+    resp = create_reminder(task_id, token, minutes_before_due=30)
+    assert resp.status_code == 201
+    before_due = json.loads(resp.text)
+    assert before_due['minutes_before_due'] == 30 and before_due['remind_at'] is None
+
+    reminders = wait_for_reminders(task_id, token, ['sent', 'pending'])
+    assert reminders[0]['sent_at'] is not None
+    # Reminders relative to the due date follow it.
+    due = datetime.datetime.now(datetime.timezone.utc) + datetime.timedelta(minutes=30, seconds=2)
+    assert update_task(task_id, None, token, due_date=due.isoformat()).status_code == 200
+    wait_for_reminders(task_id, token, ['sent', 'sent'])
+    # So do they when a revision brings an earlier due date back.
+    due = datetime.datetime.now(datetime.timezone.utc) + datetime.timedelta(minutes=30, seconds=2)
+    restored_id = json.loads(create_task('Renew domain', token, due_date=due.isoformat()).text)["task_id"]
+    assert update_task(restored_id, None, token, due_date='2100-01-01T00:00:00Z').status_code == 200
+    assert create_reminder(restored_id, token, minutes_before_due=30).status_code == 201
+    time.sleep(3)
+    wait_for_reminders(restored_id, token, ['pending'])
+    assert restore_revision(restored_id, 1, token).status_code == 200
+    wait_for_reminders(restored_id, token, ['sent'])
+
+    # Every user has their own reminders.
+    assert json.loads(list_reminders(task_id, tokens[other]).text) == []
+    resp = create_reminder(task_id, tokens[other], remind_at='2100-01-01')
+    assert resp.status_code == 201
+    reminder_id = json.loads(resp.text)['reminder_id']
+    assert delete_reminder(reminder_id, token).status_code == 404
+    assert delete_reminder(reminder_id, tokens[other]).status_code == 200
+    assert delete_reminder(reminder_id, tokens[other]).status_code == 404
+    assert json.loads(list_reminders(task_id, tokens[other]).text) == []
+
+    for _ in range(8):
+        assert create_reminder(task_id, token, remind_at='2100-01-01').status_code == 201
+    assert create_reminder(task_id, token, remind_at='2100-01-01').status_code == 406
+
+    assert create_reminder(undated_id, token, minutes_before_due=10).status_code == 406
+    assert create_reminder(undated_id, token).status_code == 406
+    assert create_reminder(undated_id, token, remind_at='2100-01-01', minutes_before_due=10).status_code == 406
+    assert create_reminder(undated_id, token, minutes_before_due=-1).status_code == 406
+    assert create_reminder(undated_id, token, remind_at='tomorrow').status_code == 406
+    assert create_reminder(10 ** 12, token, remind_at='2100-01-01').status_code == 404
+    assert create_reminder(undated_id, 'bad token', remind_at='2100-01-01').status_code == 401
+
+    print('test_reminders OK')
+
+
+def test_notifications():
+    password = 'aaaaaA1*'
+    author, assignee, watcher, stranger = (random_str(10) for _ in range(4))
+    tokens, ids = {}, {}
+    for username in [author, assignee, watcher, stranger]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+        ids[username] = user_id_from_token(tokens[username])
+
+    def events(username, **params):
+        body = json.loads(list_notifications(tokens[username], **params).text)
+        return [(n['event'], n['actor_id']) for n in body['notifications']]
+
+    def latest_message(username):
+        return json.loads(list_notifications(tokens[username]).text)['notifications'][0]['message']
+
+    resp = create_task('Rotate the keys', tokens[author], assignee_id=ids[assignee])
+    task_id = json.loads(resp.text)["task_id"]
+    assert events(assignee) == [('assigned', ids[author])]
+    assert events(author) == []
+
+    assert watch_task(task_id, tokens[watcher]).status_code == 200
+    assert watch_task(10 ** 12, tokens[watcher]).status_code == 404
+    watchers = json.loads(list_watchers(task_id).text)['watchers']
+    assert watchers == sorted([ids[author], ids[assignee], ids[watcher]])
+
+    # Everyone watching hears about a comment, but not the one who wrote it.
+    create_comment(task_id, 'Which keys exactly?', tokens[stranger])
+    for username in [author, assignee, watcher]:
+        assert events(username)[0] == ('commented', ids[stranger])
+    assert 'Which keys exactly?' in latest_message(author)
+    assert events(stranger) == []
+
+    # Repeated likes are one notification.
+    like(task_id, tokens[stranger])
+    like(task_id, tokens[stranger])
+    assert events(author) == [('liked', ids[stranger]), ('commented', ids[stranger])]
+
+    resp = update_notification_preferences([{'event': 'status_changed', 'in_app': False}], tokens[watcher])
+    assert resp.status_code == 200
+    preferences = {p['event']: (p['in_app'], p['external']) for p in json.loads(resp.text)}
+    assert preferences['status_changed'] == (False, False)
+    assert preferences['commented'] == (True, False)
+    assert len(preferences) == 8
+    assert json.loads(notification_preferences(tokens[watcher]).text) == json.loads(resp.text)
+    resp = update_notification_preferences([{'event': 'sneezed', 'in_app': False}], tokens[watcher])
+    assert resp.status_code == 406
+
+    assert transition_task(task_id, 'todo', tokens[author]).status_code == 200
+    assert events(assignee)[0] == ('status_changed', ids[author])
+    assert 'from backlog to todo' in latest_message(assignee)
+    assert events(watcher)[0] == ('commented', ids[stranger])
+
+    # Authors can stop watching their own tasks.
+    assert unwatch_task(task_id, tokens[author]).status_code == 200
+    assert ids[author] not in json.loads(list_watchers(task_id).text)['watchers']
+    assert unassign_task(task_id, tokens[assignee]).status_code == 200
+    assert events(author) == [('liked', ids[stranger]), ('commented', ids[stranger])]
+    assert events(watcher)[0] == ('assigned', ids[assignee])
+
+    # Reading them.
+    body = json.loads(list_notifications(tokens[assignee]).text)
+    assert body['unread_count'] == 3
+    first = body['notifications'][0]['notification_id']
+    assert mark_notification(first, True, tokens[assignee]).status_code == 200
+    assert mark_notification(first, True, tokens[watcher]).status_code == 404
+    assert json.loads(list_notifications(tokens[assignee]).text)['unread_count'] == 2
+    assert len(events(assignee)) == 2
+    body = json.loads(list_notifications(tokens[assignee], include_read='true').text)
+    assert [n['read'] for n in body['notifications']] == [True, False, False]
+    assert mark_notification(first, False, tokens[assignee]).status_code == 200
+    resp = mark_all_notifications_read(tokens[assignee])
+    assert json.loads(resp.text)['marked'] == 3
+    assert json.loads(list_notifications(tokens[assignee]).text) == {'notifications': [], 'unread_count': 0}
+    assert len(events(assignee, include_read='true', limit=2)) == 2
+    assert list_notifications(tokens[assignee], limit=0).status_code == 406
+
+    assert delete_task(task_id, tokens[author]).status_code == 200
+    assert events(watcher)[0] == ('deleted', ids[author])
+    assert list_notifications('bad token').status_code == 401
+    assert watch_task(task_id, 'bad token').status_code == 401
+
+    print('test_notifications OK')
+
+
+def test_mentions():
+    password = 'aaaaaA1*'
+    author, reviewer, helper = (random_str(10) for _ in range(3))
+    tokens, ids = {}, {}
+    for username in [author, reviewer, helper]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+        ids[username] = user_id_from_token(tokens[username])
+
+    def mentioned(username):
+        body = json.loads(list_notifications(tokens[username]).text)
+        return [n['message'] for n in body['notifications'] if n['event'] == 'mentioned']
+
+    text = (f'Ask @{reviewer} for a review. Not `@{helper}`, not mail@{helper}.com, '
+            f'not @nobody{random_str(8)}.\n\n```\n@{helper}\n```\n')
+    task_id = json.loads(create_task(text, tokens[author], title='Review the rollout').text)["task_id"]
+    task = json.loads(get_task(task_id).text)
+    assert task['mentions'] == [{'user_id': ids[reviewer], 'username': reviewer}]
+    assert task['text'] == text
+    assert f'[@{reviewer}](/users/{ids[reviewer]})' in task['rendered_text']
+    assert f'`@{helper}`' in task['rendered_text']
+    assert len(mentioned(reviewer)) == 1
+    assert mentioned(helper) == []
+    assert json.loads(get_user(ids[reviewer]).text) == {'user_id': ids[reviewer], 'username': reviewer}
+    assert get_user(10 ** 12).status_code == 404
+
+    # Saving the text again doesn't mention anyone anew.
+    assert update_task(task_id, text + '\nUpdated.', tokens[author]).status_code == 200
+    assert len(mentioned(reviewer)) == 1
+    assert update_task(task_id, f'@{helper} takes over from @{reviewer}', tokens[author]).status_code == 200
+    assert len(mentioned(reviewer)) == 1
+    assert len(mentioned(helper)) == 1
+    task = json.loads(get_task(task_id).text)
+    assert sorted(m['user_id'] for m in task['mentions']) == sorted([ids[reviewer], ids[helper]])
+
+    resp = create_comment(task_id, f'@{author} @{author} see above', tokens[reviewer])
+    assert resp.status_code == 201
+    comment = json.loads(resp.text)
+    assert comment['mentions'] == [{'user_id': ids[author], 'username': author}]
+    assert comment['rendered_content'].startswith(f'[@{author}](/users/{ids[author]}) [@{author}]')
+    assert len(mentioned(author)) == 1 and 'see above' in mentioned(author)[0]
+    resp = edit_comment(comment['comment_id'], f'@{author} see above, @{reviewer}', tokens[reviewer])
+    assert len(json.loads(resp.text)['mentions']) == 2
+    assert len(mentioned(author)) == 1
+    # Nobody is notified of mentioning themselves.
+    assert len(mentioned(reviewer)) == 1
+    comments = json.loads(list_comments(task_id, 0, 10).text)['comments']
+    assert [m['username'] for m in comments[0]['mentions']] == sorted([author, reviewer], key=lambda u: ids[u])
+
+    print('test_mentions OK')
+
+
+def test_markdown():
+    password = 'aaaaaA1*'
+    author, other = random_str(10), random_str(10)
+    tokens = {}
+    for username in [author, other]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    token = tokens[author]
+
+    text = ('## Release\n\n- [ ] Tag the build\n- [x] Write notes for @' + other + '\n\n'
+            '| Step | Owner |\n|---|---|\n| Deploy | <script>alert(1)</script> |\n\n'
+            '[docs](https://example.com) [bad](javascript:alert(1)) <img src=x onerror=alert(1)>')
+    task_id = json.loads(create_task(text, token, title='Release').text)["task_id"]
+    task = json.loads(get_task(task_id).text)
+    assert task['text'] == text
+    html = task['text_html']
+    assert '<h2>Release</h2>' in html
+    assert '<table>' in html and '<th>Step</th>' in html
+    assert '<input type="checkbox" disabled="" data-index="0">' in html
+    assert 'data-index="1" checked=""' in html
+    assert '<script>' not in html and '&lt;script&gt;' in html
+    assert 'javascript:' not in html and '<img' not in html
+    assert '<a href="https://example.com" rel="noopener noreferrer nofollow">docs</a>' in html
+    assert f'<a href="/users/{user_id_from_token(tokens[other])}"' in html
+
+    comment = json.loads(create_comment(task_id, '**Done** <b onclick="x()">now</b>', token).text)
+    assert comment['content_html'] == '<p><strong>Done</strong> &lt;b onclick="x()"&gt;now&lt;/b&gt;</p>\n'
+    comments = json.loads(list_comments(task_id, 0, 10).text)['comments']
+    assert comments[0]['content_html'] == comment['content_html']
+
+    # Checklist items are toggled in the source text.
+    resp = set_checklist_item(task_id, 0, True, token)
+    assert resp.status_code == 200
+    task = json.loads(resp.text)
+    assert '- [x] Tag the build\n- [x] Write notes' in task['text']
+    assert 'data-index="0" checked=""' in task['text_html']
+    task = json.loads(set_checklist_item(task_id, 1, False, token).text)
+    assert '- [x] Tag the build\n- [ ] Write notes' in task['text']
+    assert json.loads(get_task(task_id).text)['text'] == task['text']
+    assert set_checklist_item(task_id, 1, False, token).status_code == 200
+    assert set_checklist_item(task_id, 2, True, token).status_code == 404
+    assert set_checklist_item(task_id, 0, False, tokens[other]).status_code == 403
+    assert set_checklist_item(10 ** 12, 0, False, token).status_code == 404
+    assert set_checklist_item(task_id, 0, False, 'bad token').status_code == 401
+
+    print('test_markdown OK')
+
+
+def test_templates():
+    password = 'aaaaaA1*'
+    lead, dev, outsider = random_str(10), random_str(10), random_str(10)
+    tokens = {}
+    for username in [lead, dev, outsider]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    token = tokens[lead]
+    team_id = json.loads(create_team(random_str(10), token).text)["team_id"]
+    add_team_member(team_id, user_id_from_token(tokens[dev]), token)
+    label_id = json.loads(create_label(random_str(8), '#ff0000', token, team_id=team_id).text)["label_id"]
+
+    resp = create_template(token, team_id=team_id, name='Bug report', title='Bug: {{ summary }}',
+                           description='Found by {{username}} in {{version}}.', priority='high',
+                           label_ids=[label_id, label_id], checklist=['Reproduce on {{version}}', 'Write a test'],
+                           subtasks=[{'title': 'Fix {{summary}}'}, {'title': 'Release', 'description': 'Ship it'}])
+    assert resp.status_code == 201
+    template = json.loads(resp.text)
+    template_id = template['template_id']
+    assert template['team_id'] == team_id and template['owner_id'] is None
+    assert template['label_ids'] == [label_id]
+    assert template['variables'] == ['summary', 'username', 'version']
+    assert create_template(token, name='x', title='{{oops').status_code == 406
+    assert create_template(token, name='x', title='{{a b}}').status_code == 406
+    assert create_template(token, name='', title='t').status_code == 406
+    assert create_template(tokens[outsider], team_id=team_id, name='x', title='t').status_code == 403
+    personal_label = json.loads(create_label(random_str(8), '#00ff00', token).text)["label_id"]
+    assert create_template(token, team_id=team_id, name='x', title='t', label_ids=[personal_label]).status_code == 406
+    assert create_template(tokens[outsider], name='x', title='t', label_ids=[label_id]).status_code == 403
+
+    # Team members see and use team templates; others don't.
+    assert template_id in [t['template_id'] for t in json.loads(list_templates(tokens[dev]).text)['templates']]
+    assert template_id not in [t['template_id'] for t in json.loads(list_templates(tokens[outsider]).text)['templates']]
+    assert get_template(template_id, tokens[outsider]).status_code == 403
+    assert get_template(10 ** 12, token).status_code == 404
+
+    resp = create_task(None, tokens[dev], template_id=template_id, variables={'summary': 'crash', 'version': '1.2'})
+    assert resp.status_code == 201
+    created = json.loads(resp.text)
+    assert len(created['subtask_ids']) == 2
+    task = json.loads(get_task(created['task_id']).text)
+    assert task['title'] == 'Bug: crash'
+    assert task['text'] == f'Found by {dev} in 1.2.\n\n- [ ] Reproduce on 1.2\n- [ ] Write a test\n'
+    assert task['priority'] == 'high'
+    assert [label['label_id'] for label in task['labels']] == [label_id]
+    children = json.loads(subtasks(created['task_id']).text)['tasks']
+    assert sorted(child['title'] for child in children) == ['Fix crash', 'Release']
+    assert set_checklist_item(created['task_id'], 1, True, tokens[dev]).status_code == 200
+
+    # Explicit fields win over the template, and every placeholder needs a value.
+    task_id = json.loads(create_task(None, tokens[dev], template_id=template_id, title='Custom', priority='low',
+                                     variables={'summary': 'x', 'version': '2'}).text)['task_id']
+    task = json.loads(get_task(task_id).text)
+    assert task['title'] == 'Custom' and task['priority'] == 'low'
+    assert create_task(None, tokens[dev], template_id=template_id, variables={'summary': 'x'}).status_code == 406
+    assert create_task(None, tokens[outsider], template_id=template_id,
+                       variables={'summary': 'x', 'version': '2'}).status_code == 403
+    body = json.loads(bulk_tasks([{'op': 'create', 'title': 't', 'template_id': template_id}], token).text)
+    assert [r['status'] for r in body['results']] == [406]
+
+    resp = update_template(template_id, tokens[dev], name='Bug', clear_priority=True, subtasks=[])
+    assert resp.status_code == 200
+    template = json.loads(resp.text)
+    assert template['name'] == 'Bug' and template['priority'] is None and template['subtasks'] == []
+    assert template['title'] == 'Bug: {{ summary }}'
+    assert update_template(template_id, tokens[outsider], name='x').status_code == 403
+    assert update_template(template_id, token, priority='someday').status_code == 406
+
+    assert delete_template(template_id, tokens[outsider]).status_code == 403
+    assert delete_template(template_id, token).status_code == 200
+    assert get_template(template_id, token).status_code == 404
+    assert json.loads(get_task(created['task_id']).text)['title'] == 'Bug: crash'
+
+    print('test_templates OK')
+
+
+def test_concurrent_updates():
+    username = random_str(10)
+    password = 'aaaaaA1*'
+    signup(username, password)
+    token = login(username, password).headers["Authorization"]
+    task_id = json.loads(create_task('Draft', token, title='Plan').text)["task_id"]
+
+    resp = get_task(task_id)
+    assert json.loads(resp.text)['version'] == 1
+    etag = resp.headers['ETag']
+    assert etag == '"1"'
+
+    # Of two edits based on the same version only the first gets through.
+    resp = update_task_if_match(task_id, etag, token, title='Mine')
+    assert resp.status_code == 200
+    assert resp.headers['ETag'] == '"2"'
+    resp = update_task_if_match(task_id, etag, token, title='Theirs')
+    assert resp.status_code == 412
+    assert json.loads(get_task(task_id).text)['title'] == 'Mine'
+    assert update_task(task_id, None, token, priority='high', expected_version=1).status_code == 412
+    assert update_task_if_match(task_id, 'W/"2"', token, title='Weak').status_code == 412
+    assert update_task_if_match(task_id, '*', token, title='Any').status_code == 200
+    assert update_task(task_id, None, token, title='Last').headers['ETag'] == '"4"'
+
+    # Every tracked change moves the version on, not only update_task.
+    transition_task(task_id, 'todo', token)
+    resp = get_task(task_id)
+    assert resp.headers['ETag'] == '"5"'
+    assert update_task_if_match(task_id, '"4"', token, title='Stale').status_code == 412
+    body = json.loads(bulk_tasks([{'op': 'update', 'task_id': task_id, 'title': 'Bulk', 'expected_version': 4}],
+                                 token).text)
+    assert [r['status'] for r in body['results']] == [412]
+    assert update_task_if_match(task_id, '"5"', token, title='Fresh').status_code == 200
+    assert json.loads(get_task(task_id).text)['title'] == 'Fresh'
+
+    print('test_concurrent_updates OK')
+
+
+def test_visibility():
+    password = 'aaaaaA1*'
+    author, mate, member, assignee, stranger = [random_str(10) for _ in range(5)]
+    tokens = {}
+    for username in [author, mate, member, assignee, stranger]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    author_id = user_id_from_token(tokens[author])
+    team_id = json.loads(create_team(random_str(10), tokens[author]).text)["team_id"]
+    add_team_member(team_id, user_id_from_token(tokens[mate]), tokens[author])
+    project_key = random_project_key()
+    project_id = json.loads(create_project(project_key, 'Secrets', tokens[author]).text)["project_id"]
+    add_project_member(project_id, user_id_from_token(tokens[member]), tokens[author])
+
+    public = json.loads(create_task('Open', tokens[author]).text)["task_id"]
+    team = json.loads(create_task('Ours', tokens[author], visibility='team').text)["task_id"]
+    private = json.loads(create_task('Mine', tokens[author], visibility='private',
+                                     assignee_id=user_id_from_token(tokens[assignee])).text)["task_id"]
+    in_project = json.loads(create_task('Plan', tokens[author], visibility='team', project_id=project_id).text)["task_id"]
+    assert create_task('Bad', tokens[author], visibility='secret').status_code == 406
+
+    assert json.loads(get_task(public).text)['visibility'] == 'public'
+    assert get_task(team).status_code == 404 and get_task(private).status_code == 404
+    assert get_task(team, tokens[mate]).status_code == 201
+    assert get_task(team, tokens[stranger]).status_code == 404
+    assert get_task(in_project, tokens[member]).status_code == 201
+    assert get_task(team, tokens[member]).status_code == 404
+    assert get_task(private, tokens[mate]).status_code == 404
+    assert get_task(private, tokens[assignee]).status_code == 201
+    assert get_task(private, tokens[author]).status_code == 201
+    assert get_task(team, 'not a token').status_code == 401
+
+    note = json.loads(upload_attachment(private, 'note.txt', b'secret', tokens[author]).text)
+    assert download_attachment(note['attachment_id']).status_code == 404
+    assert download_attachment(note['attachment_id'], tokens[mate]).status_code == 404
+    assert download_attachment(note['attachment_id'], tokens[assignee]).content == b'secret'
+    assert upload_attachment(private, 'mine.txt', b'x', tokens[stranger]).status_code == 404
+    # Nor can its history or comments be read through its id.
+    assert task_revisions(private).status_code == 404
+    assert task_revisions(private, tokens[author]).status_code == 200
+    assert revision_diff(private, 1, 1).status_code == 404
+    assert revision_diff(private, 1, 1, token=tokens[assignee]).status_code == 200
+    assert task_transitions(private, tokens[stranger]).status_code == 404
+    assert list_comments(private, 0, 10).status_code == 404
+    assert list_comments(private, 0, 10, token=tokens[assignee]).status_code == 200
+
+    # Boards show project members only the tasks they may see.
+    draft = json.loads(create_task('Draft', tokens[author], visibility='private', project_id=project_id).text)["task_id"]
+    def on_board(token):
+        board = json.loads(get_board(project_key, token).text)
+        return {task['task_id'] for column in board['columns'] for task in column['tasks']}
+    assert on_board(tokens[member]) == {in_project}
+    assert on_board(tokens[author]) == {in_project, draft}
+    assert move_board_task(project_key, draft, 'todo', tokens[member]).status_code == 404
+    nodes = json.loads(dependency_graph(project_id, tokens[member]).text)['nodes']
+    assert [node['task_id'] for node in nodes] == [in_project]
+
+    assert set_checklist_item(private, 0, False, tokens[stranger]).status_code == 404
+    assert transition_task(private, 'todo', tokens[stranger]).status_code == 404
+    body = json.loads(bulk_tasks([{'op': 'status', 'task_id': private, 'status': 'todo'}], tokens[stranger]).text)
+    assert [r['status'] for r in body['results']] == [404]
+    assert set_checklist_item(private, 5, False, tokens[assignee]).status_code == 403
+    assert task_dependencies(private).status_code == 404
+    assert task_dependencies(private, tokens[assignee]).status_code == 200
+    assert add_dependency(private, public, tokens[stranger]).status_code == 404
+    assert add_dependency(public, private, tokens[stranger]).status_code == 404
+    assert create_reminder(private, tokens[stranger], remind_at='2100-01-01').status_code == 404
+    assert create_reminder(private, tokens[assignee], remind_at='2100-01-01').status_code == 201
+
+    # Watching tells what happens to a task, so it takes being able to read it.
+    assert watch_task(private, tokens[stranger]).status_code == 404
+    assert list_watchers(private).status_code == 404
+    assert list_watchers(private, tokens[assignee]).status_code == 200
+    assert watch_task(team, tokens[mate]).status_code == 200
+    update_task(team, None, tokens[author], visibility='private')
+    create_comment(team, 'Only for me now', tokens[author])
+    assert json.loads(list_notifications(tokens[mate]).text)['notifications'] == []
+    update_task(team, None, tokens[author], visibility='team')
+    # Nor are those mentioned in a task they may not read.
+    update_task(private, f'Ask @{stranger} and @{assignee}', tokens[author])
+    assert json.loads(list_notifications(tokens[stranger]).text)['notifications'] == []
+    assert 'mentioned' in [n['event'] for n in json.loads(list_notifications(tokens[assignee]).text)['notifications']]
+
+    def listed(token):
+        return {task['task_id'] for task in json.loads(list_tasks(author_id, 0, 100, token).text)['tasks']}
+    assert listed(None) == {public}
+    assert listed(tokens[mate]) == {public, team, in_project}
+    assert listed(tokens[author]) == {public, team, private, in_project, draft}
+
+    child = json.loads(create_task('Hidden step', tokens[author], parent_id=public, visibility='private').text)["task_id"]
+    assert json.loads(subtasks(public).text)['tasks'] == []
+    # Only those who may edit a task add subtasks to it.
+    assert create_task('Sneak in', tokens[stranger], parent_id=private).status_code == 404
+    assert create_task('Sneak in', tokens[stranger], parent_id=public).status_code == 403
+    own = json.loads(create_task('Mine', tokens[stranger]).text)["task_id"]
+    assert update_task(own, None, tokens[stranger], parent_id=public).status_code == 403
+    assert [task['task_id'] for task in json.loads(subtasks(public, token=tokens[author]).text)['tasks']] == [child]
+
+    assert update_task(team, None, tokens[author], visibility='everyone').status_code == 406
+    before = json.loads(get_task(team, tokens[author]).text)['version']
+    assert update_task(team, None, tokens[author], visibility='public').status_code == 200
+    assert json.loads(get_task(team, tokens[author]).text)['version'] == before + 1
+    assert get_task(team, tokens[stranger]).status_code == 201
+
+    print('test_visibility OK')
+
+
+def test_task_grants():
+    password = 'aaaaaA1*'
+    author, viewer, editor, teammate, stranger = [random_str(10) for _ in range(5)]
+    tokens = {}
+    for username in [author, viewer, editor, teammate, stranger]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    ids = {username: user_id_from_token(tokens[username]) for username in tokens}
+    team_id = json.loads(create_team(random_str(10), tokens[stranger]).text)["team_id"]
+    add_team_member(team_id, ids[teammate], tokens[stranger])
+
+    task_id = json.loads(create_task('Salary review', tokens[author], visibility='private').text)["task_id"]
+    assert get_task(task_id, tokens[viewer]).status_code == 404
+
+    resp = share_task(task_id, 'view', tokens[author], user_id=ids[viewer])
+    assert resp.status_code == 201
+    assert json.loads(resp.text)['permission'] == 'view'
+    assert share_task(task_id, 'edit', tokens[author], user_id=ids[editor]).status_code == 201
+    team_grant = json.loads(share_task(task_id, 'comment', tokens[author], team_id=team_id).text)
+    assert share_task(task_id, 'own', tokens[author], user_id=ids[viewer]).status_code == 406
+    assert share_task(task_id, 'view', tokens[author], user_id=ids[viewer], team_id=team_id).status_code == 406
+    assert share_task(task_id, 'view', tokens[author], user_id=10 ** 12).status_code == 406
+    assert share_task(task_id, 'edit', tokens[viewer], user_id=ids[viewer]).status_code == 403
+    assert share_task(10 ** 12, 'view', tokens[author], user_id=ids[viewer]).status_code == 404
+
+    # Shared tasks are visible whatever their visibility.
+    assert get_task(task_id, tokens[viewer]).status_code == 201
+    assert get_task(task_id, tokens[teammate]).status_code == 201
+    assert get_task(task_id, tokens[stranger]).status_code == 404
+    listed = json.loads(list_tasks(ids[author], 0, 10, tokens[viewer]).text)['tasks']
+    assert [task['task_id'] for task in listed] == [task_id]
+
+    assert create_comment(task_id, 'Looks fine', tokens[viewer]).status_code == 403
+    assert create_comment(task_id, 'Agreed', tokens[teammate]).status_code == 201
+    assert create_comment(task_id, 'Hello?', tokens[stranger]).status_code == 404
+
+    assert update_task(task_id, None, tokens[viewer], title='Mine now').status_code == 403
+    assert update_task(task_id, None, tokens[teammate], title='Mine now').status_code == 403
+    assert update_task(task_id, None, tokens[editor], title='Salary review 2027').status_code == 200
+    assert json.loads(get_task(task_id, tokens[editor]).text)['title'] == 'Salary review 2027'
+
+    # The grant covers the rest of what changes the task as well.
+    assert restore_revision(task_id, 1, tokens[viewer]).status_code == 403
+    assert restore_revision(task_id, 1, tokens[editor]).status_code == 200
+    assert json.loads(get_task(task_id, tokens[editor]).text)['title'] == 'Salary review'
+    assert assign_task(task_id, ids[viewer], tokens[viewer]).status_code == 403
+    assert assign_task(task_id, ids[editor], tokens[editor]).status_code == 200
+    blocker = json.loads(create_task('Budget', tokens[editor]).text)["task_id"]
+    assert add_dependency(blocker, task_id, tokens[teammate]).status_code == 403
+    assert add_dependency(blocker, task_id, tokens[editor]).status_code == 200
+    label_id = json.loads(create_label('hr', '#AA0000', tokens[editor]).text)['label_id']
+    assert attach_label(task_id, label_id, tokens[editor]).status_code == 200
+
+    grants = json.loads(list_grants(task_id, tokens[editor]).text)['grants']
+    assert sorted(grant['permission'] for grant in grants) == ['comment', 'edit', 'view']
+    assert list_grants(task_id, tokens[viewer]).status_code == 403
+
+    # Sharing again changes the permission; revoking takes it away.
+    resp = share_task(task_id, 'edit', tokens[author], user_id=ids[viewer])
+    assert resp.status_code == 201
+    assert create_comment(task_id, 'Now I can', tokens[viewer]).status_code == 201
+    assert len(json.loads(list_grants(task_id, tokens[author]).text)['grants']) == 3
+    assert revoke_grant(task_id, team_grant['grant_id'], tokens[editor]).status_code == 403
+    assert revoke_grant(task_id, team_grant['grant_id'], tokens[author]).status_code == 200
+    assert revoke_grant(task_id, team_grant['grant_id'], tokens[author]).status_code == 404
+    assert get_task(task_id, tokens[teammate]).status_code == 404
+
+    assert delete_task(task_id, tokens[stranger]).status_code == 403
+    assert delete_task(task_id, tokens[editor]).status_code == 200
+
+    print('test_task_grants OK')
+
+
+def test_time_tracking():
+    password = 'aaaaaA1*'
+    owner, member, outsider = random_str(10), random_str(10), random_str(10)
+    tokens = {}
+    for username in [owner, member, outsider]:
+        signup(username, password)
+        tokens[username] = login(username, password).headers["Authorization"]
+    member_id = user_id_from_token(tokens[member])
+    project_id = json.loads(create_project(random_project_key(), 'Billing', tokens[owner]).text)["project_id"]
+    add_project_member(project_id, member_id, tokens[owner])
+    first = json.loads(create_task('Invoice run', tokens[owner], project_id=project_id).text)["task_id"]
+    second = json.loads(create_task('Audit', tokens[owner], project_id=project_id).text)["task_id"]
+    hidden = json.loads(create_task('Private notes', tokens[owner], visibility='private').text)["task_id"]
+
+    # One running timer per user.
+    resp = start_timer(first, tokens[member])
+    assert resp.status_code == 201
+    timer = json.loads(resp.text)
+    assert timer['minutes'] is None and timer['project_id'] == project_id
+    assert start_timer(second, tokens[member]).status_code == 409
+    assert start_timer(hidden, tokens[member]).status_code == 404
+    assert json.loads(get_timer(tokens[member]).text)['work_log_id'] == timer['work_log_id']
+    assert update_work_log(timer['work_log_id'], tokens[member], minutes=5).status_code == 409
+    assert update_work_log(timer['work_log_id'], tokens[member], note='Batch 1').status_code == 200
+    assert update_work_log(timer['work_log_id'], tokens[member], started_at='2999-01-01').status_code == 406
+    resp = stop_timer(tokens[member])
+    assert resp.status_code == 200
+    stopped = json.loads(resp.text)
+    assert stopped['minutes'] == 0 and stopped['note'] == 'Batch 1'
+    assert stop_timer(tokens[member]).status_code == 404
+    assert get_timer(tokens[member]).status_code == 404
+    assert start_timer(second, tokens[member]).status_code == 201
+    assert stop_timer(tokens[member]).status_code == 200
+
+    # Manual entries.
+    monday = json.loads(log_work(first, 90, tokens[member], started_at='2026-03-02T09:00:00Z',
+                                 note='Reconciliation').text)
+    assert monday['minutes'] == 90
+    log_work(first, 30, tokens[member], started_at='2026-03-03')
+    log_work(second, 60, tokens[owner], started_at='2026-03-09T23:30:00Z')
+    assert log_work(first, 0, tokens[member]).status_code == 406
+    assert log_work(first, 24 * 60 + 1, tokens[member]).status_code == 406
+    assert log_work(first, 10, tokens[member], started_at='yesterday').status_code == 406
+    assert log_work(first, 10, tokens[member], started_at='2999-01-01').status_code == 406
+    assert log_work(first, 10, tokens[member], note='x' * 1001).status_code == 406
+    assert log_work(hidden, 10, tokens[member]).status_code == 404
+
+    logs = json.loads(list_work_logs(first, tokens[owner]).text)
+    assert logs['total_minutes'] == 120 and len(logs['work_logs']) == 3
+
+    assert update_work_log(monday['work_log_id'], tokens[owner], minutes=10).status_code == 403
+    resp = update_work_log(monday['work_log_id'], tokens[member], minutes=120)
+    assert resp.status_code == 200
+    assert json.loads(resp.text)['note'] == 'Reconciliation'
+    assert update_work_log(10 ** 12, tokens[member], minutes=10).status_code == 404
+
+    # Totals per task, user and project.
+    assert json.loads(time_totals(tokens[member], task_id=first).text)['total_minutes'] == 150
+    assert json.loads(time_totals(tokens[member]).text)['total_minutes'] == 150
+    assert json.loads(time_totals(tokens[owner], project_id=project_id).text)['total_minutes'] == 210
+    assert json.loads(time_totals(tokens[owner], project_id=project_id,
+                                  user_id=member_id).text)['total_minutes'] == 150
+    assert time_totals(tokens[owner], user_id=member_id).status_code == 403
+    assert time_totals(tokens[outsider], project_id=project_id).status_code == 403
+
+    report = json.loads(time_report('2026-03-01', '2026-03-31', tokens[owner], project_id=project_id).text)
+    assert report['periods'] == [{'start': '2026-03-02', 'minutes': 120}, {'start': '2026-03-03', 'minutes': 30},
+                                 {'start': '2026-03-09', 'minutes': 60}]
+    assert report['total_minutes'] == 210
+    report = json.loads(time_report('2026-03-01', '2026-03-31', tokens[owner], project_id=project_id,
+                                    group_by='week', timezone='Europe/Berlin').text)
+    assert report['periods'] == [{'start': '2026-03-02', 'minutes': 150}, {'start': '2026-03-09', 'minutes': 60}]
+    report = json.loads(time_report('2026-03-03', '2026-03-09', tokens[owner], project_id=project_id,
+                                    timezone='Europe/Berlin').text)
+    assert report['periods'] == [{'start': '2026-03-03', 'minutes': 30}]
+    assert time_report('2026-03-31', '2026-03-01', tokens[owner]).status_code == 406
+    assert time_report('2026-01-01', '2027-06-01', tokens[owner]).status_code == 406
+    assert time_report('2026-03-01', '2026-03-31', tokens[owner], timezone='Mars/Olympus').status_code == 406
+
+    assert delete_work_log(monday['work_log_id'], tokens[owner]).status_code == 403
+    assert delete_work_log(monday['work_log_id'], tokens[member]).status_code == 200
+    assert json.loads(time_totals(tokens[member]).text)['total_minutes'] == 30
+
+    print('test_time_tracking OK')
+
+
 def test_like_view():
     username = random_str(10)
     password = 'aaaaaA1*'
@@ -182,6 +1641,31 @@ def test_aggregate2():
 
 test_signup_login_update()
 test_tasks()
+test_rich_task()
+test_workflow()
+test_assignment()
+test_comments()
+test_labels()
+test_projects()
+test_board()
+test_dependencies()
+test_search()
+test_task_listing()
+test_revisions()
+test_trash()
+test_attachments()
+test_bulk()
+test_import_export()
+test_recurring()
+test_reminders()
+test_notifications()
+test_mentions()
+test_markdown()
+test_templates()
+test_concurrent_updates()
+test_visibility()
+test_task_grants()
+test_time_tracking()
 test_like_view()
 test_stat()
 test_aggregate()
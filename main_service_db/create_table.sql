@@ -6,5 +6,176 @@ CREATE TABLE IF NOT EXISTS users (
     second_name varchar(30),
     birthday date,
     email varchar(255),
-    phone_number varchar(20)
+    phone_number varchar(20),
+    is_moderator boolean NOT NULL DEFAULT false
 );
+
+-- Databases created before a column was added get it here; on new ones this does nothing.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_moderator boolean NOT NULL DEFAULT false;
+
+CREATE TABLE IF NOT EXISTS teams (
+    id bigserial PRIMARY KEY,
+    name varchar(100) UNIQUE NOT NULL,
+    owner_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS team_members (
+    team_id bigint NOT NULL REFERENCES teams (id) ON DELETE CASCADE,
+    user_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    PRIMARY KEY (team_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS projects (
+    id bigserial PRIMARY KEY,
+    key varchar(10) UNIQUE NOT NULL,
+    name varchar(100) NOT NULL,
+    owner_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    archived boolean NOT NULL DEFAULT false
+);
+
+CREATE TABLE IF NOT EXISTS project_members (
+    project_id bigint NOT NULL REFERENCES projects (id) ON DELETE CASCADE,
+    user_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    PRIMARY KEY (project_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS board_wip_limits (
+    project_id bigint NOT NULL REFERENCES projects (id) ON DELETE CASCADE,
+    status varchar(32) NOT NULL,
+    wip_limit bigint NOT NULL,
+    PRIMARY KEY (project_id, status)
+);
+
+-- Background jobs run by the scheduler of main_service. A job runs at least once: it stays
+-- pending until it succeeds, and is retried with backoff until it runs out of attempts.
+CREATE TABLE IF NOT EXISTS jobs (
+    id bigserial PRIMARY KEY,
+    kind varchar(32) NOT NULL,
+    payload jsonb NOT NULL,
+    run_at timestamptz NOT NULL DEFAULT now(),
+    -- pending, done or failed.
+    status varchar(16) NOT NULL DEFAULT 'pending',
+    attempts integer NOT NULL DEFAULT 0,
+    last_error text,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS jobs_due_idx ON jobs (run_at) WHERE status = 'pending';
+
+-- A reminder fires at a fixed time or a number of minutes before the due date of the task.
+CREATE TABLE IF NOT EXISTS reminders (
+    id bigserial PRIMARY KEY,
+    task_id bigint NOT NULL,
+    user_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    remind_at timestamptz,
+    minutes_before_due bigint,
+    job_id bigint REFERENCES jobs (id) ON DELETE SET NULL,
+    sent_at timestamptz,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    CHECK ((remind_at IS NULL) <> (minutes_before_due IS NULL))
+);
+
+CREATE INDEX IF NOT EXISTS reminders_task_idx ON reminders (task_id, user_id);
+
+-- Authors and assignees watch their tasks without a row here; a row with `watching` false
+-- records that they stopped.
+CREATE TABLE IF NOT EXISTS task_watchers (
+    task_id bigint NOT NULL,
+    user_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    watching boolean NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (task_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS notifications (
+    id bigserial PRIMARY KEY,
+    user_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    task_id bigint NOT NULL,
+    actor_id bigint NOT NULL,
+    event varchar(32) NOT NULL,
+    message text NOT NULL,
+    read_at timestamptz,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS notifications_user_idx ON notifications (user_id, id);
+CREATE INDEX IF NOT EXISTS notifications_unread_idx ON notifications (user_id) WHERE read_at IS NULL;
+
+-- Events without a row are kept in the notification centre and not delivered elsewhere.
+CREATE TABLE IF NOT EXISTS notification_preferences (
+    user_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    event varchar(32) NOT NULL,
+    in_app boolean NOT NULL,
+    external boolean NOT NULL,
+    PRIMARY KEY (user_id, event)
+);
+
+-- Users mentioned with `@username` in the text of a task (source `task`) or in a comment.
+CREATE TABLE IF NOT EXISTS mentions (
+    source varchar(16) NOT NULL,
+    source_id bigint NOT NULL,
+    task_id bigint NOT NULL,
+    user_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (source, source_id, user_id)
+);
+
+-- Skeletons for new tasks, owned by a user or shared by a team. Texts may contain
+-- `{{variable}}` placeholders that are filled in when a task is created from the template.
+CREATE TABLE IF NOT EXISTS task_templates (
+    id bigserial PRIMARY KEY,
+    owner_id bigint REFERENCES users (id) ON DELETE CASCADE,
+    team_id bigint REFERENCES teams (id) ON DELETE CASCADE,
+    name varchar(100) NOT NULL,
+    title text NOT NULL,
+    description text NOT NULL DEFAULT '',
+    priority varchar(16),
+    label_ids bigint[] NOT NULL DEFAULT '{}',
+    checklist text[] NOT NULL DEFAULT '{}',
+    -- An array of {"title", "description"} objects.
+    subtasks jsonb NOT NULL DEFAULT '[]',
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    CHECK ((owner_id IS NULL) <> (team_id IS NULL))
+);
+
+CREATE INDEX IF NOT EXISTS task_templates_owner_idx ON task_templates (owner_id);
+CREATE INDEX IF NOT EXISTS task_templates_team_idx ON task_templates (team_id);
+
+-- Access to a single task given by its author to a user or to every member of a team, on top
+-- of what its visibility allows. `view` < `comment` < `edit`, each including the ones before.
+CREATE TABLE IF NOT EXISTS task_grants (
+    id bigserial PRIMARY KEY,
+    task_id bigint NOT NULL,
+    user_id bigint REFERENCES users (id) ON DELETE CASCADE,
+    team_id bigint REFERENCES teams (id) ON DELETE CASCADE,
+    permission varchar(16) NOT NULL,
+    granted_by bigint NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    CHECK ((user_id IS NULL) <> (team_id IS NULL))
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS task_grants_user_idx ON task_grants (task_id, user_id) WHERE user_id IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS task_grants_team_idx ON task_grants (task_id, team_id) WHERE team_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS task_grants_grantee_user_idx ON task_grants (user_id);
+CREATE INDEX IF NOT EXISTS task_grants_grantee_team_idx ON task_grants (team_id);
+
+-- Time spent by users on tasks. A running timer is an entry without `minutes` yet, and a user
+-- has at most one. `project_id` is the project the task was in when the time was logged.
+CREATE TABLE IF NOT EXISTS work_logs (
+    id bigserial PRIMARY KEY,
+    user_id bigint NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    task_id bigint NOT NULL,
+    project_id bigint,
+    started_at timestamptz NOT NULL DEFAULT now(),
+    minutes integer,
+    note text NOT NULL DEFAULT '',
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS work_logs_running_idx ON work_logs (user_id) WHERE minutes IS NULL;
+CREATE INDEX IF NOT EXISTS work_logs_task_idx ON work_logs (task_id);
+CREATE INDEX IF NOT EXISTS work_logs_user_idx ON work_logs (user_id, started_at);
+CREATE INDEX IF NOT EXISTS work_logs_project_idx ON work_logs (project_id, started_at);
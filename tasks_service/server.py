@@ -2,22 +2,255 @@ import os, sys
 from concurrent import futures
 import grpc
 import psycopg2
+import psycopg2.errors
 from google.protobuf import empty_pb2
 from kafka import KafkaProducer
 import json
+import base64
+import threading
 
 import common_pb2
 import common_pb2_grpc
 
+TASK_COLUMNS = ("task_id, author_id, text, title, status, priority, due_date, assignee_id, created_at, updated_at, "
+                "project_id, project_number, board_rank, parent_id, estimate_minutes, deleted_at, external_id, "
+                "series_id, version, visibility")
+
+# Serializes changes of the subtask tree and the dependency graph, so that two concurrent
+# edits can't close a cycle that neither of them sees on its own.
+TASK_GRAPH_LOCK = 33
+
+
+def task_fields(row):
+    fields = {
+        'task_id': row[0],
+        'author_id': row[1],
+        'text': row[2],
+        'title': row[3],
+        'status': row[4],
+        'priority': row[5],
+        'created_at': row[8].isoformat(),
+        'updated_at': row[9].isoformat(),
+    }
+    if row[6] is not None:
+        fields['due_date'] = row[6].isoformat()
+    if row[7] is not None:
+        fields['assignee_id'] = row[7]
+    if row[10] is not None:
+        fields['project_id'] = row[10]
+        fields['project_number'] = row[11]
+    if row[12] is not None:
+        fields['rank'] = row[12]
+    if row[13] is not None:
+        fields['parent_id'] = row[13]
+    if row[14] is not None:
+        fields['estimate_minutes'] = row[14]
+    if row[15] is not None:
+        fields['deleted_at'] = row[15].isoformat()
+    if row[16] is not None:
+        fields['external_id'] = row[16]
+    if row[17] is not None:
+        fields['series_id'] = row[17]
+    fields['version'] = row[18]
+    fields['visibility'] = row[19]
+    return fields
+
+
+def visibility_condition(viewer, prefix=''):
+    """The SQL condition, with its parameters, for the tasks the viewer may see."""
+    condition = (f"({prefix}visibility = 'public' OR {prefix}author_id = %s OR {prefix}assignee_id = %s "
+                 f"OR ({prefix}visibility = 'team' AND ({prefix}author_id = ANY(%s) OR {prefix}project_id = ANY(%s))) "
+                 f"OR {prefix}task_id = ANY(%s))")
+    return condition, [viewer.user_id, viewer.user_id, list(viewer.teammate_ids), list(viewer.project_ids),
+                       list(viewer.shared_task_ids)]
+
+
+def series_visible(series, viewer):
+    """Whether the viewer may see the series; the rule of visibility_condition, without grants."""
+    author_id, assignee_id, project_id, visibility = series[1], series[8], series[9], series[14]
+    return (visibility == 'public' or viewer.user_id in (author_id, assignee_id)
+            or (visibility == 'team' and (author_id in viewer.teammate_ids
+                                          or (project_id is not None and project_id in viewer.project_ids))))
+
+
+LIST_FILTERS = (('assignee_id', "assignee_id = %s"),
+                ('created_after', "created_at >= %s"), ('created_before', "created_at < %s"),
+                ('updated_after', "updated_at >= %s"), ('updated_before', "updated_at < %s"),
+                ('due_after', "due_date >= %s"), ('due_before', "due_date < %s"))
+
+PRIORITY_ORDER = "CASE priority WHEN 'low' THEN 0 WHEN 'medium' THEN 1 WHEN 'high' THEN 2 ELSE 3 END"
+
+# Ascending and descending sort expressions with their type. They never return NULL, so that
+# they can be compared with the cursor; tasks without a due date go last in either direction.
+SORT_KEYS = {
+    common_pb2.TASK_SORT_CREATED: ("created_at", "created_at", "timestamptz"),
+    common_pb2.TASK_SORT_UPDATED: ("updated_at", "updated_at", "timestamptz"),
+    common_pb2.TASK_SORT_DUE: ("coalesce(due_date, 'infinity')", "coalesce(due_date, '-infinity')", "timestamptz"),
+    common_pb2.TASK_SORT_PRIORITY: (PRIORITY_ORDER, PRIORITY_ORDER, "integer"),
+    common_pb2.TASK_SORT_LIKES: ("like_count", "like_count", "bigint"),
+}
+
+
+def encode_cursor(position):
+    return base64.urlsafe_b64encode(json.dumps(position).encode()).decode()
+
+
+def decode_cursor(cursor):
+    try:
+        position = json.loads(base64.urlsafe_b64decode(cursor.encode()))
+        if (isinstance(position, dict) and isinstance(position.get('id'), int)
+                and -2 ** 63 <= position['id'] < 2 ** 63 and isinstance(position.get('key'), str)):
+            return position
+    except ValueError:
+        pass
+    return None
+
+
+SNIPPET_OPTIONS = "StartSel=<mark>, StopSel=</mark>, MinWords=10, MaxWords=30, MaxFragments=2"
+
+
+def escaped_html(column):
+    # Snippets are HTML with <mark> highlights, so the text itself must not add any markup.
+    return f"replace(replace(replace({column}, '&', '&amp;'), '<', '&lt;'), '>', '&gt;')"
+
+
+# Task fields tracked in revisions, in the order of the snapshot columns.
+REVISION_FIELDS = ('text', 'title', 'status', 'priority', 'due_date', 'assignee_id', 'parent_id', 'estimate_minutes',
+                   'visibility')
+# Values of fields tracked later than the oldest snapshots, which were taken before they existed.
+REVISION_DEFAULTS = {'visibility': 'public'}
+REVISION_COLUMNS = "r.task_id, r.revision_number, r.actor_id, r.created_at, r.changed_fields, r.snapshot"
+
+
+def revision_message(row):
+    snapshot = {field: value for field, value in {**REVISION_DEFAULTS, **row[5]}.items() if value is not None}
+    return common_pb2.Revision(task_id=row[0], revision_number=row[1], actor_id=row[2],
+                               created_at=row[3].isoformat(), changed_fields=row[4], **snapshot)
+
+
+LABEL_COLUMNS = "label_id, owner_kind, owner_id, name, color, description"
+
+
+def label_message(row):
+    return common_pb2.Label(label_id=row[0], owner_kind=row[1], owner_id=row[2],
+                            name=row[3], color=row[4], description=row[5])
+
+
+ATTACHMENT_COLUMNS = "attachment_id, task_id, uploader_id, filename, content_type, size_bytes, sha256, created_at"
+
+
+def attachment_message(row):
+    return common_pb2.Attachment(attachment_id=row[0], task_id=row[1], uploader_id=row[2], filename=row[3],
+                                 content_type=row[4], size=row[5], sha256=row[6], created_at=row[7].isoformat())
+
+
+SERIES_COLUMNS = ("series_id, author_id, rrule, timezone, dtstart, title, text, priority, assignee_id, project_id, "
+                  "estimate_minutes, next_at, created_at, updated_at, visibility")
+
+# Template fields of a series, which are also the fields of its occurrences.
+SERIES_TEMPLATE_FIELDS = ('title', 'text', 'priority', 'assignee_id', 'estimate_minutes')
+
+
+def series_message(row, occurrence_ids):
+    fields = {
+        'series_id': row[0],
+        'author_id': row[1],
+        'rrule': row[2],
+        'timezone': row[3],
+        'dtstart': row[4].isoformat(),
+        'title': row[5],
+        'text': row[6],
+        'priority': row[7],
+        'created_at': row[12].isoformat(),
+        'updated_at': row[13].isoformat(),
+    }
+    for name, value in (('assignee_id', row[8]), ('project_id', row[9]), ('estimate_minutes', row[10])):
+        if value is not None:
+            fields[name] = value
+    if row[11] is not None:
+        fields['next_at'] = row[11].isoformat()
+    return common_pb2.TaskSeries(**fields, occurrence_ids=occurrence_ids)
+
+
+COMMENT_COLUMNS = ("c.comment_id, c.task_id, c.parent_id, c.author_id, c.content, c.created_at, c.edited_at, "
+                   "c.deleted, (SELECT count(*) FROM comments r WHERE r.parent_id = c.comment_id)")
+
+
+def comment_fields(row):
+    fields = {
+        'comment_id': row[0],
+        'task_id': row[1],
+        'author_id': row[3],
+        'content': row[4],
+        'created_at': row[5].isoformat(),
+        'deleted': row[7],
+        'reply_count': row[8],
+    }
+    if row[2] is not None:
+        fields['parent_id'] = row[2]
+    if row[6] is not None:
+        fields['edited_at'] = row[6].isoformat()
+    return fields
+
+
+class BulkItemError(Exception):
+    def __init__(self, code, details):
+        super().__init__(details)
+        self.code = code
+        self.details = details
+
+
+class BulkItemContext:
+    """Stands in for the gRPC context while one operation of a bulk request runs, so that
+    aborting fails only that operation. It carries the transaction of the bulk request, which
+    the handlers commit and roll back through."""
+
+    def __init__(self, transaction):
+        self.transaction = transaction
+
+    def abort(self, code, details):
+        raise BulkItemError(code, details)
+
+
+class SavepointConnection:
+    """Stands in for the connection during a bulk request: the handlers' commits are deferred
+    to the end of the request and their rollbacks only undo the current operation."""
+
+    def __init__(self, cur):
+        self.cur = cur
+
+    def commit(self):
+        pass
+
+    def rollback(self):
+        self.cur.execute("ROLLBACK TO SAVEPOINT bulk_item;")
+
+
+class RollbackInterceptor(grpc.ServerInterceptor):
+    """Rolls back whatever a request leaves uncommitted, after an error in particular, so that
+    the next request on the connection of its thread starts from a clean transaction."""
+
+    def __init__(self, service):
+        self.service = service
+
+    def intercept_service(self, continuation, handler_call_details):
+        handler = continuation(handler_call_details)
+        if handler is None or handler.unary_unary is None:
+            return handler
+
+        def unary_unary(request, context):
+            try:
+                return handler.unary_unary(request, context)
+            finally:
+                self.service.conn.rollback()
+
+        return handler._replace(unary_unary=unary_unary)
+
+
 class TaskService(common_pb2_grpc.TaskServiceServicer):
     def __init__(self):
         print('__init__ called', file=sys.stderr)
-        self.conn = psycopg2.connect(host=os.getenv("DATABASE_HOST"),
-                                     port=os.getenv("DATABASE_PORT"),
-                                     dbname=os.getenv("DATABASE_NAME"),
-                                     user=os.getenv("DATABASE_USER"),
-                                     password=os.getenv("DATABASE_PASSWORD"))
-        self.cur = self.conn.cursor()
+        self.local = threading.local()
 
         self.producer = KafkaProducer(
             bootstrap_servers=['kafka:29092'],
@@ -26,9 +259,34 @@ class TaskService(common_pb2_grpc.TaskServiceServicer):
             request_timeout_ms=3000
         )
 
+    def connection(self):
+        """The connection of the current thread. gRPC runs a request on a single thread of its pool,
+        so each request has a transaction of its own, and row locks hold between requests."""
+        if not hasattr(self.local, 'conn'):
+            self.local.conn = psycopg2.connect(host=os.getenv("DATABASE_HOST"),
+                                               port=os.getenv("DATABASE_PORT"),
+                                               dbname=os.getenv("DATABASE_NAME"),
+                                               user=os.getenv("DATABASE_USER"),
+                                               password=os.getenv("DATABASE_PASSWORD"))
+            self.local.cur = self.local.conn.cursor()
+        return self.local
+
+    def transaction(self, context):
+        """What a handler commits or rolls back: the connection of the request, or the savepoints
+        of the bulk request it runs in."""
+        return getattr(context, 'transaction', None) or self.conn
+
+    @property
+    def conn(self):
+        return self.connection().conn
+
+    @property
+    def cur(self):
+        return self.connection().cur
+
     def get_author_id_of_task(self, task_id):
         print('get_author_id_of_task called', file=sys.stderr)
-        self.cur.execute("SELECT author_id FROM tasks WHERE task_id = %s;", (task_id,))
+        self.cur.execute("SELECT author_id FROM tasks WHERE task_id = %s AND deleted_at IS NULL;", (task_id,))
         print('execute called', file=sys.stderr)
         row = self.cur.fetchone()
         print(f'type(row): {type(row)}', file=sys.stderr)
@@ -36,66 +294,943 @@ class TaskService(common_pb2_grpc.TaskServiceServicer):
             return None
         return row[0]
 
-    def CreateTask(self, request, context):
-        if not request.author_id or not request.text:
-            raise ValueError("author_id or text is missing or empty")
-        self.cur.execute("INSERT INTO tasks (author_id, text) VALUES (%s, %s) RETURNING task_id;",
-                         (request.author_id, request.text))
+    def labels_of_tasks(self, task_ids):
+        labels = {task_id: [] for task_id in task_ids}
+        if task_ids:
+            self.cur.execute(f"SELECT tl.task_id, {', '.join('l.' + c for c in LABEL_COLUMNS.split(', '))} "
+                             "FROM task_labels tl JOIN labels l ON l.label_id = tl.label_id "
+                             "WHERE tl.task_id = ANY(%s) ORDER BY l.name;", (list(task_ids),))
+            for row in self.cur.fetchall():
+                labels[row[0]].append(label_message(row[1:]))
+        return labels
+
+    def attachments_of_tasks(self, task_ids):
+        attachments = {task_id: [] for task_id in task_ids}
+        if task_ids:
+            self.cur.execute(f"SELECT {ATTACHMENT_COLUMNS} FROM attachments WHERE task_id = ANY(%s) "
+                             "ORDER BY attachment_id;", (list(task_ids),))
+            for row in self.cur.fetchall():
+                attachments[row[1]].append(attachment_message(row))
+        return attachments
+
+    def relations_of_tasks(self, task_ids):
+        relations = {task_id: {'subtask_statuses': [], 'blocker_statuses': []} for task_id in task_ids}
+        if task_ids:
+            self.cur.execute("SELECT parent_id, status, count(*) FROM tasks WHERE parent_id = ANY(%s) "
+                             "AND deleted_at IS NULL GROUP BY parent_id, status ORDER BY status;", (list(task_ids),))
+            for row in self.cur.fetchall():
+                relations[row[0]]['subtask_statuses'].append(common_pb2.StatusCount(status=row[1], count=row[2]))
+            self.cur.execute("SELECT d.blocked_id, t.status, count(*) FROM task_dependencies d "
+                             "JOIN tasks t ON t.task_id = d.blocker_id WHERE d.blocked_id = ANY(%s) "
+                             "AND t.deleted_at IS NULL GROUP BY d.blocked_id, t.status ORDER BY t.status;", (list(task_ids),))
+            for row in self.cur.fetchall():
+                relations[row[0]]['blocker_statuses'].append(common_pb2.StatusCount(status=row[1], count=row[2]))
+        return relations
+
+    def task_messages(self, message, rows):
+        task_ids = [row[0] for row in rows]
+        labels = self.labels_of_tasks(task_ids)
+        attachments = self.attachments_of_tasks(task_ids)
+        relations = self.relations_of_tasks(task_ids)
+        return [message(**task_fields(row), labels=labels[row[0]], attachments=attachments[row[0]], **relations[row[0]])
+                for row in rows]
+
+    def check_parent(self, task_id, parent_id, project_id, context):
+        self.cur.execute("SELECT project_id FROM tasks WHERE task_id = %s AND deleted_at IS NULL;", (parent_id,))
+        parent = self.cur.fetchone()
+        if not parent:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Parent task doesn't exist")
+        if parent[0] != project_id:
+            context.abort(grpc.StatusCode.INVALID_ARGUMENT, "A subtask must be in the same project as its parent")
+        if task_id is None:
+            return
+        # Walking up from the new parent must not reach the task itself.
+        self.cur.execute("WITH RECURSIVE ancestors(task_id, parent_id) AS ("
+                         "SELECT task_id, parent_id FROM tasks WHERE task_id = %s "
+                         "UNION SELECT t.task_id, t.parent_id FROM tasks t JOIN ancestors a ON t.task_id = a.parent_id) "
+                         "SELECT 1 FROM ancestors WHERE task_id = %s;", (parent_id, task_id))
+        if self.cur.fetchone():
+            self.transaction(context).rollback()
+            context.abort(grpc.StatusCode.FAILED_PRECONDITION, "A task can't be a subtask of itself or of its subtask")
+
+    def record_revision(self, task_id, actor_id):
+        # Locking the task keeps revision numbers of concurrent edits in order.
+        self.cur.execute(f"SELECT {', '.join(REVISION_FIELDS)} FROM tasks WHERE task_id = %s FOR UPDATE;",
+                         (task_id,))
+        row = self.cur.fetchone()
+        snapshot = dict(zip(REVISION_FIELDS, row))
+        if snapshot['due_date'] is not None:
+            snapshot['due_date'] = snapshot['due_date'].isoformat()
+        self.cur.execute("SELECT revision_number, snapshot FROM task_revisions WHERE task_id = %s "
+                         "ORDER BY revision_number DESC LIMIT 1;", (task_id,))
+        last = self.cur.fetchone()
+        revision_number, previous = (last[0] + 1, {**REVISION_DEFAULTS, **last[1]}) if last else (1, {})
+        changed_fields = [field for field in REVISION_FIELDS
+                          if field not in previous or previous[field] != snapshot[field]]
+        if not changed_fields:
+            return None
+        self.cur.execute("INSERT INTO task_revisions (task_id, revision_number, actor_id, changed_fields, snapshot) "
+                         "VALUES (%s, %s, %s, %s, %s) RETURNING revision_number;",
+                         (task_id, revision_number, actor_id, changed_fields, json.dumps(snapshot)))
+        self.cur.execute("UPDATE tasks SET version = %s WHERE task_id = %s;", (revision_number, task_id))
+        return revision_number
+
+    def insert_task(self, request, context, series_id=None):
+        due_date = request.due_date if request.HasField('due_date') else None
+        assignee_id = request.assignee_id if request.HasField('assignee_id') else None
+        project_id = request.project_id if request.HasField('project_id') else None
+        parent_id = request.parent_id if request.HasField('parent_id') else None
+        estimate_minutes = request.estimate_minutes if request.HasField('estimate_minutes') else None
+        external_id = request.external_id if request.HasField('external_id') else None
+        if parent_id is not None:
+            self.check_parent(None, parent_id, project_id, context)
+        project_number = None
+        if project_id is not None:
+            self.cur.execute("INSERT INTO project_counters (project_id, last_number) VALUES (%s, 1) "
+                             "ON CONFLICT (project_id) DO UPDATE SET last_number = project_counters.last_number + 1 "
+                             "RETURNING last_number;", (project_id,))
+            project_number = self.cur.fetchone()[0]
+        try:
+            self.cur.execute("INSERT INTO tasks (author_id, text, title, status, priority, due_date, assignee_id, "
+                             "project_id, project_number, parent_id, estimate_minutes, external_id, series_id, "
+                             "visibility) VALUES (%s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s) "
+                             "RETURNING task_id;",
+                             (request.author_id, request.text, request.title, request.status or 'backlog',
+                              request.priority or 'medium', due_date, assignee_id, project_id, project_number,
+                              parent_id, estimate_minutes, external_id, series_id, request.visibility or 'public'))
+        except psycopg2.errors.UniqueViolation:
+            self.transaction(context).rollback()
+            context.abort(grpc.StatusCode.ALREADY_EXISTS, "A task with this external id already exists")
         task_id = self.cur.fetchone()[0]
-        self.conn.commit()
-        return common_pb2.CreateTaskResponse(task_id=task_id)
+        self.record_revision(task_id, request.author_id)
+        return task_id
+
+    def CreateTask(self, request, context):
+        if not request.author_id or not (request.title or request.text):
+            raise ValueError("author_id or both title and text are missing or empty")
+        task_id = self.insert_task(request, context)
+        for label_id in request.label_ids:
+            self.cur.execute("INSERT INTO task_labels (task_id, label_id) VALUES (%s, %s) ON CONFLICT DO NOTHING;",
+                             (task_id, label_id))
+        subtask_ids = []
+        for template in request.subtasks:
+            subtask = common_pb2.CreateTaskRequest()
+            subtask.CopyFrom(template)
+            subtask.author_id = request.author_id
+            subtask.parent_id = task_id
+            if request.HasField('project_id'):
+                subtask.project_id = request.project_id
+            subtask_ids.append(self.insert_task(subtask, context))
+        self.transaction(context).commit()
+        return common_pb2.CreateTaskResponse(task_id=task_id, subtask_ids=subtask_ids)
 
     def UpdateTask(self, request, context):
-        if not request.user_id or not request.task_id or not request.new_text:
-            raise ValueError("user_id, task_id or new_text is missing or empty")
-        self.cur.execute(
-            "SELECT author_id FROM tasks WHERE task_id = %s;", (request.task_id,))
+        if not request.user_id or not request.task_id:
+            raise ValueError("user_id or task_id is missing or empty")
+        # The lock keeps the version from changing between the check and the update.
+        self.cur.execute("SELECT author_id, project_id, version FROM tasks WHERE task_id = %s AND deleted_at IS NULL "
+                         "FOR UPDATE;", (request.task_id,))
         task = self.cur.fetchone()
-        if not task or task[0] != request.user_id:
+        if not task or (task[0] != request.user_id and not request.granted):
+            self.transaction(context).rollback()
             context.abort(grpc.StatusCode.PERMISSION_DENIED,
                           "Permission Denied")
+        if request.HasField('expected_version') and request.expected_version != task[2]:
+            self.transaction(context).rollback()
+            context.abort(grpc.StatusCode.ABORTED,
+                          f"The task has been changed: it is at version {task[2]}, not {request.expected_version}")
+
+        if request.HasField('parent_id'):
+            self.cur.execute("SELECT pg_advisory_xact_lock(%s);", (TASK_GRAPH_LOCK,))
+            self.check_parent(request.task_id, request.parent_id, task[1], context)
+        updates, params = [], []
+        for field, column in (('new_text', 'text'), ('title', 'title'), ('status', 'status'),
+                              ('priority', 'priority'), ('due_date', 'due_date'), ('assignee_id', 'assignee_id'),
+                              ('parent_id', 'parent_id'), ('estimate_minutes', 'estimate_minutes'),
+                              ('visibility', 'visibility')):
+            if request.HasField(field):
+                updates.append(f"{column} = %s")
+                params.append(getattr(request, field))
+        if request.clear_due_date:
+            updates.append("due_date = NULL")
+        if request.clear_assignee:
+            updates.append("assignee_id = NULL")
+        if request.clear_parent:
+            updates.append("parent_id = NULL")
+        if request.clear_estimate:
+            updates.append("estimate_minutes = NULL")
+        if not updates:
+            self.transaction(context).rollback()
+            context.abort(grpc.StatusCode.INVALID_ARGUMENT, "Nothing to update")
+        updates.append("updated_at = now()")
 
-        self.cur.execute("UPDATE tasks SET text = %s WHERE task_id = %s;",
-                         (request.new_text, request.task_id))
+        self.cur.execute(f"UPDATE tasks SET {', '.join(updates)} WHERE task_id = %s RETURNING version;",
+                         (*params, request.task_id))
+        version = self.cur.fetchone()[0]
+        version = self.record_revision(request.task_id, request.user_id) or version
+        self.transaction(context).commit()
+        return common_pb2.UpdateTaskResponse(version=version)
+
+    def BulkTasks(self, request, context):
+        handlers = {'create': self.CreateTask, 'update': self.UpdateTask, 'delete': self.DeleteTask,
+                    'transition': self.TransitionTask, 'assign': self.AssignTask}
+        item_context = BulkItemContext(SavepointConnection(self.cur))
+        results, failed = [], False
+        try:
+            for operation in request.operations:
+                kind = operation.WhichOneof('op')
+                self.cur.execute("SAVEPOINT bulk_item;")
+                try:
+                    response = handlers[kind](getattr(operation, kind), item_context)
+                    self.cur.execute("RELEASE SAVEPOINT bulk_item;")
+                    results.append(common_pb2.BulkResult(code=grpc.StatusCode.OK.value[0],
+                                                         task_id=response.task_id if kind == 'create' else 0))
+                    continue
+                except BulkItemError as e:
+                    result = common_pb2.BulkResult(code=e.code.value[0], message=e.details)
+                except ValueError as e:
+                    result = common_pb2.BulkResult(code=grpc.StatusCode.INVALID_ARGUMENT.value[0], message=str(e))
+                except psycopg2.Error as e:
+                    result = common_pb2.BulkResult(code=grpc.StatusCode.INTERNAL.value[0], message=str(e))
+                self.cur.execute("ROLLBACK TO SAVEPOINT bulk_item;")
+                results.append(result)
+                failed = True
+                if request.atomic:
+                    break
+        except Exception:
+            self.conn.rollback()
+            raise
+        if request.atomic and failed:
+            self.conn.rollback()
+            return common_pb2.BulkTasksResponse(results=results, committed=False)
         self.conn.commit()
-        return empty_pb2.Empty()
+        return common_pb2.BulkTasksResponse(results=results, committed=True)
+
+    def FindTasksByExternalIds(self, request, context):
+        # Tasks in the trash are included, since they still hold their external ids.
+        self.cur.execute(f"SELECT {TASK_COLUMNS} FROM tasks WHERE author_id = %s AND external_id = ANY(%s) "
+                         "ORDER BY task_id;", (request.author_id, list(request.external_ids)))
+        return common_pb2.ListTasksResponse(tasks=self.task_messages(common_pb2.Task, self.cur.fetchall()))
 
     def DeleteTask(self, request, context):
         if not request.user_id or not request.task_id:
             raise ValueError("user_id or task_id is missing or empty")
-        self.cur.execute("SELECT author_id FROM tasks WHERE task_id = %s;", (request.task_id,))
+        self.cur.execute("SELECT author_id FROM tasks WHERE task_id = %s AND deleted_at IS NULL;", (request.task_id,))
         task = self.cur.fetchone()
-        if not task or task[0] != request.user_id:
+        if not task or (task[0] != request.user_id and not request.granted):
             context.abort(grpc.StatusCode.PERMISSION_DENIED,
                           "Permission Denied")
 
-        self.cur.execute(
-            "DELETE FROM tasks WHERE task_id = %s;", (request.task_id,))
-        self.conn.commit()
+        # The task goes to the author's trash; PurgeTrash removes it for good later.
+        self.cur.execute("UPDATE tasks SET deleted_at = now(), deleted_by = %s WHERE task_id = %s;",
+                         (request.user_id, request.task_id))
+        self.transaction(context).commit()
         return empty_pb2.Empty()
 
-    def GetTask(self, request, context):
-        if not request.task_id:
+    def ListTrash(self, request, context):
+        self.cur.execute(f"SELECT {TASK_COLUMNS} FROM tasks WHERE author_id = %s AND deleted_at IS NOT NULL "
+                         "ORDER BY deleted_at DESC, task_id DESC;", (request.user_id,))
+        return common_pb2.ListTasksResponse(tasks=self.task_messages(common_pb2.Task, self.cur.fetchall()))
+
+    def RestoreTask(self, request, context):
+        if not request.user_id or not request.task_id:
             raise ValueError("user_id or task_id is missing or empty")
-        self.cur.execute("SELECT task_id, author_id, text FROM tasks WHERE task_id = %s;", (request.task_id,))
+        self.cur.execute("SELECT author_id, deleted_at > now() - make_interval(secs => %s) FROM tasks "
+                         "WHERE task_id = %s AND deleted_at IS NOT NULL;",
+                         (request.retention_seconds, request.task_id))
         task = self.cur.fetchone()
         if not task:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Task is not in the trash")
+        if task[0] != request.user_id:
+            context.abort(grpc.StatusCode.PERMISSION_DENIED, "Only the author can restore a task")
+        if not task[1]:
+            context.abort(grpc.StatusCode.FAILED_PRECONDITION, "The retention period of this task is over")
+        self.cur.execute("UPDATE tasks SET deleted_at = NULL, deleted_by = NULL WHERE task_id = %s;",
+                         (request.task_id,))
+        self.conn.commit()
+        return common_pb2.EmptyMessage()
+
+    def PurgeTrash(self, request, context):
+        self.cur.execute("SELECT task_id FROM tasks WHERE deleted_at < now() - make_interval(secs => %s) "
+                         "ORDER BY task_id FOR UPDATE;", (request.retention_seconds,))
+        task_ids = [row[0] for row in self.cur.fetchall()]
+        self.cur.execute("SELECT DISTINCT sha256 FROM attachments WHERE task_id = ANY(%s);", (task_ids,))
+        blobs = [row[0] for row in self.cur.fetchall()]
+        # Attachments go away with their tasks.
+        self.cur.execute("DELETE FROM tasks WHERE task_id = ANY(%s);", (task_ids,))
+        self.cur.execute("SELECT DISTINCT sha256 FROM attachments WHERE sha256 = ANY(%s);", (blobs,))
+        still_used = {row[0] for row in self.cur.fetchall()}
+        self.conn.commit()
+        return common_pb2.PurgeTrashResponse(task_ids=task_ids,
+                                             orphaned_blobs=sorted(set(blobs) - still_used))
+
+    def CreateAttachment(self, request, context):
+        if not request.user_id or not request.task_id or not request.filename or not request.sha256:
+            raise ValueError("user_id, task_id, filename or sha256 is missing or empty")
+        # Locking the task makes concurrent uploads to it take turns with the quota check.
+        self.cur.execute("SELECT task_id FROM tasks WHERE task_id = %s AND deleted_at IS NULL FOR UPDATE;",
+                         (request.task_id,))
+        if not self.cur.fetchone():
+            self.conn.rollback()
             context.abort(grpc.StatusCode.NOT_FOUND, "Task doesn't exist")
+        self.cur.execute("SELECT coalesce(sum(size_bytes), 0) FROM attachments WHERE task_id = %s;",
+                         (request.task_id,))
+        used = self.cur.fetchone()[0]
+        if used + request.size > request.max_task_bytes:
+            self.conn.rollback()
+            context.abort(grpc.StatusCode.RESOURCE_EXHAUSTED,
+                          f"Attachments of a task can't take more than {request.max_task_bytes} bytes")
+        self.cur.execute(f"INSERT INTO attachments (task_id, uploader_id, filename, content_type, size_bytes, sha256) "
+                         f"VALUES (%s, %s, %s, %s, %s, %s) RETURNING {ATTACHMENT_COLUMNS};",
+                         (request.task_id, request.user_id, request.filename, request.content_type,
+                          request.size, request.sha256))
+        row = self.cur.fetchone()
+        self.conn.commit()
+        return attachment_message(row)
+
+    def GetAttachment(self, request, context):
+        self.cur.execute(f"SELECT {', '.join('a.' + c for c in ATTACHMENT_COLUMNS.split(', '))} FROM attachments a "
+                         "JOIN tasks t ON t.task_id = a.task_id "
+                         "WHERE a.attachment_id = %s AND t.deleted_at IS NULL;", (request.attachment_id,))
+        row = self.cur.fetchone()
+        if not row:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Attachment doesn't exist")
+        return attachment_message(row)
+
+    def DeleteAttachment(self, request, context):
+        if not request.user_id or not request.attachment_id:
+            raise ValueError("user_id or attachment_id is missing or empty")
+        self.cur.execute("SELECT a.uploader_id, t.author_id, a.sha256 FROM attachments a "
+                         "JOIN tasks t ON t.task_id = a.task_id "
+                         "WHERE a.attachment_id = %s AND t.deleted_at IS NULL;", (request.attachment_id,))
+        attachment = self.cur.fetchone()
+        if not attachment:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Attachment doesn't exist")
+        if request.user_id not in (attachment[0], attachment[1]):
+            context.abort(grpc.StatusCode.PERMISSION_DENIED,
+                          "Only the uploader or the task author can delete an attachment")
+        self.cur.execute("DELETE FROM attachments WHERE attachment_id = %s;", (request.attachment_id,))
+        self.cur.execute("SELECT count(*) FROM attachments WHERE sha256 = %s;", (attachment[2],))
+        orphaned = self.cur.fetchone()[0] == 0
+        self.conn.commit()
+        return common_pb2.DeleteAttachmentResponse(sha256=attachment[2], orphaned=orphaned)
 
-        return common_pb2.GetTaskResponse(task_id=task[0], author_id=task[1], text=task[2])
+    def get_series(self, series_id, lock=False):
+        self.cur.execute(f"SELECT {SERIES_COLUMNS} FROM task_series WHERE series_id = %s"
+                         f"{' FOR UPDATE' if lock else ''};", (series_id,))
+        return self.cur.fetchone()
+
+    def series_messages(self, rows):
+        series_ids = [row[0] for row in rows]
+        occurrences = {series_id: [] for series_id in series_ids}
+        if series_ids:
+            self.cur.execute("SELECT series_id, task_id FROM tasks WHERE series_id = ANY(%s) AND deleted_at IS NULL "
+                             "ORDER BY task_id;", (series_ids,))
+            for row in self.cur.fetchall():
+                occurrences[row[0]].append(row[1])
+        return [series_message(row, occurrences[row[0]]) for row in rows]
+
+    def CreateSeries(self, request, context):
+        first = request.first
+        if not first.author_id or not (first.title or first.text) or not request.rrule or not request.dtstart:
+            raise ValueError("author_id, both title and text, rrule or dtstart is missing or empty")
+        next_at = request.next_at if request.HasField('next_at') else None
+        self.cur.execute("INSERT INTO task_series (author_id, rrule, timezone, dtstart, title, text, priority, "
+                         "assignee_id, project_id, estimate_minutes, next_at, visibility) "
+                         "VALUES (%s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s, %s) RETURNING series_id;",
+                         (first.author_id, request.rrule, request.timezone, request.dtstart, first.title, first.text,
+                          first.priority or 'medium',
+                          first.assignee_id if first.HasField('assignee_id') else None,
+                          first.project_id if first.HasField('project_id') else None,
+                          first.estimate_minutes if first.HasField('estimate_minutes') else None, next_at,
+                          first.visibility or 'public'))
+        series_id = self.cur.fetchone()[0]
+        task_id = self.insert_task(first, context, series_id)
+        self.conn.commit()
+        return common_pb2.CreateSeriesResponse(series_id=series_id, task_id=task_id)
+
+    def GetSeries(self, request, context):
+        series = self.get_series(request.series_id)
+        if not series or (request.HasField('viewer') and not series_visible(series, request.viewer)):
+            context.abort(grpc.StatusCode.NOT_FOUND, "Series doesn't exist")
+        return self.series_messages([series])[0]
+
+    def check_series_author(self, request, context):
+        series = self.get_series(request.series_id, lock=True)
+        if not series:
+            self.conn.rollback()
+            context.abort(grpc.StatusCode.NOT_FOUND, "Series doesn't exist")
+        if series[1] != request.user_id:
+            self.conn.rollback()
+            context.abort(grpc.StatusCode.PERMISSION_DENIED, "Only the author can change a series")
+
+    def UpdateSeries(self, request, context):
+        if not request.user_id or not request.series_id:
+            raise ValueError("user_id or series_id is missing or empty")
+        self.check_series_author(request, context)
+        changes = {field: getattr(request, field) for field in SERIES_TEMPLATE_FIELDS if request.HasField(field)}
+        if request.clear_assignee:
+            changes['assignee_id'] = None
+        if request.clear_estimate:
+            changes['estimate_minutes'] = None
+        rule = {}
+        if request.HasField('rrule'):
+            rule = {'rrule': request.rrule, 'timezone': request.timezone, 'dtstart': request.dtstart,
+                    'next_at': request.next_at if request.HasField('next_at') else None}
+        series_changes = {**changes, **rule}
+        if series_changes:
+            self.cur.execute(f"UPDATE task_series SET {', '.join(f'{name} = %s' for name in series_changes)}, "
+                             "updated_at = now() WHERE series_id = %s;",
+                             (*series_changes.values(), request.series_id))
+        if changes:
+            # Occurrences that aren't finished yet follow the series; the rest keep their history.
+            self.cur.execute("SELECT task_id FROM tasks WHERE series_id = %s AND deleted_at IS NULL "
+                             "AND NOT status = ANY(%s) ORDER BY task_id FOR UPDATE;",
+                             (request.series_id, list(request.done_statuses)))
+            for (task_id,) in self.cur.fetchall():
+                self.cur.execute(f"UPDATE tasks SET {', '.join(f'{name} = %s' for name in changes)}, "
+                                 "updated_at = now() WHERE task_id = %s;", (*changes.values(), task_id))
+                self.record_revision(task_id, request.user_id)
+        self.conn.commit()
+        return self.series_messages([self.get_series(request.series_id)])[0]
+
+    def StopSeries(self, request, context):
+        if not request.user_id or not request.series_id:
+            raise ValueError("user_id or series_id is missing or empty")
+        self.check_series_author(request, context)
+        self.cur.execute("UPDATE task_series SET next_at = NULL, updated_at = now() WHERE series_id = %s;",
+                         (request.series_id,))
+        self.conn.commit()
+        return common_pb2.EmptyMessage()
+
+    def ListDueSeries(self, request, context):
+        series_id = request.series_id if request.HasField('series_id') else None
+        self.cur.execute(f"SELECT {SERIES_COLUMNS} FROM task_series s WHERE next_at IS NOT NULL "
+                         "AND (%s::bigint IS NULL OR series_id = %s) "
+                         "AND (next_at <= now() OR (SELECT t.status = ANY(%s) FROM tasks t "
+                         "WHERE t.series_id = s.series_id ORDER BY t.task_id DESC LIMIT 1)) "
+                         "ORDER BY next_at;", (series_id, series_id, list(request.done_statuses)))
+        return common_pb2.ListSeriesResponse(series=self.series_messages(self.cur.fetchall()))
+
+    def MaterializeOccurrence(self, request, context):
+        if not request.series_id or not request.occurrence_at or not request.status:
+            raise ValueError("series_id, occurrence_at or status is missing or empty")
+        next_at = request.next_at if request.HasField('next_at') else None
+        # Moving next_at on only from the value the occurrence was computed from keeps two
+        # schedulers from creating the same occurrence.
+        self.cur.execute(f"UPDATE task_series SET next_at = %s WHERE series_id = %s AND next_at = %s "
+                         f"RETURNING {SERIES_COLUMNS};", (next_at, request.series_id, request.expected_next_at))
+        series = self.cur.fetchone()
+        if not series:
+            self.conn.rollback()
+            context.abort(grpc.StatusCode.FAILED_PRECONDITION, "The series has moved on")
+        optional = {name: value for name, value in
+                    (('assignee_id', series[8]), ('project_id', series[9]), ('estimate_minutes', series[10]))
+                    if value is not None}
+        occurrence = common_pb2.CreateTaskRequest(author_id=series[1], text=series[6], title=series[5],
+                                                  status=request.status, priority=series[7],
+                                                  due_date=request.occurrence_at, visibility=series[14], **optional)
+        task_id = self.insert_task(occurrence, context, request.series_id)
+        self.conn.commit()
+        return common_pb2.CreateTaskResponse(task_id=task_id)
+
+    def GetTask(self, request, context):
+        if request.task_id:
+            conditions, params = ["task_id = %s"], [request.task_id]
+        elif request.project_id and request.project_number:
+            conditions, params = ["project_id = %s", "project_number = %s"], [request.project_id,
+                                                                              request.project_number]
+        else:
+            raise ValueError("task_id or project key is missing or empty")
+        if request.HasField('viewer'):
+            # Tasks hidden from the viewer look missing, so that their ids can't be probed.
+            condition, visibility_params = visibility_condition(request.viewer)
+            conditions.append(condition)
+            params += visibility_params
+        self.cur.execute(f"SELECT {TASK_COLUMNS} FROM tasks WHERE {' AND '.join(conditions)} "
+                         "AND deleted_at IS NULL;", params)
+        task = self.cur.fetchone()
+        if not task:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Task doesn't exist")
+
+        return self.task_messages(common_pb2.GetTaskResponse, [task])[0]
 
     def ListTasks(self, request, context):
-        # if not request.user_id or not request.offset or not request.limit:
-        #     raise ValueError("user_id, offset or limit is missing or empty")
-        # TODO а здесь порядок гарантирован?
-        self.cur.execute("SELECT task_id, author_id, text FROM tasks WHERE author_id = %s LIMIT %s OFFSET %s;",
-                         (request.user_id, request.limit, request.offset))
+        if request.kind == common_pb2.ASSIGNED_TO_USER:
+            conditions, params = ["assignee_id = %s"], [request.user_id]
+        elif request.kind == common_pb2.UNASSIGNED:
+            conditions, params = ["assignee_id IS NULL"], []
+        elif request.kind == common_pb2.IN_PROJECT:
+            conditions, params = ["project_id = %s"], [request.project_id]
+        elif request.kind == common_pb2.SUBTASKS:
+            conditions, params = ["parent_id = %s"], [request.parent_id]
+        else:
+            conditions, params = ["author_id = %s"], [request.user_id]
+        conditions.append("deleted_at IS NULL")
+        if request.HasField('viewer'):
+            condition, visibility_params = visibility_condition(request.viewer)
+            conditions.append(condition)
+            params += visibility_params
+        if request.label_ids:
+            # Only tasks carrying every requested label.
+            conditions.append("task_id IN (SELECT task_id FROM task_labels WHERE label_id = ANY(%s) "
+                              "GROUP BY task_id HAVING count(*) = %s)")
+            label_ids = list(set(request.label_ids))
+            params += [label_ids, len(label_ids)]
+        if request.statuses:
+            conditions.append("status = ANY(%s)")
+            params.append(list(request.statuses))
+        for field, condition in LIST_FILTERS:
+            if request.HasField(field):
+                conditions.append(condition)
+                params.append(getattr(request, field))
+
+        total_count = None
+        if request.include_total:
+            self.cur.execute(f"SELECT count(*) FROM tasks WHERE {' AND '.join(conditions)};", params)
+            total_count = self.cur.fetchone()[0]
+
+        ascending_key, descending_key, key_type = SORT_KEYS[request.sort]
+        key = descending_key if request.descending else ascending_key
+        direction, comparison = ('DESC', '<') if request.descending else ('ASC', '>')
+        if request.cursor:
+            cursor = decode_cursor(request.cursor)
+            if not cursor or cursor.get('sort') != request.sort or cursor.get('desc') != request.descending:
+                context.abort(grpc.StatusCode.INVALID_ARGUMENT, "Cursor doesn't match the sort order")
+            # Cursors come back from clients, so the key is checked before it is cast.
+            self.cur.execute("SELECT pg_input_is_valid(%s, %s);", (cursor['key'], key_type))
+            if not self.cur.fetchone()[0]:
+                context.abort(grpc.StatusCode.INVALID_ARGUMENT, "Cursor is invalid")
+            conditions.append(f"({key}, task_id) {comparison} (%s::{key_type}, %s)")
+            params += [cursor['key'], cursor['id']]
+        # One extra row tells whether there is a next page.
+        self.cur.execute(f"SELECT {TASK_COLUMNS}, ({key})::text FROM tasks WHERE {' AND '.join(conditions)} "
+                         f"ORDER BY {key} {direction}, task_id {direction} LIMIT %s OFFSET %s;",
+                         (*params, request.limit + 1, request.offset))
+        rows = self.cur.fetchall()
+
+        next_cursor = ''
+        if len(rows) > request.limit:
+            rows = rows[:request.limit]
+            next_cursor = encode_cursor({'sort': request.sort, 'desc': request.descending,
+                                         'key': rows[-1][-1], 'id': rows[-1][0]})
+        tasks_list = self.task_messages(common_pb2.Task, [row[:-1] for row in rows])
+        return common_pb2.ListTasksResponse(tasks=tasks_list, next_cursor=next_cursor, total_count=total_count)
+
+    def AssignTask(self, request, context):
+        if not request.user_id or not request.task_id:
+            raise ValueError("user_id or task_id is missing or empty")
+        self.cur.execute("SELECT author_id, assignee_id FROM tasks WHERE task_id = %s AND deleted_at IS NULL;",
+                         (request.task_id,))
+        task = self.cur.fetchone()
+        if not task:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Task doesn't exist")
+        if request.user_id not in task and not request.granted:
+            context.abort(grpc.StatusCode.PERMISSION_DENIED,
+                          "Only the author, the assignee or an editor can change the assignee")
 
-        tasks_rows = self.cur.fetchall()
-        tasks_list = [common_pb2.Task(task_id=row[0], author_id=row[1], text=row[2]) for row in tasks_rows]
+        assignee_id = request.assignee_id if request.HasField('assignee_id') else None
+        self.cur.execute("UPDATE tasks SET assignee_id = %s, updated_at = now() WHERE task_id = %s;",
+                         (assignee_id, request.task_id))
+        self.record_revision(request.task_id, request.user_id)
+        self.transaction(context).commit()
+        return common_pb2.EmptyMessage()
 
+    def ListBoardTasks(self, request, context):
+        conditions, params = ["project_id = %s", "deleted_at IS NULL"], [request.project_id]
+        if request.HasField('viewer'):
+            condition, visibility_params = visibility_condition(request.viewer)
+            conditions.append(condition)
+            params += visibility_params
+        # Unranked tasks have never been moved on the board and go to the bottom of their column.
+        self.cur.execute(f"SELECT {TASK_COLUMNS} FROM tasks WHERE {' AND '.join(conditions)} "
+                         "ORDER BY board_rank NULLS LAST, task_id;", params)
+        tasks_list = self.task_messages(common_pb2.Task, self.cur.fetchall())
         return common_pb2.ListTasksResponse(tasks=tasks_list)
 
+    def MoveTask(self, request, context):
+        if not request.task_id or not request.actor_id or not request.to_status or not request.rank:
+            raise ValueError("task_id, actor_id, to_status or rank is missing or empty")
+        for update in request.rank_updates:
+            self.cur.execute("UPDATE tasks SET board_rank = %s WHERE task_id = %s AND project_id = %s;",
+                             (update.rank, update.task_id, request.project_id))
+        status_changes = request.from_status != request.to_status
+        if status_changes and request.HasField('wip_limit'):
+            # Row locks can't stop a task from entering the column, so concurrent moves into it
+            # take turns on a lock of the column itself; the count then sees the earlier moves.
+            self.cur.execute("SELECT pg_advisory_xact_lock(hashtextextended(%s, %s));",
+                             (request.to_status, request.project_id))
+            self.cur.execute("SELECT count(*) FROM tasks WHERE project_id = %s AND status = %s "
+                             "AND deleted_at IS NULL;",
+                             (request.project_id, request.to_status))
+            if self.cur.fetchone()[0] >= request.wip_limit:
+                self.conn.rollback()
+                context.abort(grpc.StatusCode.FAILED_PRECONDITION,
+                              f"WIP limit of {request.wip_limit} reached for column '{request.to_status}'")
+        self.cur.execute("UPDATE tasks SET status = %s, board_rank = %s, updated_at = now() "
+                         "WHERE task_id = %s AND project_id = %s AND status = %s AND deleted_at IS NULL;",
+                         (request.to_status, request.rank, request.task_id, request.project_id, request.from_status))
+        if self.cur.rowcount == 0:
+            self.conn.rollback()
+            context.abort(grpc.StatusCode.FAILED_PRECONDITION,
+                          f"Task is no longer in status '{request.from_status}'")
+        if status_changes:
+            self.cur.execute("INSERT INTO task_transitions (task_id, actor_id, from_status, to_status) "
+                             "VALUES (%s, %s, %s, %s);",
+                             (request.task_id, request.actor_id, request.from_status, request.to_status))
+            self.record_revision(request.task_id, request.actor_id)
+        self.conn.commit()
+        return common_pb2.EmptyMessage()
+
+    def TransitionTask(self, request, context):
+        if not request.task_id or not request.actor_id or not request.to_status:
+            raise ValueError("task_id, actor_id or to_status is missing or empty")
+        # The status check makes the move atomic: a concurrent transition wins and this one fails.
+        self.cur.execute("UPDATE tasks SET status = %s, updated_at = now() "
+                         "WHERE task_id = %s AND status = %s AND deleted_at IS NULL;",
+                         (request.to_status, request.task_id, request.from_status))
+        if self.cur.rowcount == 0:
+            self.transaction(context).rollback()
+            context.abort(grpc.StatusCode.FAILED_PRECONDITION,
+                          f"Task is no longer in status '{request.from_status}'")
+        self.cur.execute("INSERT INTO task_transitions (task_id, actor_id, from_status, to_status) "
+                         "VALUES (%s, %s, %s, %s) RETURNING created_at;",
+                         (request.task_id, request.actor_id, request.from_status, request.to_status))
+        created_at = self.cur.fetchone()[0]
+        self.record_revision(request.task_id, request.actor_id)
+        self.transaction(context).commit()
+        return common_pb2.TaskTransition(task_id=request.task_id, actor_id=request.actor_id,
+                                         from_status=request.from_status, to_status=request.to_status,
+                                         created_at=created_at.isoformat())
+
+    def ListTaskTransitions(self, request, context):
+        self.cur.execute("SELECT task_id, actor_id, from_status, to_status, created_at FROM task_transitions "
+                         "WHERE task_id = %s ORDER BY transition_id;", (request.task_id,))
+        transitions = [common_pb2.TaskTransition(task_id=row[0], actor_id=row[1], from_status=row[2],
+                                                 to_status=row[3], created_at=row[4].isoformat())
+                       for row in self.cur.fetchall()]
+        return common_pb2.ListTaskTransitionsResponse(transitions=transitions)
+
+    def dependency_tasks(self, request, context):
+        if not request.user_id or not request.blocker_id or not request.blocked_id:
+            raise ValueError("user_id, blocker_id or blocked_id is missing or empty")
+        self.cur.execute("SELECT task_id, author_id, assignee_id, project_id FROM tasks "
+                         "WHERE task_id = ANY(%s) AND deleted_at IS NULL;",
+                         ([request.blocker_id, request.blocked_id],))
+        tasks = {row[0]: row[1:] for row in self.cur.fetchall()}
+        if request.blocker_id not in tasks or request.blocked_id not in tasks:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Task doesn't exist")
+        blocked = tasks[request.blocked_id]
+        if request.user_id not in blocked[:2] and not request.granted:
+            context.abort(grpc.StatusCode.PERMISSION_DENIED,
+                          "Only the author, the assignee or an editor of the blocked task can change its dependencies")
+        return tasks[request.blocker_id], blocked
+
+    def AddDependency(self, request, context):
+        if request.blocker_id and request.blocker_id == request.blocked_id:
+            context.abort(grpc.StatusCode.INVALID_ARGUMENT, "A task can't block itself")
+        blocker, blocked = self.dependency_tasks(request, context)
+        if blocker[2] != blocked[2]:
+            context.abort(grpc.StatusCode.INVALID_ARGUMENT, "Dependent tasks must be in the same project")
+        self.cur.execute("SELECT pg_advisory_xact_lock(%s);", (TASK_GRAPH_LOCK,))
+        # The new edge closes a cycle if the blocker is already reachable from the blocked task.
+        self.cur.execute("WITH RECURSIVE reachable(task_id) AS (SELECT %s::bigint "
+                         "UNION SELECT d.blocked_id FROM task_dependencies d JOIN reachable r ON d.blocker_id = r.task_id) "
+                         "SELECT 1 FROM reachable WHERE task_id = %s;", (request.blocked_id, request.blocker_id))
+        if self.cur.fetchone():
+            self.conn.rollback()
+            context.abort(grpc.StatusCode.FAILED_PRECONDITION, "The dependency would create a cycle")
+        self.cur.execute("INSERT INTO task_dependencies (blocker_id, blocked_id) VALUES (%s, %s) "
+                         "ON CONFLICT DO NOTHING;", (request.blocker_id, request.blocked_id))
+        self.conn.commit()
+        return common_pb2.EmptyMessage()
+
+    def RemoveDependency(self, request, context):
+        self.dependency_tasks(request, context)
+        self.cur.execute("DELETE FROM task_dependencies WHERE blocker_id = %s AND blocked_id = %s;",
+                         (request.blocker_id, request.blocked_id))
+        if self.cur.rowcount == 0:
+            self.conn.rollback()
+            context.abort(grpc.StatusCode.NOT_FOUND, "Dependency doesn't exist")
+        self.conn.commit()
+        return common_pb2.EmptyMessage()
+
+    def ListDependencies(self, request, context):
+        # Dependencies on tasks in the trash are kept for a restore but not shown.
+        live = ("FROM task_dependencies d JOIN tasks t ON t.task_id = d.blocker_id AND t.deleted_at IS NULL "
+                "JOIN tasks b ON b.task_id = d.blocked_id AND b.deleted_at IS NULL")
+        if request.project_id:
+            # Both ends of a dependency are always in the same project.
+            self.cur.execute(f"SELECT d.blocker_id, d.blocked_id {live} WHERE t.project_id = %s "
+                             "ORDER BY d.blocker_id, d.blocked_id;", (request.project_id,))
+        elif request.task_id:
+            self.cur.execute(f"SELECT d.blocker_id, d.blocked_id {live} "
+                             "WHERE d.blocker_id = %s OR d.blocked_id = %s ORDER BY d.blocker_id, d.blocked_id;",
+                             (request.task_id, request.task_id))
+        else:
+            raise ValueError("project_id or task_id is missing or empty")
+        dependencies = [common_pb2.Dependency(blocker_id=row[0], blocked_id=row[1]) for row in self.cur.fetchall()]
+        return common_pb2.ListDependenciesResponse(dependencies=dependencies)
+
+    def SearchTasks(self, request, context):
+        if not request.tsquery or not request.limit:
+            raise ValueError("tsquery or limit is missing or empty")
+        comment_match = "c.task_id = t.task_id AND NOT c.deleted AND c.search_vector @@ q.query"
+        conditions = [f"(t.search_vector @@ q.query OR EXISTS (SELECT 1 FROM comments c WHERE {comment_match}))",
+                      "(t.project_id IS NULL OR t.project_id = ANY(%s))", "t.deleted_at IS NULL"]
+        params = [list(request.visible_project_ids)]
+        if request.HasField('viewer'):
+            condition, visibility_params = visibility_condition(request.viewer, 't.')
+            conditions.append(condition)
+            params += visibility_params
+        for field, condition in (('author_id', "t.author_id = %s"), ('assignee_id', "t.assignee_id = %s"),
+                                 ('status', "t.status = %s"),
+                                 ('label_id', "EXISTS (SELECT 1 FROM task_labels tl "
+                                              "WHERE tl.task_id = t.task_id AND tl.label_id = %s)"),
+                                 ('created_after', "t.created_at >= %s"), ('created_before', "t.created_at < %s")):
+            if request.HasField(field):
+                conditions.append(condition)
+                params.append(getattr(request, field))
+
+        # Matches in comments count, but weigh less than matches in the task itself.
+        self.cur.execute(
+            "WITH q AS (SELECT to_tsquery('simple', %s) AS query), "
+            "matches AS (SELECT t.task_id, ts_rank(t.search_vector, q.query) + 0.5 * coalesce("
+            f"(SELECT max(ts_rank(c.search_vector, q.query)) FROM comments c WHERE {comment_match}), 0) AS rank "
+            f"FROM tasks t, q WHERE {' AND '.join(conditions)}) "
+            f"SELECT {', '.join('t.' + c for c in TASK_COLUMNS.split(', '))}, m.rank, "
+            f"ts_headline('simple', {escaped_html('t.title')}, q.query, 'HighlightAll=true, StartSel=<mark>, StopSel=</mark>'), "
+            f"ts_headline('simple', {escaped_html('t.text')}, q.query, '{SNIPPET_OPTIONS}'), "
+            f"(SELECT ts_headline('simple', {escaped_html('c.content')}, q.query, '{SNIPPET_OPTIONS}') "
+            f"FROM comments c WHERE {comment_match} "
+            "ORDER BY ts_rank(c.search_vector, q.query) DESC, c.comment_id LIMIT 1), "
+            "count(*) OVER () "
+            "FROM matches m JOIN tasks t ON t.task_id = m.task_id, q "
+            "ORDER BY m.rank DESC, t.task_id DESC LIMIT %s OFFSET %s;",
+            (request.tsquery, *params, request.limit, request.offset))
+        rows = self.cur.fetchall()
+
+        width = len(TASK_COLUMNS.split(', '))
+        tasks = self.task_messages(common_pb2.Task, [row[:width] for row in rows])
+        hits = []
+        for task, row in zip(tasks, rows):
+            hit = common_pb2.SearchHit(task=task, rank=row[width], title_snippet=row[width + 1],
+                                       description_snippet=row[width + 2])
+            if row[width + 3] is not None:
+                hit.comment_snippet = row[width + 3]
+            hits.append(hit)
+        total_count = rows[0][width + 4] if rows else 0
+        return common_pb2.SearchTasksResponse(hits=hits, total_count=total_count)
+
+    def ListRevisions(self, request, context):
+        self.cur.execute(f"SELECT {REVISION_COLUMNS} FROM task_revisions r WHERE r.task_id = %s "
+                         "ORDER BY r.revision_number;", (request.task_id,))
+        revisions = [revision_message(row) for row in self.cur.fetchall()]
+        return common_pb2.ListRevisionsResponse(revisions=revisions)
+
+    def get_revision(self, task_id, revision_number, context):
+        self.cur.execute(f"SELECT {REVISION_COLUMNS} FROM task_revisions r "
+                         "WHERE r.task_id = %s AND r.revision_number = %s;", (task_id, revision_number))
+        row = self.cur.fetchone()
+        if not row:
+            context.abort(grpc.StatusCode.NOT_FOUND, f"Revision {revision_number} doesn't exist")
+        return row
+
+    def GetRevision(self, request, context):
+        return revision_message(self.get_revision(request.task_id, request.revision_number, context))
+
+    def RestoreRevision(self, request, context):
+        if not request.user_id or not request.task_id:
+            raise ValueError("user_id or task_id is missing or empty")
+        self.cur.execute("SELECT author_id FROM tasks WHERE task_id = %s AND deleted_at IS NULL;", (request.task_id,))
+        task = self.cur.fetchone()
+        if not task:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Task doesn't exist")
+        if task[0] != request.user_id and not request.granted:
+            context.abort(grpc.StatusCode.PERMISSION_DENIED, "Only the author or an editor can restore a revision")
+        snapshot = self.get_revision(request.task_id, request.revision_number, context)[5]
+
+        # Status and parent have their own rules (workflow, cycle checks), so only content comes back.
+        restored = ('text', 'title', 'priority', 'due_date', 'assignee_id', 'estimate_minutes')
+        self.cur.execute(f"UPDATE tasks SET {', '.join(f + ' = %s' for f in restored)}, updated_at = now() "
+                         "WHERE task_id = %s;", (*[snapshot[f] for f in restored], request.task_id))
+        revision_number = self.record_revision(request.task_id, request.user_id)
+        if revision_number is None:
+            self.conn.rollback()
+            context.abort(grpc.StatusCode.FAILED_PRECONDITION, "The task already matches this revision")
+        self.conn.commit()
+        return revision_message(self.get_revision(request.task_id, revision_number, context))
+
+    def get_comment(self, comment_id):
+        self.cur.execute(f"SELECT {COMMENT_COLUMNS} FROM comments c WHERE c.comment_id = %s;", (comment_id,))
+        return self.cur.fetchone()
+
+    def CreateComment(self, request, context):
+        if not request.task_id or not request.author_id or not request.content:
+            raise ValueError("task_id, author_id or content is missing or empty")
+        if not self.get_author_id_of_task(request.task_id):
+            context.abort(grpc.StatusCode.NOT_FOUND, "Task doesn't exist")
+        parent_id = None
+        if request.HasField('parent_id'):
+            parent = self.get_comment(request.parent_id)
+            if not parent or parent[1] != request.task_id:
+                context.abort(grpc.StatusCode.NOT_FOUND, "Parent comment doesn't exist")
+            if parent[7]:
+                context.abort(grpc.StatusCode.FAILED_PRECONDITION, "Can't reply to a deleted comment")
+            parent_id = request.parent_id
+
+        self.cur.execute("INSERT INTO comments (task_id, parent_id, author_id, content) "
+                         "VALUES (%s, %s, %s, %s) RETURNING comment_id;",
+                         (request.task_id, parent_id, request.author_id, request.content))
+        comment_id = self.cur.fetchone()[0]
+        self.conn.commit()
+        return common_pb2.Comment(**comment_fields(self.get_comment(comment_id)))
+
+    def EditComment(self, request, context):
+        if not request.comment_id or not request.user_id or not request.content:
+            raise ValueError("comment_id, user_id or content is missing or empty")
+        comment = self.get_comment(request.comment_id)
+        if not comment:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Comment doesn't exist")
+        if comment[3] != request.user_id:
+            context.abort(grpc.StatusCode.PERMISSION_DENIED, "Only the author can edit a comment")
+        if comment[7]:
+            context.abort(grpc.StatusCode.FAILED_PRECONDITION, "Comment is deleted")
+
+        self.cur.execute("UPDATE comments SET content = %s, edited_at = now() WHERE comment_id = %s;",
+                         (request.content, request.comment_id))
+        self.conn.commit()
+        return common_pb2.Comment(**comment_fields(self.get_comment(request.comment_id)))
+
+    def DeleteComment(self, request, context):
+        if not request.comment_id or not request.user_id:
+            raise ValueError("comment_id or user_id is missing or empty")
+        comment = self.get_comment(request.comment_id)
+        if not comment or comment[7]:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Comment doesn't exist")
+        if comment[3] != request.user_id and not request.is_moderator:
+            context.abort(grpc.StatusCode.PERMISSION_DENIED, "Permission Denied")
+
+        # Replies stay in place, so the comment is replaced by a tombstone instead of being removed.
+        self.cur.execute("UPDATE comments SET content = '', deleted = true, deleted_by = %s WHERE comment_id = %s;",
+                         (request.user_id, request.comment_id))
+        self.conn.commit()
+        return common_pb2.EmptyMessage()
+
+    def ListComments(self, request, context):
+        if request.HasField('parent_id'):
+            condition, params = "c.parent_id = %s", (request.task_id, request.parent_id)
+        else:
+            condition, params = "c.parent_id IS NULL", (request.task_id,)
+        self.cur.execute(f"SELECT count(*) FROM comments c WHERE c.task_id = %s AND {condition};", params)
+        total_count = self.cur.fetchone()[0]
+        self.cur.execute(f"SELECT {COMMENT_COLUMNS} FROM comments c WHERE c.task_id = %s AND {condition} "
+                         "ORDER BY c.comment_id LIMIT %s OFFSET %s;",
+                         (*params, request.limit, request.offset))
+        comments = [common_pb2.Comment(**comment_fields(row)) for row in self.cur.fetchall()]
+        return common_pb2.ListCommentsResponse(comments=comments, total_count=total_count)
+
+    def get_label(self, label_id):
+        self.cur.execute(f"SELECT {LABEL_COLUMNS} FROM labels WHERE label_id = %s;", (label_id,))
+        return self.cur.fetchone()
+
+    def CreateLabel(self, request, context):
+        if not request.owner_id or not request.name or not request.color:
+            raise ValueError("owner_id, name or color is missing or empty")
+        try:
+            self.cur.execute(f"INSERT INTO labels (owner_kind, owner_id, name, color, description) "
+                             f"VALUES (%s, %s, %s, %s, %s) RETURNING {LABEL_COLUMNS};",
+                             (request.owner_kind, request.owner_id, request.name, request.color, request.description))
+        except psycopg2.errors.UniqueViolation:
+            self.conn.rollback()
+            context.abort(grpc.StatusCode.ALREADY_EXISTS, "Label with this name already exists")
+        label = self.cur.fetchone()
+        self.conn.commit()
+        return label_message(label)
+
+    def UpdateLabel(self, request, context):
+        if not self.get_label(request.label_id):
+            context.abort(grpc.StatusCode.NOT_FOUND, "Label doesn't exist")
+        updates, params = [], []
+        for field in ('name', 'color', 'description'):
+            if request.HasField(field):
+                updates.append(f"{field} = %s")
+                params.append(getattr(request, field))
+        if not updates:
+            raise ValueError("nothing to update")
+        # Tasks reference labels by id, so a rename is visible on every task at once.
+        try:
+            self.cur.execute(f"UPDATE labels SET {', '.join(updates)} WHERE label_id = %s RETURNING {LABEL_COLUMNS};",
+                             (*params, request.label_id))
+        except psycopg2.errors.UniqueViolation:
+            self.conn.rollback()
+            context.abort(grpc.StatusCode.ALREADY_EXISTS, "Label with this name already exists")
+        label = self.cur.fetchone()
+        self.conn.commit()
+        return label_message(label)
+
+    def DeleteLabel(self, request, context):
+        self.cur.execute("DELETE FROM labels WHERE label_id = %s;", (request.label_id,))
+        if self.cur.rowcount == 0:
+            self.conn.rollback()
+            context.abort(grpc.StatusCode.NOT_FOUND, "Label doesn't exist")
+        self.conn.commit()
+        return common_pb2.EmptyMessage()
+
+    def MergeLabels(self, request, context):
+        source = self.get_label(request.source_label_id)
+        target = self.get_label(request.target_label_id)
+        if not source or not target:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Label doesn't exist")
+        if source[0] == target[0]:
+            context.abort(grpc.StatusCode.FAILED_PRECONDITION, "Can't merge a label into itself")
+        if source[1:3] != target[1:3]:
+            context.abort(grpc.StatusCode.FAILED_PRECONDITION, "Only labels of the same owner can be merged")
+
+        self.cur.execute("INSERT INTO task_labels (task_id, label_id) "
+                         "SELECT task_id, %s FROM task_labels WHERE label_id = %s ON CONFLICT DO NOTHING;",
+                         (target[0], source[0]))
+        self.cur.execute("DELETE FROM labels WHERE label_id = %s;", (source[0],))
+        self.conn.commit()
+        return label_message(target)
+
+    def GetLabel(self, request, context):
+        label = self.get_label(request.label_id)
+        if not label:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Label doesn't exist")
+        return label_message(label)
+
+    def ListLabels(self, request, context):
+        self.cur.execute(f"SELECT {LABEL_COLUMNS} FROM labels "
+                         "WHERE (owner_kind = %s AND owner_id = %s) OR (owner_kind = %s AND owner_id = ANY(%s)) "
+                         "ORDER BY owner_kind, owner_id, name;",
+                         (common_pb2.OWNER_KIND_USER, request.user_id,
+                          common_pb2.OWNER_KIND_TEAM, list(request.team_ids)))
+        return common_pb2.ListLabelsResponse(labels=[label_message(row) for row in self.cur.fetchall()])
+
+    def check_can_label(self, request, context):
+        self.cur.execute("SELECT author_id, assignee_id FROM tasks WHERE task_id = %s AND deleted_at IS NULL;",
+                         (request.task_id,))
+        task = self.cur.fetchone()
+        if not task:
+            context.abort(grpc.StatusCode.NOT_FOUND, "Task doesn't exist")
+        if request.user_id not in task and not request.granted:
+            context.abort(grpc.StatusCode.PERMISSION_DENIED, "Permission Denied")
+
+    def AttachLabel(self, request, context):
+        self.check_can_label(request, context)
+        self.cur.execute("INSERT INTO task_labels (task_id, label_id) VALUES (%s, %s) ON CONFLICT DO NOTHING;",
+                         (request.task_id, request.label_id))
+        self.conn.commit()
+        return common_pb2.EmptyMessage()
+
+    def DetachLabel(self, request, context):
+        self.check_can_label(request, context)
+        self.cur.execute("DELETE FROM task_labels WHERE task_id = %s AND label_id = %s;",
+                         (request.task_id, request.label_id))
+        self.conn.commit()
+        return common_pb2.EmptyMessage()
+
     def SendLike(self, request, context):
         print('SendLike called', file=sys.stderr)
         author_id = self.get_author_id_of_task(request.task_id)
@@ -103,6 +1238,12 @@ class TaskService(common_pb2_grpc.TaskServiceServicer):
         print(f'type(author_id): {type(author_id)}, author_id: {author_id}', file=sys.stderr)
         if not author_id:
             raise ValueError("No such task_id")
+        # The counter only backs sorting by likes; the statistics stay in stat_service.
+        self.cur.execute("INSERT INTO task_likes (task_id, liker_id) VALUES (%s, %s) ON CONFLICT DO NOTHING;",
+                         (request.task_id, request.liker_id))
+        if self.cur.rowcount:
+            self.cur.execute("UPDATE tasks SET like_count = like_count + 1 WHERE task_id = %s;", (request.task_id,))
+        self.conn.commit()
         send_result = self.producer.send('queue_likes', {
             'task_id': request.task_id,
             'author_id': author_id,
@@ -125,8 +1266,9 @@ class TaskService(common_pb2_grpc.TaskServiceServicer):
 
 
 def serve():
-    server = grpc.server(futures.ThreadPoolExecutor(max_workers=10))
-    common_pb2_grpc.add_TaskServiceServicer_to_server(TaskService(), server)
+    service = TaskService()
+    server = grpc.server(futures.ThreadPoolExecutor(max_workers=10), interceptors=[RollbackInterceptor(service)])
+    common_pb2_grpc.add_TaskServiceServicer_to_server(service, server)
     server.add_insecure_port('[::]:50051')
     server.start()
     server.wait_for_termination()
@@ -1,5 +1,186 @@
+-- A recurring task: the occurrences are tasks created from the template columns, one for each
+-- start of the iCalendar RRULE in `timezone`.
+CREATE TABLE IF NOT EXISTS task_series (
+    series_id bigserial PRIMARY KEY,
+    author_id bigint NOT NULL,
+    rrule varchar(500) NOT NULL,
+    timezone varchar(64) NOT NULL,
+    dtstart timestamptz NOT NULL,
+    title varchar(200) NOT NULL,
+    text varchar NOT NULL,
+    priority varchar(16) NOT NULL,
+    assignee_id bigint,
+    project_id bigint,
+    estimate_minutes bigint,
+    visibility varchar(16) NOT NULL DEFAULT 'public',
+    -- Start of the next occurrence to create; NULL once the rule is exhausted or the series is stopped.
+    next_at timestamptz,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now()
+);
+
+ALTER TABLE task_series ADD COLUMN IF NOT EXISTS visibility varchar(16) NOT NULL DEFAULT 'public';
+
+CREATE INDEX IF NOT EXISTS task_series_next_idx ON task_series (next_at) WHERE next_at IS NOT NULL;
+
 CREATE TABLE IF NOT EXISTS tasks (
     task_id bigserial PRIMARY KEY,
     author_id bigint NOT NULL,
-    text varchar NOT NULL
+    text varchar NOT NULL,
+    title varchar(200) NOT NULL DEFAULT '',
+    status varchar(32) NOT NULL DEFAULT 'backlog',
+    priority varchar(16) NOT NULL DEFAULT 'medium',
+    due_date timestamptz,
+    assignee_id bigint,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    project_id bigint,
+    project_number bigint,
+    board_rank text COLLATE "C",
+    parent_id bigint REFERENCES tasks (task_id) ON DELETE SET NULL,
+    estimate_minutes bigint,
+    like_count bigint NOT NULL DEFAULT 0,
+    -- Set while the task is in its author's trash.
+    deleted_at timestamptz,
+    deleted_by bigint,
+    -- Id of the task in the system it was imported from.
+    external_id varchar(200),
+    -- Set on the occurrences of a recurring task.
+    series_id bigint REFERENCES task_series (series_id) ON DELETE SET NULL,
+    -- Number of the latest revision; update_task can be made conditional on it.
+    version bigint NOT NULL DEFAULT 1,
+    -- private, team or public; see the Viewer message for who sees what.
+    visibility varchar(16) NOT NULL DEFAULT 'public',
+    search_vector tsvector GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', title), 'A') || setweight(to_tsvector('simple', text), 'B')
+    ) STORED
+);
+
+-- Databases created before a column was added get it here; on new ones these do nothing.
+ALTER TABLE tasks
+    ADD COLUMN IF NOT EXISTS title varchar(200) NOT NULL DEFAULT '',
+    ADD COLUMN IF NOT EXISTS status varchar(32) NOT NULL DEFAULT 'backlog',
+    ADD COLUMN IF NOT EXISTS priority varchar(16) NOT NULL DEFAULT 'medium',
+    ADD COLUMN IF NOT EXISTS due_date timestamptz,
+    ADD COLUMN IF NOT EXISTS assignee_id bigint,
+    ADD COLUMN IF NOT EXISTS created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN IF NOT EXISTS updated_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN IF NOT EXISTS project_id bigint,
+    ADD COLUMN IF NOT EXISTS project_number bigint,
+    ADD COLUMN IF NOT EXISTS board_rank text COLLATE "C",
+    ADD COLUMN IF NOT EXISTS parent_id bigint REFERENCES tasks (task_id) ON DELETE SET NULL,
+    ADD COLUMN IF NOT EXISTS estimate_minutes bigint,
+    ADD COLUMN IF NOT EXISTS like_count bigint NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS deleted_at timestamptz,
+    ADD COLUMN IF NOT EXISTS deleted_by bigint,
+    ADD COLUMN IF NOT EXISTS external_id varchar(200),
+    ADD COLUMN IF NOT EXISTS series_id bigint REFERENCES task_series (series_id) ON DELETE SET NULL,
+    ADD COLUMN IF NOT EXISTS version bigint NOT NULL DEFAULT 1,
+    ADD COLUMN IF NOT EXISTS visibility varchar(16) NOT NULL DEFAULT 'public',
+    ADD COLUMN IF NOT EXISTS search_vector tsvector GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', title), 'A') || setweight(to_tsvector('simple', text), 'B')
+    ) STORED;
+
+CREATE UNIQUE INDEX IF NOT EXISTS tasks_project_number_idx ON tasks (project_id, project_number);
+
+CREATE INDEX IF NOT EXISTS tasks_search_idx ON tasks USING GIN (search_vector);
+
+CREATE INDEX IF NOT EXISTS tasks_parent_idx ON tasks (parent_id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS tasks_external_id_idx ON tasks (author_id, external_id);
+
+CREATE INDEX IF NOT EXISTS tasks_series_idx ON tasks (series_id);
+
+CREATE INDEX IF NOT EXISTS tasks_trash_idx ON tasks (deleted_at) WHERE deleted_at IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS task_dependencies (
+    blocker_id bigint NOT NULL REFERENCES tasks (task_id) ON DELETE CASCADE,
+    blocked_id bigint NOT NULL REFERENCES tasks (task_id) ON DELETE CASCADE,
+    PRIMARY KEY (blocker_id, blocked_id),
+    CHECK (blocker_id <> blocked_id)
+);
+
+CREATE INDEX IF NOT EXISTS task_dependencies_blocked_idx ON task_dependencies (blocked_id);
+
+CREATE TABLE IF NOT EXISTS task_likes (
+    task_id bigint NOT NULL REFERENCES tasks (task_id) ON DELETE CASCADE,
+    liker_id bigint NOT NULL,
+    PRIMARY KEY (task_id, liker_id)
+);
+
+CREATE TABLE IF NOT EXISTS task_revisions (
+    task_id bigint NOT NULL REFERENCES tasks (task_id) ON DELETE CASCADE,
+    revision_number integer NOT NULL,
+    actor_id bigint NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    changed_fields text[] NOT NULL,
+    -- Values of the tracked fields after the change.
+    snapshot jsonb NOT NULL,
+    PRIMARY KEY (task_id, revision_number)
+);
+
+CREATE TABLE IF NOT EXISTS project_counters (
+    project_id bigint PRIMARY KEY,
+    last_number bigint NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS task_transitions (
+    transition_id bigserial PRIMARY KEY,
+    task_id bigint NOT NULL REFERENCES tasks (task_id) ON DELETE CASCADE,
+    actor_id bigint NOT NULL,
+    from_status varchar(32) NOT NULL,
+    to_status varchar(32) NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS comments (
+    comment_id bigserial PRIMARY KEY,
+    task_id bigint NOT NULL REFERENCES tasks (task_id) ON DELETE CASCADE,
+    parent_id bigint REFERENCES comments (comment_id) ON DELETE CASCADE,
+    author_id bigint NOT NULL,
+    content varchar NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    edited_at timestamptz,
+    deleted boolean NOT NULL DEFAULT false,
+    deleted_by bigint,
+    search_vector tsvector GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED
+);
+
+ALTER TABLE comments
+    ADD COLUMN IF NOT EXISTS search_vector tsvector GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED;
+
+CREATE INDEX IF NOT EXISTS comments_search_idx ON comments USING GIN (search_vector);
+
+CREATE INDEX IF NOT EXISTS comments_thread_idx ON comments (task_id, parent_id, comment_id);
+
+CREATE TABLE IF NOT EXISTS labels (
+    label_id bigserial PRIMARY KEY,
+    owner_kind smallint NOT NULL,
+    owner_id bigint NOT NULL,
+    name varchar(50) NOT NULL,
+    color char(7) NOT NULL,
+    description varchar(200) NOT NULL DEFAULT '',
+    UNIQUE (owner_kind, owner_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS task_labels (
+    task_id bigint NOT NULL REFERENCES tasks (task_id) ON DELETE CASCADE,
+    label_id bigint NOT NULL REFERENCES labels (label_id) ON DELETE CASCADE,
+    PRIMARY KEY (task_id, label_id)
+);
+
+CREATE TABLE IF NOT EXISTS attachments (
+    attachment_id bigserial PRIMARY KEY,
+    task_id bigint NOT NULL REFERENCES tasks (task_id) ON DELETE CASCADE,
+    uploader_id bigint NOT NULL,
+    filename varchar(255) NOT NULL,
+    content_type varchar(100) NOT NULL,
+    size_bytes bigint NOT NULL,
+    -- Key of the contents in the blob storage, shared by identical files.
+    sha256 char(64) NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
 );
+
+CREATE INDEX IF NOT EXISTS attachments_task_idx ON attachments (task_id);
+
+CREATE INDEX IF NOT EXISTS attachments_sha256_idx ON attachments (sha256);